@@ -68,9 +68,21 @@ if [ "$deploy_executor" = "y" ] || [ "$deploy_executor" = "Y" ]; then
   clean_old_images "executor" "$project_id"
 fi
 
+# The API refuses requests without credentials, the admin token is only shown once.
+if ! kubectl get secret dyno-code-auth > /dev/null 2>&1; then
+  admin_token=$(openssl rand -hex 32)
+  kubectl create secret generic dyno-code-auth \
+    --from-literal=admin-token="$admin_token" \
+    --from-literal=api-keys.json='[]'
+  echo "Jeton administrateur (ADMIN_TOKEN) : $admin_token"
+fi
+
+kubectl apply -f history-pvc.yaml
+kubectl apply -f files-pvc.yaml
 kubectl apply -f deployment.yaml
 kubectl apply -f service.yaml
 kubectl apply -f role_binding.yaml
+kubectl apply -f executor-images.yaml
 kubectl apply -f ingress.yaml
 kubectl apply -f nfs-server-service.yaml
 kubectl apply -f nfs-server.yaml
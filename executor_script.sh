@@ -4,37 +4,52 @@ LANGUAGE=$1
 CODE=$2
 INPUT_FILE=$3
 OUTPUT_FILE=$4
+# Directory the program may write any number of files to, collected after the execution.
+OUTPUT_DIR=$5
+# Limit of each case of a batch when the server sets no $TIME_LIMIT_MS, see MAX_TIME_LIMIT_MS.
+DEFAULT_TIME_LIMIT_MS=30000
+
+# Appends "<name>=<milliseconds since $2>" to $TIMINGS_FILE, when the server asked for timings.
+record_timing() {
+  if [[ -n "$TIMINGS_FILE" ]]; then
+    echo "$1=$(( ($(date +%s%N) - $2) / 1000000 ))" >> "$TIMINGS_FILE"
+  fi
+}
 
 execute_code() {
   cmd=$1
   code=$2
   input_file=$3
   output_file=$4
+  output_dir=$5
 
   mkdir -p /home/executor/sandbox
   cp $(which $cmd) /home/executor/sandbox/ 
   chmod +x /home/executor/sandbox/$(basename $cmd)
   echo "$input_file" > /home/executor/sandbox/input
+  if [[ -n "$output_dir" ]]; then
+    mkdir -p "$output_dir"
+  fi
 
   if [[ $cmd == "python3" ]]; then
     if [[ -z "$input_file" ]]; then
-      echo "$code" > /home/executor/sandbox/code
+      { printf "OUTPUT_DIR = '%s'\n" "$output_dir"; echo "$code"; } > /home/executor/sandbox/code
     else
-      echo -e "INPUT_PATH='/home/executor/sandbox/$(basename $input_file)'\nOUTPUT_PATH ='$output_file'\n$code" > /home/executor/sandbox/code
+      echo -e "INPUT_PATH='/home/executor/sandbox/$(basename $input_file)'\nOUTPUT_PATH ='$output_file'\nOUTPUT_DIR = '$output_dir'\n$code" > /home/executor/sandbox/code
       cp "$input_file" /home/executor/sandbox/
     fi
   elif [[ $cmd == "lua" ]]; then
     if [[ -z "$input_file" ]]; then
-      echo "$code" > /home/executor/sandbox/code
+      { printf "local OUTPUT_DIR = '%s'\n" "$output_dir"; echo "$code"; } > /home/executor/sandbox/code
     else
-      echo -e "local INPUT_PATH = '/home/executor/sandbox/$(basename $input_file)'\nlocal OUTPUT_PATH = '$output_file'\n$code" > /home/executor/sandbox/code
+      echo -e "local INPUT_PATH = '/home/executor/sandbox/$(basename $input_file)'\nlocal OUTPUT_PATH = '$output_file'\nlocal OUTPUT_DIR = '$output_dir'\n$code" > /home/executor/sandbox/code
       cp "$input_file" /home/executor/sandbox/
     fi
   elif [[ $cmd == "node" ]]; then
     if [[ -z "$input_file" ]]; then
-      echo "$code" > /home/executor/sandbox/code
+      { printf "const OUTPUT_DIR = '%s';\n" "$output_dir"; echo "$code"; } > /home/executor/sandbox/code
     else
-      echo -e "const fs = require('fs');\nconst INPUT_PATH = '/home/executor/sandbox/$(basename $input_file)';\nconst OUTPUT_PATH = '$output_file';\n$code" > /home/executor/sandbox/code
+      echo -e "const fs = require('fs');\nconst INPUT_PATH = '/home/executor/sandbox/$(basename $input_file)';\nconst OUTPUT_PATH = '$output_file';\nconst OUTPUT_DIR = '$output_dir';\n$code" > /home/executor/sandbox/code
       cp "$input_file" /home/executor/sandbox/
     fi
   else
@@ -44,14 +59,17 @@ execute_code() {
   output=$(mktemp /home/executor/sandbox/tmp.XXXXXXXXXX)
   error=$(mktemp /home/executor/sandbox/tmp.XXXXXXXXXX)
   
+  start=$(date +%s%N)
   /home/executor/sandbox/$(basename $cmd) /home/executor/sandbox/code > "$output" 2> "$error"
 
   EXIT_CODE=$?
+  record_timing run_ms $start
   if [ $EXIT_CODE -ne 0 ]; then
     cat "$error"
     echo "EXECUTOR_ERROR"
     rm "$output" "$error"
-    exit 1
+    # The exit code of the container is the one the server reports for the program.
+    exit $EXIT_CODE
   else
     cat "$output"
     rm "$output" "$error"
@@ -62,15 +80,18 @@ compile_and_execute_rust() {
   code=$1
   input_file=$2
   output_file=$3
-  
+  output_dir=$4
+
+  if [[ -n "$output_dir" ]]; then
+    mkdir -p "$output_dir"
+  fi
+
   if [[ -z "$input_file" ]]; then
-    echo "$code" > /home/executor/sandbox/temp.rs
+    { printf "#[allow(dead_code)]\nconst OUTPUT_DIR: &str = \"%s\";\n" "$output_dir"; echo "$code"; } > /home/executor/sandbox/temp.rs
   else
     file_path="/home/executor/sandbox/temp.rs"
-    echo "$code" | awk 'BEGIN{print "const INPUT_PATH: &str = \"'/mnt/shared/$(basename $input_file)'\";\nconst OUTPUT_PATH: &str = \"'/mnt/shared/output/$(basename $output_file)'\";"} 1' > $file_path
+    echo "$code" | awk 'BEGIN{print "const INPUT_PATH: &str = \"'$input_file'\";\nconst OUTPUT_PATH: &str = \"'$output_file'\";\n#[allow(dead_code)]\nconst OUTPUT_DIR: &str = \"'$output_dir'\";"} 1' > $file_path
 
-    # DEBUG
-    #cat /home/executor/sandbox/temp.rs > /mnt/shared/output/debug_temp.rs
   fi
 
   if [ ! -s /home/executor/sandbox/temp.rs ]; then
@@ -79,14 +100,15 @@ compile_and_execute_rust() {
     exit 1
   fi
 
-  # DEBUG
-  #echo "$code_with_paths" > /mnt/shared/output/$(basename $output_file).rs
   export TMPDIR=/home/executor/sandbox
 
+  start=$(date +%s%N)
   COMPILE_RESULT=$(rustc /home/executor/sandbox/temp.rs -o /home/executor/sandbox/temp 2>&1)
   COMPILE_EXIT_CODE=$?
+  record_timing compile_ms $start
   if [ $COMPILE_EXIT_CODE -ne 0 ]; then
     echo "$COMPILE_RESULT"
+    echo "COMPILE_ERROR"
     echo "EXECUTOR_ERROR"
     exit 1
   fi
@@ -94,11 +116,14 @@ compile_and_execute_rust() {
   output=$(mktemp /home/executor/sandbox/tmp.XXXXXXXXXX)
   error=$(mktemp /home/executor/sandbox/tmp.XXXXXXXXXX)
 
+  start=$(date +%s%N)
   if [[ -z "$input_file" ]]; then
     /home/executor/sandbox/temp > "$output" 2> "$error"
   else
-    /home/executor/sandbox/temp /mnt/shared/input/$(basename $input_file) > "$output" 2> "$error"
+    /home/executor/sandbox/temp "$input_file" > "$output" 2> "$error"
   fi
+  RUN_EXIT_CODE=$?
+  record_timing run_ms $start
 
   if [ ! -s "$output" ]; then
     EXEC_RESULT=$(cat "$error")
@@ -112,25 +137,254 @@ compile_and_execute_rust() {
     cat "$error"
     echo "EXECUTOR_ERROR"
     rm "$output" "$error"
-    exit 1
+    exit $RUN_EXIT_CODE
   else
     cat "$output"
     rm "$output" "$error"
+    # A program that printed its output before failing still succeeds, with its exit code.
+    exit $RUN_EXIT_CODE
   fi
 }
 
+# Writes the program of the given language to $dir, compiling it if needed, and sets
+# $prepared_cmd to the command running it. INPUT_PATH and OUTPUT_PATH are defined as in the
+# single execution mode.
+prepare_program() {
+  local language=$1
+  local code=$2
+  local dir=$3
+  local input_path=$4
+  local output_path=$5
+
+  mkdir -p "$dir"
+  case $language in
+    "python")
+      printf "INPUT_PATH = '%s'\nOUTPUT_PATH = '%s'\n%s\n" "$input_path" "$output_path" "$code" > $dir/code
+      prepared_cmd="python3 $dir/code"
+      ;;
+    "lua")
+      printf "local INPUT_PATH = '%s'\nlocal OUTPUT_PATH = '%s'\n%s\n" "$input_path" "$output_path" "$code" > $dir/code
+      prepared_cmd="lua $dir/code"
+      ;;
+    "javascript")
+      printf "const fs = require('fs');\nconst INPUT_PATH = '%s';\nconst OUTPUT_PATH = '%s';\n%s\n" "$input_path" "$output_path" "$code" > $dir/code
+      prepared_cmd="node $dir/code"
+      ;;
+    "rust")
+      printf "#[allow(dead_code)]\nconst INPUT_PATH: &str = \"%s\";\n#[allow(dead_code)]\nconst OUTPUT_PATH: &str = \"%s\";\n%s\n" "$input_path" "$output_path" "$code" > $dir/temp.rs
+
+      COMPILE_RESULT=$(rustc $dir/temp.rs -o $dir/temp 2>&1)
+      if [ $? -ne 0 ]; then
+        echo "$COMPILE_RESULT"
+        return 1
+      fi
+      prepared_cmd="$dir/temp"
+      ;;
+    *)
+      echo "Unsupported language"
+      return 1
+      ;;
+  esac
+}
+
+# Sets $limit_cmd to the command killing a program once it runs for longer than $TIME_LIMIT_MS,
+# or $DEFAULT_TIME_LIMIT_MS if unset.
+set_limit_cmd() {
+  local limit_ms=${TIME_LIMIT_MS:-$DEFAULT_TIME_LIMIT_MS}
+  limit_cmd="timeout -k 1 $((limit_ms / 1000)).$(printf '%03d' $((limit_ms % 1000)))"
+}
+
+# Batch mode: the program is prepared once, then run for every case listed in $BATCH_MANIFEST
+# (one "<case id>|<input file>|<space separated arguments>" line per case). The supervisor runs in
+# another container, as another user, see run_supervisor: each case reads its stdin from and
+# writes its stdout to the pipes of $PIPES_DIR/<case id>/, and the supervisor records its results,
+# so that the program can forge none of them. Only the exit code of the case comes from here,
+# through the `status` pipe, its stderr and output file being written to
+# $BATCH_OUTPUT_DIR/<case id>/. Every case is killed once it runs for longer than its limit, see
+# set_limit_cmd. $PIPES_DIR/program_ready tells the supervisor that the program is prepared.
+run_batch() {
+  language=$1
+  code=$2
+  sandbox=${SANDBOX_DIR:-/home/executor/sandbox}
+  input_path="$sandbox/input"
+  output_path="$sandbox/output$OUTPUT_EXTENSION"
+
+  mkdir -p $sandbox
+  export TMPDIR=$sandbox
+  set_limit_cmd
+
+  if ! prepare_program "$language" "$code" "$sandbox" "$input_path" "$output_path"; then
+    touch "$PIPES_DIR/program_failed"
+    echo "COMPILE_ERROR"
+    echo "EXECUTOR_ERROR"
+    exit 1
+  fi
+  run_cmd=$prepared_cmd
+  touch "$PIPES_DIR/program_ready"
+
+  while IFS='|' read -r case_id input_file case_args; do
+    case_dir="$BATCH_OUTPUT_DIR/$case_id"
+    mkdir -p "$case_dir"
+    rm -f "$input_path" "$output_path"
+
+    if [[ -n "$input_file" ]]; then
+      cp "$input_file" "$input_path"
+    fi
+
+    channel="$PIPES_DIR/$case_id"
+    if ! wait_for_channel "$channel"; then
+      if [[ -e "$PIPES_DIR/interactor_failed" ]]; then
+        echo "Interactor compilation failed"
+        echo "COMPILE_ERROR"
+        echo "EXECUTOR_ERROR"
+        exit 1
+      fi
+      continue
+    fi
+
+    # The pipes are opened under the limit, so that a supervisor that never opens its end does
+    # not block the case past it. Both ends open the pipe to the program first, otherwise they
+    # would wait for each other.
+    $limit_cmd bash -c 'in=$1 out=$2; shift 2; exec "$@" < "$in" > "$out"' _ \
+      "$channel/to_program" "$channel/to_interactor" $run_cmd $case_args 2> "$case_dir/stderr"
+    # The supervisor stops waiting for it once the case outlived its limit.
+    timeout 1 bash -c 'echo "$1" > "$2"' _ $? "$channel/status"
+
+    if [ -f "$output_path" ]; then
+      mv "$output_path" "$case_dir/output$OUTPUT_EXTENSION"
+    fi
+  done < "$BATCH_MANIFEST"
+}
+
+# Waits for the supervisor to create the pipes of a case, failing once it gave up or after a
+# minute.
+wait_for_channel() {
+  local channel=$1
+  for _ in $(seq 600); do
+    if [[ -p "$channel/to_interactor" ]]; then
+      return 0
+    fi
+    if [[ -e "$PIPES_DIR/interactor_failed" || -e "$PIPES_DIR/supervisor_done" ]]; then
+      return 1
+    fi
+    sleep 0.1
+  done
+  return 1
+}
+
+# Waits for the program to be prepared, which may take longer than the time limit of a case,
+# failing once it could not be.
+wait_for_program() {
+  until [[ -e "$PIPES_DIR/program_ready" ]]; do
+    if [[ -e "$PIPES_DIR/program_failed" ]]; then
+      return 1
+    fi
+    sleep 0.1
+  done
+}
+
+# Runs a case on the side of the supervisor, see run_supervisor: hands the input file to the
+# program, or runs the interactor with it, then waits for the program to report its exit code,
+# which only happens once it exited, not when it merely closed its stdout. The duration of the
+# case runs from the program opening its pipes to then.
+supervise_case() {
+  local channel=$1
+  local input_file=$2
+  local case_dir=$3
+
+  exec 3> "$channel/to_program" 4< "$channel/to_interactor"
+  local start=$(date +%s%N)
+  if [[ -n "$interactor_cmd" ]]; then
+    $interactor_cmd "$input_file" "$case_dir/interactor_result" >&3 <&4 3>&- 4<&- 2> "$case_dir/interactor_stderr"
+    echo $? > "$case_dir/interactor_exit_code"
+    exec 3>&- 4<&-
+  else
+    cat "$input_file" >&3 4<&- &
+    exec 3>&-
+    cat <&4 > "$case_dir/stdout"
+    exec 4<&-
+  fi
+  head -c 16 "$channel/status" > "$case_dir/exit_code"
+  echo $(( ($(date +%s%N) - start) / 1000000 )) > "$case_dir/time_ms"
+}
+export -f supervise_case
+
+# Supervisor mode: runs in its own container, as another user than the program, every case of
+# $SUPERVISOR_MANIFEST (one "<case id>|<input file>" line per case). For each case it creates the
+# pipes of $PIPES_DIR/<case id>/, that only it may remove, and runs supervise_case, which writes
+# the stdout, exit code and duration of the program to $SUPERVISOR_OUTPUT_DIR/<case id>/, which the
+# program cannot access. With $INTERACTOR_CODE_PATH, the interactor of $INTERACTOR_LANGUAGE runs
+# with the input file and a result file as arguments, its stdout piped to the stdin of the program
+# and the other way around, and its exit code, stderr and result file are written there as well.
+run_supervisor() {
+  sandbox=${SANDBOX_DIR:-/home/executor/sandbox}
+  limit_ms=${TIME_LIMIT_MS:-$DEFAULT_TIME_LIMIT_MS}
+  # Leaves the program the time to notice the pipes and to be killed past its own limit.
+  supervise_cmd="timeout -k 1 $((limit_ms / 1000 + 3)).$(printf '%03d' $((limit_ms % 1000)))"
+
+  mkdir -p $sandbox
+  export TMPDIR=$sandbox
+
+  if [[ -n "$INTERACTOR_CODE_PATH" ]]; then
+    if ! prepare_program "$INTERACTOR_LANGUAGE" "$(cat "$INTERACTOR_CODE_PATH")" "$sandbox/interactor" "" ""; then
+      touch "$PIPES_DIR/interactor_failed"
+      echo "Interactor compilation failed"
+      exit 1
+    fi
+    export interactor_cmd=$prepared_cmd
+  fi
+  # The limit of the first case would otherwise run while the program is still compiling.
+  wait_for_program
+
+  while IFS='|' read -r case_id input_file; do
+    if [[ -e "$PIPES_DIR/program_failed" ]]; then
+      break
+    fi
+    case_dir="$SUPERVISOR_OUTPUT_DIR/$case_id"
+    mkdir -p "$case_dir"
+    if [[ -n "$interactor_cmd" ]]; then
+      touch "$case_dir/interactor_result"
+    fi
+
+    channel="$PIPES_DIR/$case_id"
+    mkdir -m 755 "$channel"
+    mkfifo -m 644 "$channel/to_program"
+    mkfifo -m 622 "$channel/status"
+    mkfifo -m 622 "$channel/to_interactor"
+    start=$(date +%s%N)
+    $supervise_cmd bash -c 'supervise_case "$@"' _ "$channel" "$input_file" "$case_dir"
+    if [[ ! -s "$case_dir/time_ms" ]]; then
+      # The program never reported its exit code.
+      echo 124 > "$case_dir/exit_code"
+      echo $(( ($(date +%s%N) - start) / 1000000 )) > "$case_dir/time_ms"
+    fi
+    rm -r "$channel"
+  done < "$SUPERVISOR_MANIFEST"
+  touch "$PIPES_DIR/supervisor_done"
+}
+
+if [[ -n "$SUPERVISOR_MANIFEST" ]]; then
+  run_supervisor
+  exit 0
+fi
+
+if [[ -n "$BATCH_MANIFEST" ]]; then
+  run_batch "$LANGUAGE" "$CODE"
+  exit 0
+fi
+
 case $LANGUAGE in
   "python")
-    execute_code "python3" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE"
+    execute_code "python3" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE" "$OUTPUT_DIR"
     ;;
   "lua")
-    execute_code "lua" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE"
+    execute_code "lua" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE" "$OUTPUT_DIR"
     ;;
   "javascript")
-    execute_code "node" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE"
+    execute_code "node" "$CODE" "$INPUT_FILE" "$OUTPUT_FILE" "$OUTPUT_DIR"
     ;;
   "rust")
-    compile_and_execute_rust "$CODE" "$INPUT_FILE" "$OUTPUT_FILE"
+    compile_and_execute_rust "$CODE" "$INPUT_FILE" "$OUTPUT_FILE" "$OUTPUT_DIR"
     ;;
   *)
     echo "Unsupported language EXECUTOR_ERROR"
@@ -3,18 +3,89 @@ use actix_web::{
     Responder,
 };
 
-use std::env;
+use std::{
+    env,
+    path::Path,
+};
+
+use crate::{
+    executor::{
+        readiness,
+        Language,
+        LANGUAGES,
+    },
+    metrics,
+    types::ReadinessReport,
+    web::SHARED_DIR,
+};
 
 const DEFAULT_PORT: u16 = 8080;
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "service",
+    security(()),
+    responses((status = 200, description = "Service is up", body = String))
+)]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "service",
+    security(()),
+    responses(
+        (status = 200, description = "Every dependency is available", body = ReadinessReport),
+        (status = 503, description = "A dependency is unavailable", body = ReadinessReport),
+    )
+)]
+pub async fn ready_check() -> impl Responder {
+    let report = readiness::check(Path::new(SHARED_DIR)).await;
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "service",
+    security(()),
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String))
+)]
+pub async fn export_metrics() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
+}
+
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "service",
+    security(()),
+    responses((status = 200, description = "Version of the service", body = String))
+)]
 pub async fn check_version() -> impl Responder {
     HttpResponse::Ok().body(env!("CARGO_PKG_VERSION"))
 }
 
+#[utoipa::path(
+    get,
+    path = "/languages",
+    tag = "service",
+    security(()),
+    responses((status = 200, description = "Supported languages", body = [Language]))
+)]
+pub async fn list_languages() -> impl Responder {
+    HttpResponse::Ok().json(LANGUAGES)
+}
+
 pub fn get_server_port() -> u16 {
     env::var("APP_PORT")
         .unwrap_or_else(|_| DEFAULT_PORT.to_string())
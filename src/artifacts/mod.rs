@@ -0,0 +1,248 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    env,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt,
+};
+
+use crate::types::OutputFile;
+
+/// Kept out of the shared volume by default, which the submissions can read.
+const DEFAULT_ARTIFACTS_DIR: &str = "artifacts";
+const DEFAULT_ARTIFACT_TTL_SECS: u64 = 3600;
+const CONTENT_FILE: &str = "content";
+const METADATA_FILE: &str = "metadata.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactMetadata {
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub sha256: String,
+    /// Unix timestamp, in seconds, after which the artifact is deleted.
+    pub expires_at: u64,
+    /// Principal the execution that wrote the file ran for, the only one that may download it.
+    pub owner: String,
+}
+
+/// Files produced by executions, kept for `ARTIFACT_TTL_SECS` in `ARTIFACTS_DIR` to be
+/// downloaded from `GET /artifacts/{id}`. Each artifact is a directory holding its content and
+/// its metadata.
+pub struct ArtifactStore {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ArtifactStore {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        ArtifactStore {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl_secs = env::var("ARTIFACT_TTL_SECS")
+            .ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .unwrap_or(DEFAULT_ARTIFACT_TTL_SECS);
+        Self::new(
+            env::var("ARTIFACTS_DIR").unwrap_or_else(|_| DEFAULT_ARTIFACTS_DIR.to_string()),
+            Duration::from_secs(ttl_secs),
+        )
+    }
+
+    fn artifact_dir(&self, id: &str) -> Option<PathBuf> {
+        uuid::Uuid::parse_str(id)
+            .ok()
+            .map(|id| self.dir.join(id.to_string()))
+    }
+
+    /// Copies the file at `source` into the store under the given name, hashing it on the way.
+    pub async fn store(&self, owner: &str, source: &Path, name: &str) -> io::Result<OutputFile> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let artifact_dir = self.dir.join(&id);
+        tokio::fs::create_dir_all(&artifact_dir).await?;
+
+        let mut reader = tokio::fs::File::open(source).await?;
+        let mut writer = tokio::fs::File::create(artifact_dir.join(CONTENT_FILE)).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            writer.write_all(&buffer[..read]).await?;
+            size += read as u64;
+        }
+        writer.flush().await?;
+
+        let metadata = ArtifactMetadata {
+            name: name.to_string(),
+            mime_type: mime_guess::from_path(name)
+                .first_or_octet_stream()
+                .to_string(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            expires_at: unix_time(SystemTime::now() + self.ttl),
+            owner: owner.to_string(),
+        };
+        tokio::fs::write(
+            artifact_dir.join(METADATA_FILE),
+            serde_json::to_vec(&metadata)?,
+        )
+        .await?;
+
+        Ok(OutputFile {
+            name: metadata.name,
+            size: metadata.size,
+            mime_type: metadata.mime_type,
+            sha256: metadata.sha256,
+            url: format!("/artifacts/{}", id),
+            artifact_id: id,
+            expires_at: metadata.expires_at,
+        })
+    }
+
+    /// Returns the metadata and the path of the content of an artifact of the owner that has not
+    /// expired.
+    pub async fn open(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> io::Result<Option<(ArtifactMetadata, PathBuf)>> {
+        let Some(artifact_dir) = self.artifact_dir(id) else {
+            return Ok(None);
+        };
+
+        let metadata = match tokio::fs::read(artifact_dir.join(METADATA_FILE)).await {
+            Ok(metadata) => serde_json::from_slice::<ArtifactMetadata>(&metadata)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if metadata.expires_at <= unix_time(SystemTime::now()) {
+            tokio::fs::remove_dir_all(&artifact_dir).await?;
+            return Ok(None);
+        }
+        if metadata.owner != owner {
+            return Ok(None);
+        }
+
+        Ok(Some((metadata, artifact_dir.join(CONTENT_FILE))))
+    }
+
+    /// Deletes the expired artifacts, returning how many there were.
+    pub async fn purge_expired(&self) -> io::Result<usize> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let now = unix_time(SystemTime::now());
+        let mut purged = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let expires_at = tokio::fs::read(entry.path().join(METADATA_FILE))
+                .await
+                .ok()
+                .and_then(|metadata| serde_json::from_slice::<ArtifactMetadata>(&metadata).ok())
+                .map(|metadata| metadata.expires_at);
+            // Artifacts without readable metadata are leftovers of an interrupted copy.
+            if !matches!(expires_at, Some(expires_at) if expires_at > now) {
+                tokio::fs::remove_dir_all(entry.path()).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+}
+
+pub(crate) fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_and_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::write(&source, "hello").unwrap();
+
+        let store = ArtifactStore::new(dir.path().join("store"), Duration::from_secs(60));
+        let first = store.store("alice", &source, "greeting.txt").await.unwrap();
+        let output_file = first.clone();
+        assert_eq!(output_file.size, 5);
+        assert_eq!(output_file.mime_type, "text/plain");
+        assert_eq!(
+            output_file.sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            output_file.url,
+            format!("/artifacts/{}", output_file.artifact_id)
+        );
+
+        let (metadata, path) = store
+            .open("alice", &output_file.artifact_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.name, "greeting.txt");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+        assert!(store
+            .open("bob", &output_file.artifact_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store.open("alice", "../source").await.unwrap().is_none());
+        assert_eq!(store.purge_expired().await.unwrap(), 0);
+
+        let expired = ArtifactStore::new(dir.path().join("store"), Duration::ZERO);
+        let output_file = expired
+            .store("alice", &source, "greeting.txt")
+            .await
+            .unwrap();
+        assert!(expired
+            .open("alice", &output_file.artifact_id)
+            .await
+            .unwrap()
+            .is_none());
+        expired
+            .store("alice", &source, "greeting.txt")
+            .await
+            .unwrap();
+        assert_eq!(expired.purge_expired().await.unwrap(), 1);
+        assert!(store
+            .open("alice", &first.artifact_id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,404 @@
+use actix_web::http::header::{
+    self,
+    HeaderMap,
+};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters,
+        JwkSet,
+    },
+    Algorithm,
+    DecodingKey,
+    Validation,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    collections::HashMap,
+    env,
+    io,
+};
+use utoipa::ToSchema;
+
+use crate::error::{
+    Error,
+    Result,
+};
+
+pub const ADMIN_ROLE: &str = "admin";
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+    /// No credentials were sent, on a public route or with `AUTH_REQUIRED=false`.
+    Anonymous,
+}
+
+/// Who a request is made by, attached to every request by the authentication middleware.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Principal {
+    /// Name of the API key prefixed with `key:` or subject of the token prefixed with `jwt:`,
+    /// so that a token cannot take the identity of a key of the same name, or `anonymous`.
+    pub id: String,
+    pub method: AuthMethod,
+    pub tenant: Option<String>,
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    pub fn anonymous() -> Self {
+        Principal {
+            id: "anonymous".to_string(),
+            method: AuthMethod::Anonymous,
+            tenant: None,
+            roles: Vec::new(),
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.has_role(ADMIN_ROLE)
+    }
+}
+
+/// Entry of `API_KEYS_FILE`. Only the SHA-256 of the key is stored, so that the file does not
+/// hold usable secrets.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    pub name: String,
+    /// Hex encoded SHA-256 of the key.
+    pub key_sha256: String,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Claims read from bearer tokens besides the ones checked by [`Validation`].
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    tenant: Option<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+enum JwtKeys {
+    /// Shared secret of HS256 tokens.
+    Secret(DecodingKey),
+    /// Public keys of RS256 tokens, by key id.
+    Public(Vec<(Option<String>, DecodingKey)>),
+}
+
+/// Checks the signature, expiry and, when configured, issuer and audience of bearer tokens.
+pub struct JwtVerifier {
+    keys: JwtKeys,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    pub fn hs256(secret: &[u8], issuer: Option<&str>, audience: Option<&str>) -> Self {
+        JwtVerifier {
+            keys: JwtKeys::Secret(DecodingKey::from_secret(secret)),
+            validation: validation(Algorithm::HS256, issuer, audience),
+        }
+    }
+
+    /// Uses the RSA keys of a JWKS, tokens naming a key with `kid` being checked against that
+    /// key only.
+    pub fn rs256(jwks: &JwkSet, issuer: Option<&str>, audience: Option<&str>) -> io::Result<Self> {
+        let mut keys = Vec::new();
+        for jwk in &jwks.keys {
+            if !matches!(jwk.algorithm, AlgorithmParameters::RSA(_)) {
+                continue;
+            }
+            let key = DecodingKey::from_jwk(jwk)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            keys.push((jwk.common.key_id.clone(), key));
+        }
+        if keys.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The JWKS holds no RSA key",
+            ));
+        }
+        Ok(JwtVerifier {
+            keys: JwtKeys::Public(keys),
+            validation: validation(Algorithm::RS256, issuer, audience),
+        })
+    }
+
+    fn verify(&self, token: &str) -> Result<Principal> {
+        let invalid = |e: jsonwebtoken::errors::Error| {
+            Error::Unauthorized(format!("Invalid bearer token: {}", e))
+        };
+        let keys = match &self.keys {
+            JwtKeys::Secret(key) => vec![key],
+            JwtKeys::Public(keys) => {
+                let kid = jsonwebtoken::decode_header(token).map_err(invalid)?.kid;
+                keys.iter()
+                    .filter(|(key_id, _)| kid.is_none() || *key_id == kid)
+                    .map(|(_, key)| key)
+                    .collect()
+            }
+        };
+
+        let mut last_error = None;
+        for key in keys {
+            match jsonwebtoken::decode::<Claims>(token, key, &self.validation) {
+                Ok(data) => {
+                    return Ok(Principal {
+                        id: format!("jwt:{}", data.claims.sub),
+                        method: AuthMethod::Jwt,
+                        tenant: data.claims.tenant,
+                        roles: data.claims.roles,
+                    })
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(match last_error {
+            Some(e) => invalid(e),
+            None => Error::Unauthorized("Invalid bearer token: unknown key id".to_string()),
+        })
+    }
+}
+
+fn validation(algorithm: Algorithm, issuer: Option<&str>, audience: Option<&str>) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.set_required_spec_claims(&["exp", "sub"]);
+    if let Some(issuer) = issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    match audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+    validation
+}
+
+/// Credentials accepted by the API: static API keys, sent in `X-API-Key` or as a bearer token,
+/// and JWT bearer tokens.
+pub struct AuthConfig {
+    /// By SHA-256 of the key.
+    api_keys: HashMap<String, ApiKey>,
+    jwt: Option<JwtVerifier>,
+    /// Whether requests without credentials are rejected rather than served anonymously.
+    pub required: bool,
+}
+
+impl AuthConfig {
+    pub fn new(api_keys: Vec<ApiKey>, jwt: Option<JwtVerifier>, required: bool) -> Self {
+        AuthConfig {
+            api_keys: api_keys
+                .into_iter()
+                .map(|key| (key.key_sha256.to_ascii_lowercase(), key))
+                .collect(),
+            jwt,
+            required,
+        }
+    }
+
+    /// Reads the API keys from the JSON list of `API_KEYS_FILE`, `ADMIN_TOKEN` being an API key
+    /// with the admin role, and the tokens from `JWT_HS256_SECRET` or `JWT_JWKS_FILE`.
+    pub fn from_env() -> io::Result<Self> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.trim().is_empty());
+
+        let mut api_keys: Vec<ApiKey> = match var("API_KEYS_FILE") {
+            Some(path) => serde_json::from_slice(&std::fs::read(path.trim())?)?,
+            None => Vec::new(),
+        };
+        if let Some(admin_token) = var("ADMIN_TOKEN") {
+            api_keys.push(ApiKey {
+                name: "admin".to_string(),
+                key_sha256: sha256(&admin_token),
+                tenant: None,
+                roles: vec![ADMIN_ROLE.to_string()],
+            });
+        }
+
+        let issuer = var("JWT_ISSUER");
+        let audience = var("JWT_AUDIENCE");
+        let jwt = match (var("JWT_HS256_SECRET"), var("JWT_JWKS_FILE")) {
+            (Some(_), Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "JWT_HS256_SECRET and JWT_JWKS_FILE cannot both be set",
+                ))
+            }
+            (Some(secret), None) => Some(JwtVerifier::hs256(
+                secret.as_bytes(),
+                issuer.as_deref(),
+                audience.as_deref(),
+            )),
+            (None, Some(path)) => {
+                let jwks = serde_json::from_slice(&std::fs::read(path.trim())?)?;
+                Some(JwtVerifier::rs256(
+                    &jwks,
+                    issuer.as_deref(),
+                    audience.as_deref(),
+                )?)
+            }
+            (None, None) => None,
+        };
+
+        let required = env::var("AUTH_REQUIRED")
+            .map(|value| value.trim() != "false")
+            .unwrap_or(true);
+        Ok(Self::new(api_keys, jwt, required))
+    }
+
+    /// Whether any API key or token can be accepted.
+    pub fn has_credentials(&self) -> bool {
+        !self.api_keys.is_empty() || self.jwt.is_some()
+    }
+
+    /// Returns who sent the credentials of the request, if it sent any.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Principal>> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| value.to_str().map(str::trim).unwrap_or_default())
+        };
+
+        if let Some(key) = header(API_KEY_HEADER) {
+            return self
+                .api_key(key)
+                .map(Some)
+                .ok_or_else(|| Error::Unauthorized("Invalid API key".to_string()));
+        }
+
+        let Some(authorization) = header(header::AUTHORIZATION.as_str()) else {
+            return Ok(None);
+        };
+        let Some(token) = authorization
+            .strip_prefix("Bearer ")
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+        else {
+            return Err(Error::Unauthorized(
+                "Authorization must be a bearer token".to_string(),
+            ));
+        };
+        if let Some(principal) = self.api_key(token) {
+            return Ok(Some(principal));
+        }
+        match &self.jwt {
+            Some(jwt) => jwt.verify(token).map(Some),
+            None => Err(Error::Unauthorized("Invalid API key".to_string())),
+        }
+    }
+
+    fn api_key(&self, key: &str) -> Option<Principal> {
+        self.api_keys.get(&sha256(key)).map(|key| Principal {
+            id: format!("key:{}", key.name),
+            method: AuthMethod::ApiKey,
+            tenant: key.tenant.clone(),
+            roles: key.roles.clone(),
+        })
+    }
+}
+
+fn sha256(value: &str) -> String {
+    format!("{:x}", Sha256::digest(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use jsonwebtoken::{
+        EncodingKey,
+        Header,
+    };
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::HeaderName::from_static(name),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_api_keys_and_tokens() {
+        let config = AuthConfig::new(
+            vec![ApiKey {
+                name: "ci".to_string(),
+                key_sha256: sha256("secret-key"),
+                tenant: Some("acme".to_string()),
+                roles: vec![ADMIN_ROLE.to_string()],
+            }],
+            Some(JwtVerifier::hs256(b"jwt-secret", Some("auth"), None)),
+            true,
+        );
+
+        assert_eq!(config.authenticate(&HeaderMap::new()).unwrap(), None);
+        let principal = config
+            .authenticate(&headers("x-api-key", "secret-key"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "key:ci");
+        assert_eq!(principal.tenant.as_deref(), Some("acme"));
+        assert!(principal.is_admin());
+        let principal = config
+            .authenticate(&headers("authorization", "Bearer secret-key"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.method, AuthMethod::ApiKey);
+        assert!(matches!(
+            config.authenticate(&headers("x-api-key", "wrong")),
+            Err(Error::Unauthorized(_))
+        ));
+
+        let token = |secret: &[u8], exp: u64| {
+            let claims = serde_json::json!({
+                "sub": "ci",
+                "iss": "auth",
+                "exp": exp,
+                "roles": ["student"],
+            });
+            let token = jsonwebtoken::encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret(secret),
+            )
+            .unwrap();
+            headers("authorization", &format!("Bearer {}", token))
+        };
+        let now = crate::artifacts::unix_time(std::time::SystemTime::now());
+        let principal = config
+            .authenticate(&token(b"jwt-secret", now + 60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "jwt:ci");
+        assert_eq!(principal.method, AuthMethod::Jwt);
+        assert!(principal.has_role("student"));
+        assert!(!principal.is_admin());
+        for headers in [
+            token(b"other-secret", now + 60),
+            token(b"jwt-secret", now - 3600),
+            headers("authorization", "Basic dXNlcjpwYXNz"),
+        ] {
+            assert!(matches!(
+                config.authenticate(&headers),
+                Err(Error::Unauthorized(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use actix_web::{
+    http::{
+        header,
+        StatusCode,
+    },
+    HttpResponse,
+    ResponseError,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request is malformed or out of the accepted limits.
+    #[error("{0}")]
+    Validation(String),
+    /// Fields of a form are missing or malformed, all of them being reported at once.
+    #[error("Invalid form fields")]
+    InvalidFields(Vec<FieldError>),
+    #[error("Unsupported language: {0}")]
+    UnsupportedLanguage(String),
+    /// No credentials were sent, or they are invalid.
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    /// Too many requests were sent in a short time.
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        retry_after_secs: u64,
+    },
+    /// The executions or execution time allowed per day are used up.
+    #[error("{message}")]
+    DailyQuotaExceeded {
+        message: String,
+        retry_after_secs: u64,
+    },
+    /// Too many Jobs are already waiting for a slot.
+    #[error("{0}")]
+    QueueFull(String),
+    /// Storing the upload would exceed the space allowed to uploaded files.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// The caller already used the execution id of the request.
+    #[error("{0}")]
+    Conflict(String),
+    #[error("Execution {0} was cancelled")]
+    Cancelled(String),
+    /// The submitted program did not compile, holding the compiler output.
+    #[error("Compilation failed")]
+    Compile(String),
+    /// The submitted program failed, holding its stderr and its exit code, when known.
+    #[error("Program exited with an error")]
+    Runtime {
+        details: String,
+        exit_code: Option<i32>,
+    },
+    #[error("Execution did not finish in the expected time")]
+    Timeout,
+    /// Kubernetes, the shared volume or anything else on our side failed.
+    #[error("{0}")]
+    Infrastructure(String),
+}
+
+/// Stable identifier of an [`Error`], for clients to match on.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    ValidationError,
+    UnsupportedLanguage,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RateLimited,
+    DailyQuotaExceeded,
+    QueueFull,
+    QuotaExceeded,
+    Conflict,
+    Cancelled,
+    CompileError,
+    RuntimeError,
+    Timeout,
+    InfrastructureError,
+}
+
+impl ErrorCode {
+    /// The code as serialized, e.g. `compile_error`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ValidationError => "validation_error",
+            ErrorCode::UnsupportedLanguage => "unsupported_language",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::DailyQuotaExceeded => "daily_quota_exceeded",
+            ErrorCode::QueueFull => "queue_full",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::CompileError => "compile_error",
+            ErrorCode::RuntimeError => "runtime_error",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::InfrastructureError => "infrastructure_error",
+        }
+    }
+}
+
+/// What is wrong with a field of a multipart form.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldProblem {
+    Missing,
+    /// A text field is not valid UTF-8.
+    InvalidEncoding,
+    Duplicate,
+    Unknown,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub problem: FieldProblem,
+}
+
+/// Body of every error response.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub error: String,
+    /// Compiler output or stderr of the program.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    /// Every invalid field of a rejected form.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Validation(_) | Error::InvalidFields(_) => ErrorCode::ValidationError,
+            Error::UnsupportedLanguage(_) => ErrorCode::UnsupportedLanguage,
+            Error::Unauthorized(_) => ErrorCode::Unauthorized,
+            Error::Forbidden(_) => ErrorCode::Forbidden,
+            Error::NotFound(_) => ErrorCode::NotFound,
+            Error::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            Error::UnsupportedMediaType(_) => ErrorCode::UnsupportedMediaType,
+            Error::RateLimited { .. } => ErrorCode::RateLimited,
+            Error::DailyQuotaExceeded { .. } => ErrorCode::DailyQuotaExceeded,
+            Error::QueueFull(_) => ErrorCode::QueueFull,
+            Error::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            Error::Conflict(_) => ErrorCode::Conflict,
+            Error::Cancelled(_) => ErrorCode::Cancelled,
+            Error::Compile(_) => ErrorCode::CompileError,
+            Error::Runtime { .. } => ErrorCode::RuntimeError,
+            Error::Timeout => ErrorCode::Timeout,
+            Error::Infrastructure(_) => ErrorCode::InfrastructureError,
+        }
+    }
+
+    /// Error response mentioning the execution it happened in.
+    pub fn response_for(&self, execution_id: Option<&str>) -> HttpResponse {
+        let details = match self {
+            Error::Compile(details) | Error::Runtime { details, .. } => Some(details.clone()),
+            _ => None,
+        };
+
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            Error::Unauthorized(_) => {
+                response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+            }
+            Error::RateLimited {
+                retry_after_secs, ..
+            }
+            | Error::DailyQuotaExceeded {
+                retry_after_secs, ..
+            } => {
+                response.insert_header((header::RETRY_AFTER, retry_after_secs.to_string()));
+            }
+            _ => {}
+        }
+        response.json(ErrorResponse {
+            code: self.code(),
+            error: self.to_string(),
+            details,
+            execution_id: execution_id.map(str::to_string),
+            fields: match self {
+                Error::InvalidFields(fields) => fields.clone(),
+                _ => Vec::new(),
+            },
+        })
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Validation(_) | Error::InvalidFields(_) | Error::UnsupportedLanguage(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::RateLimited { .. } | Error::DailyQuotaExceeded { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::QueueFull(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+            Error::Conflict(_) | Error::Cancelled(_) => StatusCode::CONFLICT,
+            Error::Compile(_) | Error::Runtime { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Error::Infrastructure(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.response_for(None)
+    }
+}
+
+impl From<kube::Error> for Error {
+    fn from(e: kube::Error) -> Self {
+        Error::Infrastructure(format!("Kubernetes error: {}", e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Infrastructure(format!("I/O error: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Infrastructure(format!("Serialization error: {}", e))
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Infrastructure(format!("History database error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let response = Error::Compile("expected `;`".to_string()).response_for(Some("42"));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "compile_error",
+                "error": "Compilation failed",
+                "details": "expected `;`",
+                "execution_id": "42",
+            })
+        );
+
+        let response = Error::Validation("Field 'code' is required".to_string()).error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "validation_error");
+        assert!(body.get("details").is_none());
+        assert!(body.get("fields").is_none());
+
+        let response = Error::InvalidFields(vec![FieldError {
+            field: "code".to_string(),
+            problem: FieldProblem::Missing,
+        }])
+        .error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["fields"],
+            serde_json::json!([{ "field": "code", "problem": "missing" }])
+        );
+    }
+}
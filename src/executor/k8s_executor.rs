@@ -1,17 +1,45 @@
 use crate::{
-    executor::CodeExecutor,
+    artifacts::ArtifactStore,
+    error::{
+        Error,
+        Result,
+    },
+    executor::{
+        outputs::collect_output_files,
+        queue::{
+            current_principal,
+            ExecutionQueue,
+        },
+        CodeExecutor,
+        Language,
+    },
+    metrics,
+    telemetry,
     types::{
+        BatchPayload,
+        BatchResult,
         ExecutionPayload,
         ExecutionResult,
+        ExecutionStatus,
+        OutputFile,
     },
 };
-use k8s_openapi::api::batch::v1::{
-    Job,
-    JobStatus,
+use futures_util::future::try_join_all;
+use k8s_openapi::api::{
+    batch::v1::{
+        Job,
+        JobStatus,
+    },
+    core::v1::{
+        ContainerStateTerminated,
+        Pod,
+    },
 };
 use kube::{
     api::{
         DeleteParams,
+        ListParams,
+        LogParams,
         PostParams,
     },
     Api,
@@ -21,196 +49,1201 @@ use log::{
     debug,
     error,
     info,
+    warn,
 };
 use serde_json::json;
+use sha2::{
+    Digest,
+    Sha256,
+};
 use std::{
+    collections::HashMap,
     env,
-    time::Duration,
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
 };
 use tokio::{
     task,
     time::sleep,
 };
+use tracing::{
+    info_span,
+    Instrument,
+};
 
 pub struct K8sExecutor;
 const DEFAULT_ERROR_MESSAGE: &str = "EXECUTOR_ERROR";
+/// Printed by the script before [`DEFAULT_ERROR_MESSAGE`] when the program did not compile.
+const COMPILE_ERROR_MESSAGE: &str = "COMPILE_ERROR";
+const EXECUTION_ID_LABEL: &str = "dyno-code/execution-id";
+/// Hash of the principal the Job runs for, execution ids being chosen by the clients.
+const OWNER_LABEL: &str = "dyno-code/owner";
+const TRACE_ID_LABEL: &str = "dyno-code/trace-id";
+/// W3C `traceparent` of the span that created the Job.
+const TRACEPARENT_ANNOTATION: &str = "dyno-code/traceparent";
+/// Holds a [`JobDir`] per execution.
+const JOBS_DIR: &str = "/mnt/shared/jobs";
+/// Where the pods see the `input` directory of their [`JobDir`], read-only.
+const INPUT_MOUNT: &str = "/mnt/input";
+/// Where the pods see the `output` directory of their [`JobDir`].
+const OUTPUT_MOUNT: &str = "/mnt/output";
+/// Where the supervisor of a batch sees the `supervisor` directory of its [`JobDir`].
+const SUPERVISOR_MOUNT: &str = "/mnt/supervisor";
+/// Where the supervisor creates the pipes it talks to the program through.
+const PIPES_MOUNT: &str = "/mnt/pipes";
+const EXECUTOR_USER: u32 = 1000;
+const SUPERVISOR_USER: u32 = 1001;
+/// Memory of a supervisor only handing the stdin and stdout of the cases over.
+const SUPERVISOR_MEMORY_LIMIT_MB: u64 = 64;
+pub const MAX_BATCH_PARALLELISM: usize = 8;
+pub const MAX_BATCH_CASES: usize = 1000;
+pub const MAX_TIME_LIMIT_MS: u64 = 30_000;
+pub const MAX_MEMORY_LIMIT_MB: u64 = 1024;
+const DEFAULT_MEMORY_REQUEST_MB: u64 = 512;
+/// Time a Job gets on top of running its program: scheduling the pod, pulling the image and
+/// compiling the program.
+const JOB_STARTUP_SLACK: Duration = Duration::from_secs(120);
+/// Time a case may take past its limit, `timeout` only killing it a second after.
+const CASE_KILL_GRACE: Duration = Duration::from_secs(1);
+/// Time a single execution, which has no time limit of its own, may run for.
+const EXECUTION_DEADLINE: Duration = Duration::from_secs(180);
+/// How long cancelled executions are remembered, longer than any of their Jobs can wait and run.
+const CANCELLED_RETENTION: Duration = Duration::from_secs(3600);
+
+/// Executions cancelled by [`K8sExecutor::cancel`], by owner and execution id, with when. Their
+/// running Jobs notice it without asking the API and their queued ones are not created anymore.
+static CANCELLED: Mutex<Vec<(String, String, Instant)>> = Mutex::new(Vec::new());
+
+/// Directory of the shared volume holding what the server hands to the Jobs of one execution, in
+/// `input`, and what they hand back, in `output`. Only these two directories are mounted into the
+/// pods, `input` read-only, so that a Job never sees the hidden inputs or the results of another.
+/// A batch also has a `supervisor` directory, only mounted into its supervisor.
+struct JobDir {
+    name: String,
+    /// Whether [`Self::remove`] ran, the directory being removed on drop otherwise.
+    removed: bool,
+}
+
+impl JobDir {
+    async fn create(name: String) -> std::io::Result<Self> {
+        let dir = JobDir {
+            name,
+            removed: false,
+        };
+        tokio::fs::create_dir_all(dir.input()).await?;
+        tokio::fs::create_dir_all(dir.output()).await?;
+        // The pods do not run as the user of the server.
+        tokio::fs::set_permissions(dir.output(), Permissions::from_mode(0o777)).await?;
+        Ok(dir)
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{}", JOBS_DIR, self.name)
+    }
+
+    fn input(&self) -> String {
+        format!("{}/input", self.path())
+    }
+
+    fn output(&self) -> String {
+        format!("{}/output", self.path())
+    }
+
+    /// Only mounted into the supervisor, see [`K8sExecutor::supervisor_container`].
+    fn supervisor(&self) -> String {
+        format!("{}/supervisor", self.path())
+    }
+
+    async fn create_supervisor(&self) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(self.supervisor()).await?;
+        tokio::fs::set_permissions(self.supervisor(), Permissions::from_mode(0o777)).await
+    }
+
+    /// Path of a directory of the Job relative to the root of the shared volume.
+    fn sub_path(&self, directory: &str) -> String {
+        format!("jobs/{}/{}", self.name, directory)
+    }
+
+    async fn remove(mut self) {
+        self.removed = true;
+        Self::remove_path(self.path()).await;
+    }
+
+    async fn remove_path(path: String) {
+        if let Err(e) = tokio::fs::remove_dir_all(&path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to clean up Job directory {}: {:?}", path, e);
+            }
+        }
+    }
+}
+
+/// Removes the directory in the background when the execution future is dropped before
+/// [`JobDir::remove`], which is what happens to the handler when the client disconnects.
+impl Drop for JobDir {
+    fn drop(&mut self) {
+        if self.removed {
+            return;
+        }
+
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(Self::remove_path(self.path()));
+        }
+    }
+}
+
+/// What the pod of a finished Job hands back: the logs of its executor container, split into the
+/// output and the error the script reported, and how long that container ran according to
+/// Kubernetes, which unlike the timings of the script the program cannot forge, and its exit
+/// code, the one of the program in a single execution.
+struct JobLogs {
+    output: String,
+    error: String,
+    running_time: Option<Duration>,
+    exit_code: Option<i32>,
+}
+
+/// Deletes the Job if the execution future is dropped before completion, which is what happens
+/// to the handler when the client disconnects.
+struct JobGuard {
+    jobs: Api<Job>,
+    job_name: String,
+    armed: bool,
+}
+
+impl JobGuard {
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let jobs = self.jobs.clone();
+        let job_name = std::mem::take(&mut self.job_name);
+        task::spawn(async move {
+            match jobs.delete(&job_name, &DeleteParams::foreground()).await {
+                Ok(_) => info!("Cancelled Job {} after the client went away", job_name),
+                Err(e) => error!("Failed to cancel Job {}: {:?}", job_name, e),
+            }
+        });
+    }
+}
 
 #[async_trait::async_trait]
 impl CodeExecutor for K8sExecutor {
-    async fn execute(
-        payload: &ExecutionPayload,
-    ) -> Result<ExecutionResult, Box<dyn std::error::Error>> {
-        let client = Client::try_default().await?;
-        let project_id = env::var("GOOGLE_CLOUD_PROJECT_ID")
-            .expect("GOOGLE_CLOUD_PROJECT_ID environment variable must be set");
+    async fn execute(payload: &ExecutionPayload) -> Result<ExecutionResult> {
+        let client = Client::try_default().await.map_err(api_error("connect"))?;
+        let project_id = Self::project_id()?;
         let jobs: Api<Job> = Api::default_namespaced(client.clone());
-        let job_name = format!("job-{}", uuid::Uuid::new_v4());
+        let execution_id = payload
+            .execution_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let principal = current_principal();
+        let owner = Self::owner(&principal);
+        let run_name = Self::run_name(&owner, &execution_id);
+        let job_name = Self::job_name(&run_name);
         info!(
-            "Creating Job with name: {}, to the project: {}",
-            job_name, project_id
+            job_name = job_name.as_str(),
+            language = payload.language.as_str();
+            "Creating Job in project {}",
+            project_id
         );
 
+        let job_dir = JobDir::create(run_name).await?;
         let input_file_arg = match &payload.input_file_path {
-            Some(path) => format!(
-                "/mnt/shared/{}",
-                std::path::Path::new(path)
+            Some(path) => {
+                let name = Path::new(path)
                     .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-            ),
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "input".to_string());
+                if let Err(e) = tokio::fs::copy(path, format!("{}/{}", job_dir.input(), name)).await
+                {
+                    job_dir.remove().await;
+                    return Err(e.into());
+                }
+                format!("{}/{}", INPUT_MOUNT, name)
+            }
             None => "".to_string(),
         };
 
         info!("Input file path: {}", input_file_arg);
 
-        let output_file_arg = format!(
-            "/mnt/shared/output/output_{}{}",
-            uuid::Uuid::new_v4(),
-            payload.output_extension.trim()
+        let output_file_name = format!("output{}", payload.output_extension.trim());
+        let output_file_arg = format!("{}/{}", OUTPUT_MOUNT, output_file_name);
+        let output_dir = format!("{}/files", OUTPUT_MOUNT);
+        let timings_file = format!("{}/timings", OUTPUT_MOUNT);
+
+        let image_name = Self::image_name(&project_id, &payload.language)?;
+        let command = format!(
+            "./executor_script.sh '{}' '{}' '{}' '{}' '{}'",
+            payload.language,
+            payload.code.replace("'", "'\\''"),
+            input_file_arg,
+            output_file_arg,
+            output_dir
         );
+        let job_spec = Self::job_spec(
+            &job_name,
+            &owner,
+            &execution_id,
+            vec![Self::executor_container(
+                &image_name,
+                command,
+                json!([{ "name": "TIMINGS_FILE", "value": timings_file }]),
+                None,
+                &job_dir,
+                false,
+            )],
+        )?;
 
-        let image_name = match payload.language.as_str() {
-            "rust" => format!("gcr.io/{}/executor-rust:latest", project_id),
-            "python" => format!("gcr.io/{}/executor-python:latest", project_id),
-            "javascript" => format!("gcr.io/{}/executor-nodejs:latest", project_id),
-            "lua" => format!("gcr.io/{}/executor-lua:latest", project_id),
-            _ => return Err(Box::from("Unsupported language")),
+        let logs = Self::run_job(
+            &client,
+            &jobs,
+            &job_name,
+            &job_spec,
+            (&owner, &execution_id),
+            &payload.language,
+            EXECUTION_DEADLINE,
+        )
+        .await;
+        let artifacts = ArtifactStore::from_env();
+        let (output_file, output_files) = match logs {
+            Ok(Some(JobLogs { ref error, .. })) if error.is_empty() => {
+                async {
+                    (
+                        Self::store_output_file(
+                            &artifacts,
+                            &principal,
+                            &format!("{}/{}", job_dir.output(), output_file_name),
+                        )
+                        .await,
+                        collect_output_files(
+                            Path::new(&format!("{}/files", job_dir.output())),
+                            &artifacts,
+                            &principal,
+                        )
+                        .await,
+                    )
+                }
+                .instrument(info_span!("collect_outputs"))
+                .await
+            }
+            _ => (Ok(None), Ok((Vec::new(), false))),
+        };
+        let timings = Self::read_timings(&format!("{}/timings", job_dir.output())).await;
+        if let Some(compile_ms) = timings.get("compile_ms") {
+            metrics::COMPILE_TIME.observe(&[("language", &payload.language)], seconds(*compile_ms));
+        }
+        let time_ms = timings.get("run_ms").copied();
+        if let Some(run_ms) = time_ms {
+            metrics::RUN_TIME.observe(&[("language", &payload.language)], seconds(run_ms));
+        }
+        job_dir.remove().instrument(info_span!("cleanup")).await;
+
+        let Some(JobLogs {
+            output,
+            error,
+            running_time,
+            exit_code,
+        }) = logs?
+        else {
+            return Err(Error::Cancelled(execution_id));
         };
 
-        let job_spec = json!({
+        if !error.is_empty() {
+            return Err(match error.strip_suffix(COMPILE_ERROR_MESSAGE) {
+                Some(compile_output) => Error::Compile(compile_output.trim().to_string()),
+                None => Error::Runtime {
+                    details: error,
+                    exit_code,
+                },
+            });
+        }
+
+        let output_file = output_file?;
+        let (output_files, output_files_truncated) = output_files?;
+        if output_files_truncated {
+            warn!(execution_id = execution_id.as_str(); "Output files left out of the manifest");
+        }
+        metrics::OUTPUT_SIZE.observe(&[("kind", "stdout")], output.len() as f64);
+        for file in output_file.iter().chain(&output_files) {
+            metrics::OUTPUT_SIZE.observe(&[("kind", "file")], file.size as f64);
+        }
+
+        Ok(ExecutionResult {
+            output,
+            error,
+            output_file_path: None,
+            output_file_content: None,
+            status: ExecutionStatus::Completed,
+            exit_code,
+            time_ms,
+            billed_ms: running_time.map(|time| time.as_millis() as u64),
+            output_file,
+            output_files,
+            output_files_truncated,
+        })
+    }
+
+    async fn execute_batch(payload: &BatchPayload) -> Result<BatchResult> {
+        let client = Client::try_default().await.map_err(api_error("connect"))?;
+        let project_id = Self::project_id()?;
+        let jobs: Api<Job> = Api::default_namespaced(client.clone());
+        let execution_id = payload
+            .execution_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let image_name = Self::image_name(&project_id, &payload.language)?;
+        let interactor_image_name = match payload.interactor {
+            Some(ref interactor) => Some(Self::image_name(&project_id, &interactor.language)?),
+            None => None,
+        };
+
+        let owner = Self::owner(&current_principal());
+        let job_dir = JobDir::create(Self::run_name(
+            &owner,
+            &Self::batch_name(payload, &execution_id),
+        ))
+        .await?;
+        let images = (image_name.as_str(), interactor_image_name.as_deref());
+        let result = Self::run_batch(
+            &client,
+            &jobs,
+            payload,
+            (&owner, &execution_id),
+            images,
+            &job_dir,
+        )
+        .await;
+        job_dir.remove().await;
+
+        result
+    }
+}
+
+/// Counts the failed call to the Kubernetes API before converting its error.
+fn api_error(operation: &'static str) -> impl FnOnce(kube::Error) -> Error {
+    move |e| {
+        metrics::KUBERNETES_API_ERRORS.inc(&[("operation", operation)]);
+        e.into()
+    }
+}
+
+fn seconds(milliseconds: u64) -> f64 {
+    milliseconds as f64 / 1000.0
+}
+
+impl K8sExecutor {
+    /// Value of the [`OWNER_LABEL`] of the Jobs of the principal.
+    fn owner(principal: &str) -> String {
+        format!("{:x}", Sha256::digest(principal))[..32].to_string()
+    }
+
+    /// Name of the [`JobDir`] of the execution, or of one of its stages, of the owner.
+    fn run_name(owner: &str, name: &str) -> String {
+        format!("{}-{}", owner, name)
+    }
+
+    /// Hashes the run name, which would not fit in the 63 characters of the `job-name` label
+    /// of the pods. The Jobs are found back by their labels.
+    fn job_name(run_name: &str) -> String {
+        format!("job-{}", &format!("{:x}", Sha256::digest(run_name))[..40])
+    }
+
+    pub(super) fn project_id() -> Result<String> {
+        env::var("GOOGLE_CLOUD_PROJECT_ID").map_err(|_| {
+            Error::Infrastructure(
+                "GOOGLE_CLOUD_PROJECT_ID environment variable must be set".to_string(),
+            )
+        })
+    }
+
+    pub(super) fn image_name(project_id: &str, language: &str) -> Result<String> {
+        match Language::find(language) {
+            Some(language) => Ok(format!("gcr.io/{}/{}:latest", project_id, language.image)),
+            None => Err(Error::UnsupportedLanguage(language.to_string())),
+        }
+    }
+
+    /// Container running the program with the executor script, which only sees the [`JobDir`] of
+    /// its execution. The one of a batch also mounts the pipes of the supervisor.
+    fn executor_container(
+        image_name: &str,
+        command: String,
+        env: serde_json::Value,
+        memory_limit_mb: Option<u64>,
+        job_dir: &JobDir,
+        batch: bool,
+    ) -> serde_json::Value {
+        let mut volume_mounts = vec![
+            json!({
+                "name": "shared-volume",
+                "mountPath": INPUT_MOUNT,
+                "subPath": job_dir.sub_path("input"),
+                "readOnly": true
+            }),
+            json!({
+                "name": "shared-volume",
+                "mountPath": OUTPUT_MOUNT,
+                "subPath": job_dir.sub_path("output"),
+                "readOnly": false
+            }),
+            json!({
+                "name": "executor-sandbox",
+                "mountPath": "/home/executor/sandbox",
+                "readOnly": false
+            }),
+        ];
+        if batch {
+            volume_mounts.push(json!({
+                "name": "pipes",
+                "mountPath": PIPES_MOUNT,
+                "readOnly": false
+            }));
+        }
+        Self::container(
+            "executor",
+            image_name,
+            command,
+            env,
+            memory_limit_mb,
+            EXECUTOR_USER,
+            volume_mounts,
+        )
+    }
+
+    /// Container running the supervisor of a batch next to the program, as another user and
+    /// with its own directory of the [`JobDir`]. It hands the stdin of each case to the program,
+    /// or runs the interactor, and records the stdout, exit code and duration of the case there,
+    /// so that the program can neither read the inputs nor forge the results of any case, nor
+    /// signal the supervisor.
+    fn supervisor_container(
+        image_name: &str,
+        env: serde_json::Value,
+        memory_limit_mb: Option<u64>,
+        job_dir: &JobDir,
+    ) -> serde_json::Value {
+        Self::container(
+            "supervisor",
+            image_name,
+            "./executor_script.sh".to_string(),
+            env,
+            memory_limit_mb,
+            SUPERVISOR_USER,
+            vec![
+                json!({
+                    "name": "shared-volume",
+                    "mountPath": SUPERVISOR_MOUNT,
+                    "subPath": job_dir.sub_path("supervisor"),
+                    "readOnly": false
+                }),
+                json!({
+                    "name": "supervisor-sandbox",
+                    "mountPath": "/home/executor/sandbox",
+                    "readOnly": false
+                }),
+                json!({
+                    "name": "pipes",
+                    "mountPath": PIPES_MOUNT,
+                    "readOnly": false
+                }),
+            ],
+        )
+    }
+
+    fn container(
+        name: &str,
+        image_name: &str,
+        command: String,
+        env: serde_json::Value,
+        memory_limit_mb: Option<u64>,
+        user: u32,
+        volume_mounts: Vec<serde_json::Value>,
+    ) -> serde_json::Value {
+        let memory_limit_mb = memory_limit_mb.unwrap_or(MAX_MEMORY_LIMIT_MB);
+        let memory_request_mb = memory_limit_mb.min(DEFAULT_MEMORY_REQUEST_MB);
+        json!({
+            "name": name,
+            "image": image_name,
+            "command": ["sh", "-c", command],
+            "env": env,
+            "securityContext": {
+                "runAsUser": user,
+                "runAsGroup": user,
+                "allowPrivilegeEscalation": false,
+                "capabilities": {
+                    "drop": ["ALL"]
+                },
+                "readOnlyRootFilesystem": true,
+                "seccompProfile": {
+                    "type": "RuntimeDefault"
+                },
+                "runAsNonRoot": true,
+                "privileged": false
+            },
+            "resources": {
+                "limits": {
+                    "memory": format!("{}Mi", memory_limit_mb),
+                    "cpu": "1000m"
+                },
+                "requests": {
+                    "memory": format!("{}Mi", memory_request_mb),
+                    "cpu": "500m"
+                }
+            },
+            "volumeMounts": volume_mounts
+        })
+    }
+
+    fn job_spec(
+        job_name: &str,
+        owner: &str,
+        execution_id: &str,
+        containers: Vec<serde_json::Value>,
+    ) -> Result<Job, serde_json::Error> {
+        // Lets the Job and its pod be found from the trace of the request, and the other way
+        // around.
+        let mut labels = json!({ EXECUTION_ID_LABEL: execution_id, OWNER_LABEL: owner });
+        let mut annotations = json!({});
+        if let Some(trace_id) = telemetry::current_trace_id() {
+            labels[TRACE_ID_LABEL] = json!(trace_id);
+        }
+        if let Some(traceparent) = telemetry::current_traceparent() {
+            annotations[TRACEPARENT_ANNOTATION] = json!(traceparent);
+        }
+
+        // Every other volume the containers mount is scratch space of the pod.
+        let mut volumes = vec![json!({
+            "name": "shared-volume",
+            "persistentVolumeClaim": {
+                "claimName": "shared-pvc-nfs"
+            }
+        })];
+        for name in containers
+            .iter()
+            .filter_map(|container| container["volumeMounts"].as_array())
+            .flatten()
+            .filter_map(|mount| mount["name"].as_str())
+        {
+            if !volumes.iter().any(|volume| volume["name"] == name) {
+                volumes.push(json!({ "name": name, "emptyDir": {} }));
+            }
+        }
+
+        serde_json::from_value(json!({
             "apiVersion": "batch/v1",
             "kind": "Job",
             "metadata": {
-                "name": job_name
+                "name": job_name,
+                "labels": labels,
+                "annotations": annotations
             },
             "spec": {
                 "parallelism": 1,
                 "template": {
                     "metadata": {
-                        "name": job_name
+                        "name": job_name,
+                        "labels": labels,
+                        "annotations": annotations
                     },
                     "spec": {
-                        "containers": [{
-                            "name": "executor",
-                            "image": image_name,
-                            "command": ["sh", "-c", format!(
-                                "./executor_script.sh '{}' '{}' '{}' '{}'",
-                                payload.language,
-                                payload.code.replace("'", "'\\''"),
-                                input_file_arg,
-                                output_file_arg
-                            )],
-                            "securityContext": {
-                                "runAsUser": 1000,
-                                "runAsGroup": 1000,
-                                "allowPrivilegeEscalation": false,
-                                "capabilities": {
-                                    "drop": ["ALL"]
-                                },
-                                "readOnlyRootFilesystem": true,
-                                "seccompProfile": {
-                                    "type": "RuntimeDefault"
-                                },
-                                "runAsNonRoot": true,
-                                "privileged": false
-                            },
-                            "resources": {
-                                "limits": {
-                                    "memory": "1024Mi",
-                                    "cpu": "1000m"
-                                },
-                                "requests": {
-                                    "memory": "512Mi",
-                                    "cpu": "500m"
-                                }
-                            },
-                            "volumeMounts": [{
-                                "name": "shared-volume",
-                                "mountPath": "/mnt/shared",
-                                "readOnly": false
-                            },
-                            {
-                                "name": "executor-sandbox",
-                                "mountPath": "/home/executor/sandbox",
-                                "readOnly": false
-                            }]
-                        }],
+                        "containers": containers,
                         "restartPolicy": "Never",
-                        "volumes": [{
-                            "name": "shared-volume",
-                            "persistentVolumeClaim": {
-                                "claimName": "shared-pvc-nfs"
-                            }
-                        },
-                        {
-                            "name": "executor-sandbox",
-                            "emptyDir": {}
-                        }]
+                        "volumes": volumes
                     }
                 },
                 "backoffLimit": 2
             }
-        });
+        }))
+    }
 
-        let job_spec: Job = serde_json::from_value(job_spec)?;
-        jobs.create(&PostParams::default(), &job_spec).await?;
-        let (output, error) = Self::wait_for_pod_and_get_logs(&client, &job_name).await?;
+    /// Waits for a slot in the [`ExecutionQueue`], creates the Job and waits for its logs, see
+    /// [`Self::wait_for_pod_and_get_logs`]. The Job is cleaned up in the background once it is
+    /// finished.
+    async fn run_job(
+        client: &Client,
+        jobs: &Api<Job>,
+        job_name: &str,
+        job_spec: &Job,
+        (owner, execution_id): (&str, &str),
+        language: &str,
+        deadline: Duration,
+    ) -> Result<Option<JobLogs>> {
+        let slot = ExecutionQueue::global()
+            .acquire(execution_id, language)
+            .await?;
+        if slot.is_none() || Self::was_cancelled(owner, execution_id) {
+            info!(job_name = job_name, language = language; "Job was cancelled while queued");
+            return Ok(None);
+        }
+
+        let start = Instant::now();
+        jobs.create(&PostParams::default(), job_spec)
+            .instrument(info_span!("create_job", job = job_name))
+            .await
+            .map_err(api_error("create_job"))?;
+        let guard = JobGuard {
+            jobs: jobs.clone(),
+            job_name: job_name.to_string(),
+            armed: true,
+        };
+        let logs = Self::wait_for_pod_and_get_logs(
+            client,
+            jobs,
+            job_name,
+            (owner, execution_id),
+            language,
+            deadline,
+        )
+        .await;
+        guard.disarm();
+
+        let Some(logs) = logs? else {
+            info!(job_name = job_name, language = language; "Job was cancelled");
+            return Ok(None);
+        };
+        info!(
+            job_name = job_name,
+            language = language,
+            duration_ms = start.elapsed().as_millis() as u64;
+            "Job finished"
+        );
 
         let jobs_clone = jobs.clone();
-        let job_name_clone = job_name.clone();
-        task::spawn(async move {
-            if let Err(e) = Self::cleanup_job(&jobs_clone, &job_name_clone).await {
-                error!("Failed to clean up job {}: {:?}", job_name_clone, e);
+        let job_name_clone = job_name.to_string();
+        let span = info_span!("cleanup_job", job = job_name);
+        task::spawn(
+            async move {
+                if let Err(e) = Self::cleanup_job(&jobs_clone, &job_name_clone).await {
+                    error!("Failed to clean up job {}: {:?}", job_name_clone, e);
+                }
             }
-        });
+            .instrument(span),
+        );
 
-        Ok(ExecutionResult {
-            output,
-            error,
-            output_file_path: Some(output_file_arg),
-            output_file_content: Some(String::new()),
+        Ok(Some(logs))
+    }
+
+    /// Reads the `name=milliseconds` lines the script writes to `TIMINGS_FILE`.
+    async fn read_timings(path: &str) -> HashMap<String, u64> {
+        let content = tokio::fs::read_to_string(path).await.unwrap_or_default();
+        content
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                Some((name.trim().to_string(), value.trim().parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Stores the file the program wrote to `OUTPUT_PATH` as an artifact of the principal, named
+    /// after its extension, if any.
+    async fn store_output_file(
+        artifacts: &ArtifactStore,
+        principal: &str,
+        path: &str,
+    ) -> std::io::Result<Option<OutputFile>> {
+        let path = Path::new(path);
+        if !tokio::fs::try_exists(path).await? {
+            return Ok(None);
+        }
+        let name = match path.extension() {
+            Some(extension) => format!("output.{}", extension.to_string_lossy()),
+            None => "output".to_string(),
+        };
+        artifacts.store(principal, path, &name).await.map(Some)
+    }
+
+    fn batch_name(payload: &BatchPayload, execution_id: &str) -> String {
+        match payload.stage {
+            Some(ref stage) => format!("{}-{}", execution_id, stage),
+            None => execution_id.to_string(),
+        }
+    }
+
+    /// Cases without a time limit are still killed after [`MAX_TIME_LIMIT_MS`], so that a program
+    /// that never ends cannot hold its Job until the pod times out.
+    fn case_time_limit_ms(payload: &BatchPayload) -> u64 {
+        payload.time_limit_ms.unwrap_or(MAX_TIME_LIMIT_MS)
+    }
+
+    /// Time the Job running `cases` cases of the batch one after the other may take.
+    fn batch_deadline(time_limit_ms: Option<u64>, cases: usize) -> Duration {
+        let case_time_limit = Duration::from_millis(time_limit_ms.unwrap_or(MAX_TIME_LIMIT_MS));
+        JOB_STARTUP_SLACK + (case_time_limit + CASE_KILL_GRACE) * cases as u32
+    }
+
+    /// Environment of the executor container of the Job running the cases of `manifest_name`.
+    fn batch_env(payload: &BatchPayload, manifest_name: &str) -> Vec<serde_json::Value> {
+        vec![
+            json!({ "name": "BATCH_MANIFEST", "value": format!("{}/{}", INPUT_MOUNT, manifest_name) }),
+            json!({ "name": "BATCH_OUTPUT_DIR", "value": OUTPUT_MOUNT }),
+            json!({ "name": "OUTPUT_EXTENSION", "value": payload.output_extension.trim() }),
+            json!({ "name": "PIPES_DIR", "value": PIPES_MOUNT }),
+            json!({ "name": "TIME_LIMIT_MS", "value": Self::case_time_limit_ms(payload).to_string() }),
+        ]
+    }
+
+    /// Environment of the supervisor container, see [`Self::supervisor_container`].
+    fn supervisor_env(payload: &BatchPayload, manifest_name: &str) -> Vec<serde_json::Value> {
+        let mut env = vec![
+            json!({ "name": "SUPERVISOR_MANIFEST", "value": format!("{}/{}", SUPERVISOR_MOUNT, manifest_name) }),
+            json!({ "name": "SUPERVISOR_OUTPUT_DIR", "value": SUPERVISOR_MOUNT }),
+            json!({ "name": "PIPES_DIR", "value": PIPES_MOUNT }),
+            json!({ "name": "TIME_LIMIT_MS", "value": Self::case_time_limit_ms(payload).to_string() }),
+        ];
+        if let Some(ref interactor) = payload.interactor {
+            env.push(json!({ "name": "INTERACTOR_LANGUAGE", "value": interactor.language }));
+            env.push(json!({ "name": "INTERACTOR_CODE_PATH", "value": format!("{}/code", SUPERVISOR_MOUNT) }));
+        }
+        env
+    }
+
+    /// Spreads the cases over `parallelism` Jobs. Each Job reads its cases from a manifest in the
+    /// input of the [`JobDir`] and compiles the program once. Its supervisor runs in its own
+    /// container with its own manifest, the stdin files of the cases being its inputs, and writes
+    /// one result directory per case to the `supervisor` directory, see
+    /// [`Self::supervisor_container`], the program only writing the stderr and output file of the
+    /// cases to its output. `images` are the ones of the program and of the interactor, if any,
+    /// which is also the one of the supervisor.
+    async fn run_batch(
+        client: &Client,
+        jobs: &Api<Job>,
+        payload: &BatchPayload,
+        (owner, execution_id): (&str, &str),
+        (image_name, interactor_image_name): (&str, Option<&str>),
+        job_dir: &JobDir,
+    ) -> Result<BatchResult> {
+        let run_name = Self::run_name(owner, &Self::batch_name(payload, execution_id));
+        job_dir.create_supervisor().await?;
+        let mut manifest_lines = Vec::with_capacity(payload.cases.len());
+        let mut supervisor_manifest_lines = Vec::with_capacity(payload.cases.len());
+        for (index, case) in payload.cases.iter().enumerate() {
+            match &case.stdin {
+                Some(stdin) => {
+                    tokio::fs::write(format!("{}/{}.stdin", job_dir.supervisor(), index), stdin)
+                        .await?;
+                    supervisor_manifest_lines
+                        .push(format!("{}|{}/{}.stdin\n", index, SUPERVISOR_MOUNT, index));
+                }
+                None => supervisor_manifest_lines.push(format!("{}|/dev/null\n", index)),
+            }
+            let input_path = match &case.input_file_path {
+                Some(path) => {
+                    tokio::fs::copy(path, format!("{}/{}.input", job_dir.input(), index)).await?;
+                    format!("{}/{}.input", INPUT_MOUNT, index)
+                }
+                None => String::new(),
+            };
+            let mut argument_paths = Vec::with_capacity(case.arguments.len());
+            for (position, argument) in case.arguments.iter().enumerate() {
+                let name = format!("{}.arg{}", index, position);
+                tokio::fs::write(format!("{}/{}", job_dir.input(), name), argument).await?;
+                argument_paths.push(format!("{}/{}", INPUT_MOUNT, name));
+            }
+            manifest_lines.push(format!(
+                "{}|{}|{}\n",
+                index,
+                input_path,
+                argument_paths.join(" ")
+            ));
+        }
+
+        let parallelism = payload
+            .parallelism
+            .unwrap_or(1)
+            .clamp(1, MAX_BATCH_PARALLELISM.min(payload.cases.len().max(1)));
+        let chunk_size = payload.cases.len().div_ceil(parallelism).max(1);
+        let command = format!(
+            "./executor_script.sh '{}' '{}'",
+            payload.language,
+            payload.code.replace("'", "'\\''")
+        );
+
+        if let Some(ref interactor) = payload.interactor {
+            tokio::fs::write(format!("{}/code", job_dir.supervisor()), &interactor.code).await?;
+        }
+
+        let mut runs = Vec::new();
+        for (group, lines) in manifest_lines.chunks(chunk_size).enumerate() {
+            let manifest_name = format!("cases_{}", group);
+            tokio::fs::write(
+                format!("{}/{}", job_dir.input(), manifest_name),
+                lines.concat(),
+            )
+            .await?;
+
+            let job_name = format!("{}-{}", Self::job_name(&run_name), group);
+            let start = group * chunk_size;
+            tokio::fs::write(
+                format!("{}/{}", job_dir.supervisor(), manifest_name),
+                supervisor_manifest_lines[start..start + lines.len()].concat(),
+            )
+            .await?;
+
+            let env = Self::batch_env(payload, &manifest_name);
+            let supervisor_memory_limit_mb = match payload.interactor {
+                Some(_) => payload.memory_limit_mb,
+                None => Some(SUPERVISOR_MEMORY_LIMIT_MB),
+            };
+            let containers = vec![
+                Self::executor_container(
+                    image_name,
+                    command.clone(),
+                    json!(env),
+                    payload.memory_limit_mb,
+                    job_dir,
+                    true,
+                ),
+                Self::supervisor_container(
+                    interactor_image_name.unwrap_or(image_name),
+                    json!(Self::supervisor_env(payload, &manifest_name)),
+                    supervisor_memory_limit_mb,
+                    job_dir,
+                ),
+            ];
+            let job_spec = Self::job_spec(&job_name, owner, execution_id, containers)?;
+            let deadline = Self::batch_deadline(payload.time_limit_ms, lines.len());
+            runs.push(async move {
+                Self::run_job(
+                    client,
+                    jobs,
+                    &job_name,
+                    &job_spec,
+                    (owner, execution_id),
+                    &payload.language,
+                    deadline,
+                )
+                .await
+            });
+        }
+
+        let mut compile_error = None;
+        for logs in try_join_all(runs).await? {
+            let Some(JobLogs { error, .. }) = logs else {
+                return Err(Error::Cancelled(execution_id.to_string()));
+            };
+            if error.is_empty() || compile_error.is_some() {
+                continue;
+            }
+            match error.strip_suffix(COMPILE_ERROR_MESSAGE) {
+                Some(compile_output) => compile_error = Some(compile_output.trim().to_string()),
+                None => {
+                    return Err(Error::Infrastructure(format!(
+                        "Batch Job failed: {}",
+                        error
+                    )))
+                }
+            }
+        }
+
+        let artifacts = ArtifactStore::from_env();
+        let principal = current_principal();
+        let mut results = Vec::with_capacity(payload.cases.len());
+        let mut interactor_results = Vec::new();
+        for index in 0..payload.cases.len() {
+            let case_dir = format!("{}/{}", job_dir.output(), index);
+            let supervisor_case_dir = format!("{}/{}", job_dir.supervisor(), index);
+            let result = Self::read_case_result(
+                (&artifacts, &principal),
+                (&case_dir, &supervisor_case_dir),
+                &payload.output_extension,
+                &compile_error,
+            )
+            .await;
+            if let Some(time_ms) = result.time_ms {
+                metrics::RUN_TIME.observe(&[("language", &payload.language)], seconds(time_ms));
+            }
+            results.push(result);
+            if payload.interactor.is_some() {
+                interactor_results.push(Self::read_interactor_result(&supervisor_case_dir).await);
+            }
+        }
+
+        Ok(BatchResult {
+            execution_id: execution_id.to_string(),
+            status: if compile_error.is_some() {
+                ExecutionStatus::Failed
+            } else {
+                ExecutionStatus::Completed
+            },
+            compile_error,
+            results,
+            interactor_results,
         })
     }
-}
 
-impl K8sExecutor {
+    /// Reads the result of a case from the directory the program wrote its stderr and output
+    /// file to and the one the supervisor wrote the rest to.
+    async fn read_case_result(
+        (artifacts, principal): (&ArtifactStore, &str),
+        (case_dir, supervisor_case_dir): (&str, &str),
+        output_extension: &str,
+        compile_error: &Option<String>,
+    ) -> ExecutionResult {
+        let read = |path: String| async move { tokio::fs::read(path).await.ok() };
+
+        let Some(exit_code) = read(format!("{}/exit_code", supervisor_case_dir))
+            .await
+            .and_then(|code| String::from_utf8_lossy(&code).trim().parse::<i32>().ok())
+        else {
+            return ExecutionResult {
+                output: String::new(),
+                error: compile_error
+                    .clone()
+                    .unwrap_or_else(|| "Case was not executed".to_string()),
+                output_file_path: None,
+                output_file_content: None,
+                status: ExecutionStatus::Failed,
+                exit_code: None,
+                time_ms: None,
+                billed_ms: None,
+                output_file: None,
+                output_files: Vec::new(),
+                output_files_truncated: false,
+            };
+        };
+
+        let output_path = format!("{}/output{}", case_dir, output_extension.trim());
+        let output_file = match Self::store_output_file(artifacts, principal, &output_path).await {
+            Ok(output_file) => output_file,
+            Err(e) => {
+                error!("Failed to store output file {}: {:?}", output_path, e);
+                None
+            }
+        };
+        let text =
+            |data: Option<Vec<u8>>| String::from_utf8_lossy(&data.unwrap_or_default()).to_string();
+        ExecutionResult {
+            output: text(read(format!("{}/stdout", supervisor_case_dir)).await),
+            error: text(read(format!("{}/stderr", case_dir)).await),
+            output_file_path: None,
+            output_file_content: None,
+            status: if exit_code == 0 {
+                ExecutionStatus::Completed
+            } else {
+                ExecutionStatus::Failed
+            },
+            exit_code: Some(exit_code),
+            time_ms: read(format!("{}/time_ms", supervisor_case_dir))
+                .await
+                .and_then(|time| String::from_utf8_lossy(&time).trim().parse().ok()),
+            billed_ms: None,
+            output_file,
+            output_files: Vec::new(),
+            output_files_truncated: false,
+        }
+    }
+
+    async fn read_interactor_result(case_dir: &str) -> ExecutionResult {
+        let read = |name: &'static str| async move {
+            tokio::fs::read(format!("{}/{}", case_dir, name))
+                .await
+                .map(|data| String::from_utf8_lossy(&data).to_string())
+                .unwrap_or_default()
+        };
+
+        let exit_code = read("interactor_exit_code")
+            .await
+            .trim()
+            .parse::<i32>()
+            .ok();
+        ExecutionResult {
+            output: read("interactor_result").await,
+            error: read("interactor_stderr").await,
+            output_file_path: None,
+            output_file_content: None,
+            status: if exit_code == Some(0) {
+                ExecutionStatus::Completed
+            } else {
+                ExecutionStatus::Failed
+            },
+            exit_code,
+            time_ms: None,
+            billed_ms: None,
+            output_file: None,
+            output_files: Vec::new(),
+            output_files_truncated: false,
+        }
+    }
+
+    /// Stops the execution of the principal by deleting its Jobs and, in the foreground, their
+    /// pods. Returns `false` when no Job belongs to this execution.
+    pub async fn cancel(principal: &str, execution_id: &str) -> Result<bool> {
+        let owner = Self::owner(principal);
+        {
+            let mut cancelled = CANCELLED.lock().unwrap();
+            cancelled.retain(|(_, _, at)| at.elapsed() < CANCELLED_RETENTION);
+            cancelled.push((owner.clone(), execution_id.to_string(), Instant::now()));
+        }
+
+        let client = Client::try_default().await.map_err(api_error("connect"))?;
+        let jobs: Api<Job> = Api::default_namespaced(client);
+        let list_params = ListParams::default().labels(&format!(
+            "{}={},{}={}",
+            EXECUTION_ID_LABEL, execution_id, OWNER_LABEL, owner
+        ));
+
+        let job_names = jobs
+            .list(&list_params)
+            .await
+            .map_err(api_error("list_jobs"))?
+            .into_iter()
+            .filter_map(|job| job.metadata.name)
+            .collect::<Vec<_>>();
+        for job_name in &job_names {
+            match jobs.delete(job_name, &DeleteParams::foreground()).await {
+                Ok(_) => info!("Cancelled Job with name: {}", job_name),
+                Err(kube::Error::Api(response)) if response.code == 404 => (),
+                Err(e) => return Err(api_error("delete_job")(e)),
+            }
+        }
+
+        Ok(!job_names.is_empty())
+    }
+
+    fn was_cancelled(owner: &str, execution_id: &str) -> bool {
+        CANCELLED
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(cancelled_owner, id, at)| {
+                cancelled_owner == owner && id == execution_id && at.elapsed() < CANCELLED_RETENTION
+            })
+    }
+
+    /// State of the `container` of the pod once it terminated.
+    fn terminated<'a>(pod: &'a Pod, container: &str) -> Option<&'a ContainerStateTerminated> {
+        pod.status
+            .as_ref()?
+            .container_statuses
+            .as_ref()?
+            .iter()
+            .find(|status| status.name == container)?
+            .state
+            .as_ref()?
+            .terminated
+            .as_ref()
+    }
+
+    /// How long the `container` of the terminated pod ran, from the timestamps Kubernetes
+    /// records, to the second.
+    fn running_time(pod: &Pod, container: &str) -> Option<Duration> {
+        let terminated = Self::terminated(pod, container)?;
+        let running_time = terminated.finished_at.as_ref()?.0 - terminated.started_at.as_ref()?.0;
+        running_time.to_std().ok()
+    }
+
+    /// Whether the pods of the Job are being deleted, or are gone since one was `seen`, which
+    /// happens when the Job gets deleted from outside of this server.
+    fn pods_deleted(pods: &[Pod], seen: bool) -> bool {
+        if pods.is_empty() {
+            seen
+        } else {
+            pods.iter()
+                .all(|pod| pod.metadata.deletion_timestamp.is_some())
+        }
+    }
+
+    /// Waits for the pod of the Job to terminate and returns its [`JobLogs`], or `None` when the
+    /// Job got cancelled in the meantime. The Job is deleted once it runs past
+    /// `deadline`.
     async fn wait_for_pod_and_get_logs(
         client: &Client,
+        jobs: &Api<Job>,
         job_name: &str,
-    ) -> Result<(String, String), Box<dyn std::error::Error>> {
-        let pods: Api<k8s_openapi::api::core::v1::Pod> = Api::default_namespaced(client.clone());
+        (owner, execution_id): (&str, &str),
+        language: &str,
+        deadline: Duration,
+    ) -> Result<Option<JobLogs>> {
+        let pods: Api<Pod> = Api::default_namespaced(client.clone());
+        let list_params = ListParams::default().labels(&format!("job-name={}", job_name));
+        let created_at = Instant::now();
+        // Closed once the pod leaves the Pending phase.
+        let mut scheduling = Some(info_span!("pod_scheduling", job = job_name));
+
+        let mut seen = false;
 
-        for _ in 0..180 {
+        while created_at.elapsed() < deadline {
             let pod_list = pods
-                .list(&Default::default())
-                .await?
-                .into_iter()
-                .filter(|pod| {
-                    pod.metadata
-                        .labels
-                        .as_ref()
-                        .and_then(|labels| labels.get("job-name"))
-                        .map_or(false, |name| name == job_name)
-                })
-                .collect::<Vec<_>>();
+                .list(&list_params)
+                .await
+                .map_err(api_error("list_pods"))?
+                .items;
+            if Self::was_cancelled(owner, execution_id) || Self::pods_deleted(&pod_list, seen) {
+                return Ok(None);
+            }
+            seen |= !pod_list.is_empty();
 
             if let Some(pod) = pod_list.first() {
-                let pod_name = pod.metadata.name.as_ref().ok_or("Pod name not found")?;
-                let log_params = Default::default();
-                match pods.logs(pod_name, &log_params).await {
+                let phase = pod
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.phase.as_deref());
+                if scheduling.is_some() && phase.is_some_and(|phase| phase != "Pending") {
+                    scheduling = None;
+                    metrics::POD_SCHEDULING.observe(
+                        &[("language", language)],
+                        created_at.elapsed().as_secs_f64(),
+                    );
+                }
+                if !matches!(phase, Some("Succeeded") | Some("Failed")) {
+                    debug!("Pod is still running, retrying...");
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+
+                let pod_name = pod.metadata.name.as_ref().ok_or_else(|| {
+                    Error::Infrastructure(format!("Pod of Job {} has no name", job_name))
+                })?;
+                // The logs of the supervisor are not the program's.
+                let log_params = LogParams {
+                    container: Some("executor".to_string()),
+                    ..Default::default()
+                };
+                let logs = pods
+                    .logs(pod_name, &log_params)
+                    .instrument(info_span!("retrieve_logs", pod = pod_name))
+                    .await;
+                match logs {
                     Ok(logs) => {
+                        let running_time = Self::running_time(pod, "executor");
+                        let exit_code = Self::terminated(pod, "executor")
+                            .map(|terminated| terminated.exit_code);
                         if logs.contains(DEFAULT_ERROR_MESSAGE) {
-                            pods.delete(pod_name, &DeleteParams::default()).await?;
+                            pods.delete(pod_name, &DeleteParams::default())
+                                .await
+                                .map_err(api_error("delete_pod"))?;
                             info!("Deleted Pod with name: {}", pod_name);
-                            return Ok((
-                                String::new(),
-                                logs.replace(DEFAULT_ERROR_MESSAGE, "").trim().to_string(),
-                            ));
+                            return Ok(Some(JobLogs {
+                                output: String::new(),
+                                error: logs.replace(DEFAULT_ERROR_MESSAGE, "").trim().to_string(),
+                                running_time,
+                                exit_code,
+                            }));
                         }
-                        pods.delete(pod_name, &DeleteParams::default()).await?;
+                        pods.delete(pod_name, &DeleteParams::default())
+                            .await
+                            .map_err(api_error("delete_pod"))?;
                         info!("Deleted Pod with name: {}", pod_name);
-                        return Ok((logs.trim().to_string(), String::new()));
+                        return Ok(Some(JobLogs {
+                            output: logs.trim().to_string(),
+                            error: String::new(),
+                            running_time,
+                            exit_code,
+                        }));
                     }
                     Err(_) => {
                         debug!("Pod is not ready yet, retrying...");
@@ -223,15 +1256,15 @@ impl K8sExecutor {
             }
         }
 
-        Err("No pods found for the job".into())
+        jobs.delete(job_name, &DeleteParams::foreground())
+            .await
+            .map_err(api_error("delete_job"))?;
+        Err(Error::Timeout)
     }
 
-    async fn cleanup_job(
-        jobs: &Api<Job>,
-        job_name: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn cleanup_job(jobs: &Api<Job>, job_name: &str) -> Result<()> {
         for _ in 0..60 {
-            let job = jobs.get(job_name).await?;
+            let job = jobs.get(job_name).await.map_err(api_error("get_job"))?;
             if let Some(JobStatus { conditions, .. }) = job.status {
                 if let Some(condition) = conditions.as_ref().and_then(|conds| {
                     conds
@@ -242,7 +1275,9 @@ impl K8sExecutor {
                         || condition.status == "False"
                         || condition.status == "Unknown"
                     {
-                        jobs.delete(job_name, &DeleteParams::default()).await?;
+                        jobs.delete(job_name, &DeleteParams::default())
+                            .await
+                            .map_err(api_error("delete_job"))?;
                         info!("Deleted Job with name: {}", job_name);
                         return Ok(());
                     }
@@ -252,6 +1287,411 @@ impl K8sExecutor {
             sleep(Duration::from_secs(1)).await;
         }
 
-        Err("Job did not finish in the expected time".into())
+        Err(Error::Infrastructure(format!(
+            "Job {} did not finish in the expected time",
+            job_name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::Container;
+
+    /// `(mount path, sub path, read-only)` of the shared volume in the container.
+    fn shared_mounts(container: &Container) -> Vec<(&str, Option<&str>, Option<bool>)> {
+        container
+            .volume_mounts
+            .iter()
+            .flatten()
+            .filter(|mount| mount.name == "shared-volume")
+            .map(|mount| {
+                (
+                    mount.mount_path.as_str(),
+                    mount.sub_path.as_deref(),
+                    mount.read_only,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_job_only_mounts_its_directory() {
+        let job_dir = JobDir {
+            name: "e1".to_string(),
+            removed: false,
+        };
+        let job = K8sExecutor::job_spec(
+            "job-e1-0",
+            "owner",
+            "e1",
+            vec![
+                K8sExecutor::executor_container(
+                    "gcr.io/project/python:latest",
+                    "./executor_script.sh".to_string(),
+                    json!([]),
+                    None,
+                    &job_dir,
+                    true,
+                ),
+                K8sExecutor::supervisor_container(
+                    "gcr.io/project/rust:latest",
+                    json!([]),
+                    None,
+                    &job_dir,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let pod = job.spec.unwrap().template.spec.unwrap();
+        let [executor, supervisor] = &pod.containers[..] else {
+            panic!("expected two containers");
+        };
+        assert_eq!(
+            shared_mounts(executor),
+            [
+                (INPUT_MOUNT, Some("jobs/e1/input"), Some(true)),
+                (OUTPUT_MOUNT, Some("jobs/e1/output"), Some(false)),
+            ]
+        );
+        // The inputs and the results of the cases are out of reach of the program.
+        assert_eq!(
+            shared_mounts(supervisor),
+            [(SUPERVISOR_MOUNT, Some("jobs/e1/supervisor"), Some(false))]
+        );
+        let user = |container: &Container| {
+            container
+                .security_context
+                .as_ref()
+                .and_then(|context| context.run_as_user)
+        };
+        assert_eq!(user(executor), Some(1000));
+        assert_eq!(user(supervisor), Some(1001));
+        assert_eq!(
+            supervisor.image.as_deref(),
+            Some("gcr.io/project/rust:latest")
+        );
+
+        let volumes = pod
+            .volumes
+            .unwrap()
+            .into_iter()
+            .map(|volume| (volume.name, volume.empty_dir.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            volumes,
+            [
+                ("shared-volume".to_string(), false),
+                ("executor-sandbox".to_string(), true),
+                ("pipes".to_string(), true),
+                ("supervisor-sandbox".to_string(), true),
+            ]
+        );
+        assert_eq!(job_dir.supervisor(), "/mnt/shared/jobs/e1/supervisor");
+    }
+
+    #[test]
+    fn test_jobs_are_namespaced_by_owner() {
+        let (alice, bob) = (K8sExecutor::owner("alice"), K8sExecutor::owner("bob"));
+        assert_eq!(alice.len(), 32);
+        assert_ne!(alice, bob);
+
+        // The same execution id chosen by two principals names different Jobs and directories.
+        let execution_id = "0d0e8a47-3c1f-4b7e-9f0a-6c2d5e8b1a3f";
+        let alice_run = K8sExecutor::run_name(&alice, execution_id);
+        let bob_run = K8sExecutor::run_name(&bob, execution_id);
+        assert_ne!(alice_run, bob_run);
+        assert_ne!(
+            K8sExecutor::job_name(&alice_run),
+            K8sExecutor::job_name(&bob_run)
+        );
+
+        // Batch Jobs of a stage of a long stress test still fit in the `job-name` label.
+        let stage_run = K8sExecutor::run_name(&alice, &format!("{}-brute1000", execution_id));
+        assert!(format!("{}-{}", K8sExecutor::job_name(&stage_run), 99).len() <= 63);
+
+        let job = K8sExecutor::job_spec("job-e1", &alice, execution_id, vec![]).unwrap();
+        let labels = job.metadata.labels.unwrap();
+        assert_eq!(labels[OWNER_LABEL], alice);
+        assert_eq!(labels[EXECUTION_ID_LABEL], execution_id);
+    }
+
+    #[test]
+    fn test_cancellation_is_seen_from_the_pods() {
+        let pod = |deleted: bool| {
+            let mut pod = Pod::default();
+            if deleted {
+                pod.metadata.deletion_timestamp =
+                    Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(
+                        k8s_openapi::chrono::Utc::now(),
+                    ));
+            }
+            pod
+        };
+
+        // No pod yet, the Job may still be scheduling.
+        assert!(!K8sExecutor::pods_deleted(&[], false));
+        assert!(!K8sExecutor::pods_deleted(&[pod(false)], false));
+        assert!(!K8sExecutor::pods_deleted(&[pod(true), pod(false)], true));
+        assert!(K8sExecutor::pods_deleted(&[pod(true)], false));
+        assert!(K8sExecutor::pods_deleted(&[], true));
+
+        let owner = K8sExecutor::owner("alice");
+        assert!(!K8sExecutor::was_cancelled(&owner, "e-cancelled"));
+        CANCELLED
+            .lock()
+            .unwrap()
+            .push((owner.clone(), "e-cancelled".to_string(), Instant::now()));
+        assert!(K8sExecutor::was_cancelled(&owner, "e-cancelled"));
+        assert!(!K8sExecutor::was_cancelled(
+            &K8sExecutor::owner("bob"),
+            "e-cancelled"
+        ));
+    }
+
+    #[test]
+    fn test_running_time_and_exit_code_come_from_the_pod_status() {
+        let pod: Pod = serde_json::from_value(json!({
+            "status": {
+                "containerStatuses": [
+                    {
+                        "name": "supervisor",
+                        "image": "", "imageID": "", "ready": false, "restartCount": 0,
+                        "state": { "terminated": {
+                            "exitCode": 0,
+                            "startedAt": "2024-05-01T10:00:00Z",
+                            "finishedAt": "2024-05-01T10:01:00Z"
+                        } }
+                    },
+                    {
+                        "name": "executor",
+                        "image": "", "imageID": "", "ready": false, "restartCount": 0,
+                        "state": { "terminated": {
+                            "exitCode": 1,
+                            "startedAt": "2024-05-01T10:00:02Z",
+                            "finishedAt": "2024-05-01T10:00:09Z"
+                        } }
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            K8sExecutor::running_time(&pod, "executor"),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(K8sExecutor::running_time(&Pod::default(), "executor"), None);
+        assert_eq!(
+            K8sExecutor::terminated(&pod, "executor").map(|terminated| terminated.exit_code),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_batch_cases_always_have_a_time_limit() {
+        let mut payload = BatchPayload {
+            language: "python".to_string(),
+            code: "while True: pass".to_string(),
+            output_extension: ".txt".to_string(),
+            cases: Vec::new(),
+            parallelism: None,
+            time_limit_ms: None,
+            memory_limit_mb: None,
+            execution_id: None,
+            tags: Vec::new(),
+            interactor: None,
+            stage: None,
+        };
+        let time_limit = |env: &[serde_json::Value]| {
+            env.iter()
+                .find(|var| var["name"] == "TIME_LIMIT_MS")
+                .map(|var| var["value"].as_str().unwrap().to_string())
+        };
+
+        let has = |env: &[serde_json::Value], name: &str| env.iter().any(|var| var["name"] == name);
+
+        let env = K8sExecutor::batch_env(&payload, "cases_0");
+        assert_eq!(time_limit(&env), Some(MAX_TIME_LIMIT_MS.to_string()));
+        assert_eq!(env[0]["value"], format!("{}/cases_0", INPUT_MOUNT));
+        assert!(has(&env, "PIPES_DIR"));
+        let supervisor_env = K8sExecutor::supervisor_env(&payload, "cases_0");
+        assert_eq!(
+            time_limit(&supervisor_env),
+            Some(MAX_TIME_LIMIT_MS.to_string())
+        );
+        assert!(!has(&supervisor_env, "INTERACTOR_CODE_PATH"));
+
+        payload.time_limit_ms = Some(1500);
+        payload.interactor = Some(crate::types::Program {
+            language: "rust".to_string(),
+            code: "fn main() {}".to_string(),
+        });
+        let env = K8sExecutor::batch_env(&payload, "cases_1");
+        assert_eq!(time_limit(&env), Some("1500".to_string()));
+        let supervisor_env = K8sExecutor::supervisor_env(&payload, "cases_1");
+        assert_eq!(time_limit(&supervisor_env), Some("1500".to_string()));
+        assert_eq!(
+            supervisor_env[0]["value"],
+            format!("{}/cases_1", SUPERVISOR_MOUNT)
+        );
+        assert!(has(&supervisor_env, "INTERACTOR_CODE_PATH"));
+    }
+
+    #[test]
+    fn test_batch_deadline_covers_every_case() {
+        // A pod runs its cases one after the other, each up to its limit.
+        assert_eq!(
+            K8sExecutor::batch_deadline(None, 10),
+            JOB_STARTUP_SLACK + Duration::from_secs(10 * 31)
+        );
+        assert_eq!(
+            K8sExecutor::batch_deadline(Some(500), 4),
+            JOB_STARTUP_SLACK + Duration::from_secs(6)
+        );
+    }
+
+    /// Runs the executor script of a batch and its supervisor in `dir` the way the containers of
+    /// its Job do, a case per stdin, the program starting `delay` after the supervisor.
+    fn run_batch_scripts(
+        dir: &std::path::Path,
+        program: &str,
+        interactor: Option<&str>,
+        stdins: &[&str],
+        delay: Duration,
+    ) {
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        for name in ["input", "output", "supervisor", "pipes"] {
+            std::fs::create_dir(path(name)).unwrap();
+        }
+        let mut manifest = String::new();
+        let mut supervisor_manifest = String::new();
+        for (case, stdin) in stdins.iter().enumerate() {
+            let stdin_path = format!("{}/{}.stdin", path("supervisor"), case);
+            std::fs::write(&stdin_path, stdin).unwrap();
+            manifest.push_str(&format!("{}||\n", case));
+            supervisor_manifest.push_str(&format!("{}|{}\n", case, stdin_path));
+        }
+        std::fs::write(format!("{}/cases_0", path("input")), manifest).unwrap();
+        std::fs::write(
+            format!("{}/cases_0", path("supervisor")),
+            supervisor_manifest,
+        )
+        .unwrap();
+        let script = |env: Vec<(&str, String)>| {
+            std::process::Command::new("bash")
+                .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/executor_script.sh"))
+                .args(["python", program])
+                .envs(env)
+                .env("PIPES_DIR", path("pipes"))
+                .env("TIME_LIMIT_MS", "1000")
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .unwrap()
+        };
+
+        let mut supervisor_env = vec![
+            (
+                "SUPERVISOR_MANIFEST",
+                format!("{}/cases_0", path("supervisor")),
+            ),
+            ("SUPERVISOR_OUTPUT_DIR", path("supervisor")),
+            ("SANDBOX_DIR", path("supervisor-sandbox")),
+        ];
+        if let Some(interactor) = interactor {
+            std::fs::write(format!("{}/code", path("supervisor")), interactor).unwrap();
+            supervisor_env.push(("INTERACTOR_LANGUAGE", "python".to_string()));
+            supervisor_env.push((
+                "INTERACTOR_CODE_PATH",
+                format!("{}/code", path("supervisor")),
+            ));
+        }
+        let mut supervisor = script(supervisor_env);
+        std::thread::sleep(delay);
+        let mut program = script(vec![
+            ("BATCH_MANIFEST", format!("{}/cases_0", path("input"))),
+            ("BATCH_OUTPUT_DIR", path("output")),
+            ("OUTPUT_EXTENSION", ".txt".to_string()),
+            ("SANDBOX_DIR", path("program-sandbox")),
+        ]);
+        assert!(program.wait().unwrap().success());
+        assert!(supervisor.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_interactor_waits_for_a_slow_submission() {
+        let dir = tempfile::tempdir().unwrap();
+        // The submission is still compiling, or its image being pulled, for longer than the time
+        // limit of a case.
+        run_batch_scripts(
+            dir.path(),
+            "input(); print('pong')",
+            Some(
+                "import sys\n\
+                 print('ping', flush=True)\n\
+                 answer = sys.stdin.readline().strip()\n\
+                 open(sys.argv[2], 'w').write('ok' if answer == 'pong' else answer)\n",
+            ),
+            &["", ""],
+            Duration::from_secs(2),
+        );
+
+        let read = |file: &str| std::fs::read_to_string(dir.path().join(file)).unwrap();
+        for case in ["0", "1"] {
+            assert_eq!(
+                read(&format!("supervisor/{}/interactor_result", case)),
+                "ok"
+            );
+            assert_eq!(
+                read(&format!("supervisor/{}/interactor_exit_code", case)).trim(),
+                "0"
+            );
+            assert_eq!(read(&format!("supervisor/{}/exit_code", case)).trim(), "0");
+        }
+    }
+
+    #[test]
+    fn test_supervisor_records_the_results() {
+        let dir = tempfile::tempdir().unwrap();
+        run_batch_scripts(
+            dir.path(),
+            "import sys, time\n\
+             line = input()\n\
+             if line == 'crash': sys.exit(3)\n\
+             if line == 'loop': time.sleep(5)\n\
+             print(line.upper())\n",
+            None,
+            &["ping\n", "crash\n", "loop\n"],
+            Duration::ZERO,
+        );
+
+        let read = |file: &str| std::fs::read_to_string(dir.path().join(file)).ok();
+        let result = |case: usize| {
+            (
+                read(&format!("supervisor/{}/stdout", case)).unwrap(),
+                read(&format!("supervisor/{}/exit_code", case))
+                    .unwrap()
+                    .trim()
+                    .to_string(),
+                read(&format!("supervisor/{}/time_ms", case))
+                    .unwrap()
+                    .trim()
+                    .parse::<u64>()
+                    .unwrap(),
+            )
+        };
+        let (stdout, exit_code, time_ms) = result(0);
+        assert_eq!((stdout.as_str(), exit_code.as_str()), ("PING\n", "0"));
+        assert!(time_ms < 1000);
+        assert_eq!(result(1).1, "3");
+        let (_, exit_code, time_ms) = result(2);
+        assert_eq!(exit_code, "124");
+        assert!(time_ms >= 1000);
+
+        // Nothing but the stderr and the output file of the cases comes from the program.
+        for file in ["stdout", "exit_code", "time_ms"] {
+            assert_eq!(read(&format!("output/0/{}", file)), None);
+        }
     }
 }
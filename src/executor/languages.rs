@@ -0,0 +1,103 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::types::DEFAULT_OUTPUT_EXTENSION;
+
+/// Language the executor images can run. `executor_script.sh` and the Dockerfiles of
+/// `languages/` must be kept in sync with [`LANGUAGES`].
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Language {
+    /// Value of the `language` field of the requests.
+    pub name: &'static str,
+    #[schema(value_type = Vec<String>)]
+    pub versions: &'static [&'static str],
+    pub toolchain: &'static str,
+    pub default_output_extension: &'static str,
+    /// Variables holding the path of the input file, of the output file to write and of the
+    /// directory any other output file can be written to.
+    pub input_path_variable: &'static str,
+    pub output_path_variable: &'static str,
+    pub output_dir_variable: &'static str,
+    pub template: &'static str,
+    /// Name of the executor image, without its registry.
+    #[serde(skip)]
+    pub image: &'static str,
+}
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        name: "python",
+        versions: &["3.9"],
+        toolchain: "Python 3.9",
+        default_output_extension: DEFAULT_OUTPUT_EXTENSION,
+        input_path_variable: "INPUT_PATH",
+        output_path_variable: "OUTPUT_PATH",
+        output_dir_variable: "OUTPUT_DIR",
+        template: "print(\"Hello, world!\")\n",
+        image: "executor-python",
+    },
+    Language {
+        name: "javascript",
+        versions: &["20"],
+        toolchain: "Node.js 20",
+        default_output_extension: DEFAULT_OUTPUT_EXTENSION,
+        input_path_variable: "INPUT_PATH",
+        output_path_variable: "OUTPUT_PATH",
+        output_dir_variable: "OUTPUT_DIR",
+        template: "console.log(\"Hello, world!\");\n",
+        image: "executor-nodejs",
+    },
+    Language {
+        name: "lua",
+        versions: &["5.3"],
+        toolchain: "Lua 5.3",
+        default_output_extension: DEFAULT_OUTPUT_EXTENSION,
+        input_path_variable: "INPUT_PATH",
+        output_path_variable: "OUTPUT_PATH",
+        output_dir_variable: "OUTPUT_DIR",
+        template: "print(\"Hello, world!\")\n",
+        image: "executor-lua",
+    },
+    Language {
+        name: "rust",
+        versions: &["1.77"],
+        toolchain: "rustc 1.77 (edition 2015)",
+        default_output_extension: DEFAULT_OUTPUT_EXTENSION,
+        input_path_variable: "INPUT_PATH",
+        output_path_variable: "OUTPUT_PATH",
+        output_dir_variable: "OUTPUT_DIR",
+        template: "fn main() {\n    println!(\"Hello, world!\");\n}\n",
+        image: "executor-rust",
+    },
+];
+
+impl Language {
+    pub fn find(name: &str) -> Option<&'static Language> {
+        LANGUAGES.iter().find(|language| language.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_language() {
+        assert_eq!(Language::find("rust").unwrap().image, "executor-rust");
+        assert_eq!(
+            Language::find("javascript").unwrap().image,
+            "executor-nodejs"
+        );
+        assert!(Language::find("cobol").is_none());
+
+        for language in LANGUAGES {
+            assert_eq!(
+                LANGUAGES
+                    .iter()
+                    .filter(|other| other.name == language.name)
+                    .count(),
+                1
+            );
+        }
+    }
+}
@@ -1,5 +1,19 @@
 mod k8s_executor;
-pub use k8s_executor::K8sExecutor;
+mod languages;
+mod outputs;
+pub mod queue;
+pub mod readiness;
+pub use k8s_executor::{
+    K8sExecutor,
+    MAX_BATCH_CASES,
+    MAX_BATCH_PARALLELISM,
+    MAX_MEMORY_LIMIT_MB,
+    MAX_TIME_LIMIT_MS,
+};
+pub use languages::{
+    Language,
+    LANGUAGES,
+};
 mod simple_executor;
 pub use simple_executor::SimpleExecutor;
 
@@ -7,5 +21,11 @@ pub use simple_executor::SimpleExecutor;
 pub trait CodeExecutor {
     async fn execute(
         payload: &super::types::ExecutionPayload,
-    ) -> Result<super::types::ExecutionResult, Box<dyn std::error::Error>>;
+    ) -> crate::error::Result<super::types::ExecutionResult>;
+
+    /// Runs the same program against every case, compiling it only once per pod. A cancelled
+    /// batch fails with [`crate::error::Error::Cancelled`].
+    async fn execute_batch(
+        payload: &super::types::BatchPayload,
+    ) -> crate::error::Result<super::types::BatchResult>;
 }
@@ -0,0 +1,141 @@
+use std::{
+    io,
+    path::Path,
+};
+
+use crate::{
+    artifacts::ArtifactStore,
+    types::OutputFile,
+};
+
+/// Files of an execution stored at most, the others being left out of the manifest.
+pub const MAX_OUTPUT_FILES: usize = 100;
+/// Total size of the files of an execution stored at most.
+pub const MAX_OUTPUT_FILES_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Stores the files under `dir` as artifacts of the owner and returns their manifest, sorted by
+/// name. A
+/// missing directory has no files. Only the first [`MAX_OUTPUT_FILES`] files fitting together
+/// in [`MAX_OUTPUT_FILES_BYTES`] are stored, the returned flag telling whether any was left out.
+pub async fn collect_output_files(
+    dir: &Path,
+    store: &ArtifactStore,
+    owner: &str,
+) -> io::Result<(Vec<OutputFile>, bool)> {
+    let mut paths = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&current).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            // Symlinks are skipped, they could point outside of the directory.
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                paths.push(entry.path());
+            }
+        }
+    }
+    paths.sort();
+
+    let mut files = Vec::with_capacity(paths.len().min(MAX_OUTPUT_FILES));
+    let mut total_size = 0;
+    for path in &paths {
+        let size = tokio::fs::metadata(path).await?.len();
+        if files.len() == MAX_OUTPUT_FILES || total_size + size > MAX_OUTPUT_FILES_BYTES {
+            return Ok((files, true));
+        }
+        total_size += size;
+        files.push(store.store(owner, path, &relative_name(dir, path)).await?);
+    }
+    Ok((files, false))
+}
+
+fn relative_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_collect_output_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("output");
+        std::fs::create_dir_all(output_dir.join("plots")).unwrap();
+        std::fs::write(output_dir.join("report.csv"), "a,b\n1,2\n").unwrap();
+        std::fs::write(output_dir.join("plots/sine.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        std::fs::write(output_dir.join("hello"), "hello").unwrap();
+
+        let store = ArtifactStore::new(dir.path().join("artifacts"), Duration::from_secs(60));
+        let (files, truncated) = collect_output_files(&output_dir, &store, "alice")
+            .await
+            .unwrap();
+        assert!(!truncated);
+        let names = files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["hello", "plots/sine.png", "report.csv"]);
+
+        assert_eq!(files[0].size, 5);
+        assert_eq!(files[0].mime_type, "application/octet-stream");
+        assert_eq!(files[1].mime_type, "image/png");
+        assert_eq!(files[2].mime_type, "text/csv");
+        let (_, path) = store
+            .open("alice", &files[2].artifact_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "a,b\n1,2\n");
+
+        let (missing, _) = collect_output_files(&dir.path().join("missing"), &store, "alice")
+            .await
+            .unwrap();
+        assert!(missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_collect_output_files_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::new(dir.path().join("artifacts"), Duration::from_secs(60));
+
+        let many_dir = dir.path().join("many");
+        std::fs::create_dir_all(&many_dir).unwrap();
+        for i in 0..=MAX_OUTPUT_FILES {
+            std::fs::write(many_dir.join(format!("{:04}", i)), i.to_string()).unwrap();
+        }
+        let (files, truncated) = collect_output_files(&many_dir, &store, "alice")
+            .await
+            .unwrap();
+        assert!(truncated);
+        assert_eq!(files.len(), MAX_OUTPUT_FILES);
+        assert_eq!(
+            files.last().unwrap().name,
+            format!("{:04}", MAX_OUTPUT_FILES - 1)
+        );
+
+        let large_dir = dir.path().join("large");
+        std::fs::create_dir_all(&large_dir).unwrap();
+        std::fs::write(large_dir.join("a"), "small").unwrap();
+        let large = std::fs::File::create(large_dir.join("b")).unwrap();
+        large.set_len(MAX_OUTPUT_FILES_BYTES).unwrap();
+        let (files, truncated) = collect_output_files(&large_dir, &store, "alice")
+            .await
+            .unwrap();
+        assert!(truncated);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "a");
+    }
+}
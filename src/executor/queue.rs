@@ -0,0 +1,458 @@
+use log::info;
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    env,
+    future::Future,
+    sync::{
+        Mutex,
+        MutexGuard,
+        OnceLock,
+    },
+    time::Instant,
+};
+use tokio::sync::oneshot;
+
+use crate::{
+    error::{
+        Error,
+        Result,
+    },
+    metrics,
+    types::{
+        JobInfo,
+        JobState,
+    },
+};
+
+const DEFAULT_MAX_CONCURRENT_JOBS: usize = 20;
+const DEFAULT_MAX_QUEUED_JOBS: usize = 100;
+const ANONYMOUS: &str = "anonymous";
+
+tokio::task_local! {
+    static PRINCIPAL: String;
+}
+
+/// Runs the future on behalf of the principal, whose Jobs are then queued fairly against the
+/// ones of the other principals.
+pub async fn with_principal<F: Future>(principal: String, future: F) -> F::Output {
+    PRINCIPAL.scope(principal, future).await
+}
+
+pub(crate) fn current_principal() -> String {
+    PRINCIPAL
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| ANONYMOUS.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// Jobs running at once over every language.
+    pub max_running: usize,
+    /// Jobs running at once for the listed languages, the others only being capped by
+    /// `max_running`.
+    pub max_running_per_language: HashMap<String, usize>,
+    /// Jobs waiting for a slot before new ones are rejected.
+    pub max_waiting: usize,
+    /// Share of the slots each principal gets when several are waiting, 1 when not listed.
+    pub weights: HashMap<String, u32>,
+}
+
+impl QueueConfig {
+    /// Reads `MAX_CONCURRENT_JOBS`, `MAX_QUEUED_JOBS` and the `name=number` lists of
+    /// `LANGUAGE_CONCURRENCY` and `QUEUE_WEIGHTS`.
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        QueueConfig {
+            max_running: number("MAX_CONCURRENT_JOBS", DEFAULT_MAX_CONCURRENT_JOBS),
+            max_running_per_language: pairs("LANGUAGE_CONCURRENCY"),
+            max_waiting: number("MAX_QUEUED_JOBS", DEFAULT_MAX_QUEUED_JOBS),
+            weights: pairs("QUEUE_WEIGHTS"),
+        }
+    }
+}
+
+fn pairs<T: std::str::FromStr>(name: &str) -> HashMap<String, T> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+struct Waiting {
+    ticket: u64,
+    /// Principal the Job runs for, execution ids being only unique per principal.
+    principal: String,
+    execution_id: String,
+    language: String,
+    /// Virtual time the Job is due at, the waiting Job with the lowest one being run first.
+    tag: f64,
+    /// Sent `true` when the Job may run and `false` when it is cancelled.
+    ready: oneshot::Sender<bool>,
+}
+
+struct Running {
+    principal: String,
+    execution_id: String,
+    language: String,
+}
+
+#[derive(Default)]
+struct State {
+    next_ticket: u64,
+    running: HashMap<u64, Running>,
+    waiting: Vec<Waiting>,
+    /// Granted Jobs whose waiter has not picked up its slot yet.
+    granted: HashSet<u64>,
+    virtual_time: f64,
+    /// Tag of the last Job queued by each principal.
+    last_tags: HashMap<String, f64>,
+}
+
+/// Caps the Jobs running at once, globally and per language, holding the others back in a
+/// bounded queue. Waiting Jobs are ordered by start-time fair queuing: each Job of a principal
+/// is due `1 / weight` after the previous one, so that a principal queuing many Jobs does not
+/// delay the Jobs of the others.
+pub struct ExecutionQueue {
+    config: QueueConfig,
+    state: Mutex<State>,
+}
+
+/// Permission to run a Job, given back to the queue when dropped.
+pub struct Slot<'a> {
+    queue: &'a ExecutionQueue,
+    ticket: u64,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.queue.release(self.ticket);
+    }
+}
+
+/// Leaves the queue, or gives back a slot granted in the meantime, if the waiting future is
+/// dropped, which is what happens when the client goes away.
+struct Waiter<'a> {
+    queue: &'a ExecutionQueue,
+    ticket: u64,
+    armed: bool,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let mut state = self.queue.lock();
+        if let Some(index) = state.waiting.iter().position(|w| w.ticket == self.ticket) {
+            state.waiting.remove(index);
+        } else if state.granted.remove(&self.ticket) {
+            state.running.remove(&self.ticket);
+            self.queue.dispatch(&mut state);
+        }
+    }
+}
+
+impl ExecutionQueue {
+    pub fn new(config: QueueConfig) -> Self {
+        ExecutionQueue {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Queue shared by every request, configured from the environment on first use.
+    pub fn global() -> &'static Self {
+        static QUEUE: OnceLock<ExecutionQueue> = OnceLock::new();
+        QUEUE.get_or_init(|| Self::new(QueueConfig::from_env()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for a slot to run a Job of the execution on behalf of the current principal.
+    /// Returns `None` when the execution is cancelled while waiting, and fails right away when
+    /// the queue is full.
+    pub async fn acquire(&self, execution_id: &str, language: &str) -> Result<Option<Slot<'_>>> {
+        let start = Instant::now();
+        let slot = self
+            .acquire_for(&current_principal(), execution_id, language)
+            .await?;
+        if slot.is_some() {
+            metrics::QUEUE_WAIT.observe(&[("language", language)], start.elapsed().as_secs_f64());
+        }
+        Ok(slot)
+    }
+
+    async fn acquire_for(
+        &self,
+        principal: &str,
+        execution_id: &str,
+        language: &str,
+    ) -> Result<Option<Slot<'_>>> {
+        let (ticket, ready) = {
+            let mut state = self.lock();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+
+            if self.has_capacity(&state, language) {
+                state.running.insert(
+                    ticket,
+                    Running {
+                        principal: principal.to_string(),
+                        execution_id: execution_id.to_string(),
+                        language: language.to_string(),
+                    },
+                );
+                return Ok(Some(Slot {
+                    queue: self,
+                    ticket,
+                }));
+            }
+            if state.waiting.len() >= self.config.max_waiting {
+                return Err(Error::QueueFull(format!(
+                    "The execution queue is full with {} jobs waiting",
+                    state.waiting.len()
+                )));
+            }
+
+            let weight = self.config.weights.get(principal).copied().unwrap_or(1);
+            let start = state
+                .last_tags
+                .get(principal)
+                .copied()
+                .unwrap_or_default()
+                .max(state.virtual_time);
+            let tag = start + 1.0 / weight.max(1) as f64;
+            state.last_tags.insert(principal.to_string(), tag);
+
+            let (sender, ready) = oneshot::channel();
+            state.waiting.push(Waiting {
+                ticket,
+                principal: principal.to_string(),
+                execution_id: execution_id.to_string(),
+                language: language.to_string(),
+                tag,
+                ready: sender,
+            });
+            info!(
+                "Job of execution {} queued at position {}",
+                execution_id,
+                Self::position(&state, tag, ticket)
+            );
+            (ticket, ready)
+        };
+
+        let mut waiter = Waiter {
+            queue: self,
+            ticket,
+            armed: true,
+        };
+        let granted = ready.await.unwrap_or(false);
+        waiter.armed = false;
+        if granted {
+            self.lock().granted.remove(&ticket);
+            Ok(Some(Slot {
+                queue: self,
+                ticket,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn has_capacity(&self, state: &State, language: &str) -> bool {
+        if state.running.len() >= self.config.max_running {
+            return false;
+        }
+        match self.config.max_running_per_language.get(language) {
+            Some(&max) => {
+                state
+                    .running
+                    .values()
+                    .filter(|running| running.language == language)
+                    .count()
+                    < max
+            }
+            None => true,
+        }
+    }
+
+    fn release(&self, ticket: u64) {
+        let mut state = self.lock();
+        state.running.remove(&ticket);
+        self.dispatch(&mut state);
+    }
+
+    /// Grants slots to the waiting Jobs with the lowest tags, skipping the ones whose language
+    /// is at its cap.
+    fn dispatch(&self, state: &mut State) {
+        loop {
+            let next = state
+                .waiting
+                .iter()
+                .enumerate()
+                .filter(|(_, waiting)| self.has_capacity(state, &waiting.language))
+                .min_by(|(_, a), (_, b)| a.tag.total_cmp(&b.tag))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                break;
+            };
+
+            let waiting = state.waiting.remove(index);
+            state.virtual_time = state.virtual_time.max(waiting.tag);
+            if waiting.ready.send(true).is_ok() {
+                state.running.insert(
+                    waiting.ticket,
+                    Running {
+                        principal: waiting.principal,
+                        execution_id: waiting.execution_id,
+                        language: waiting.language,
+                    },
+                );
+                state.granted.insert(waiting.ticket);
+            }
+        }
+
+        // Tags behind the virtual time no longer delay anyone.
+        let virtual_time = state.virtual_time;
+        state.last_tags.retain(|_, tag| *tag > virtual_time);
+    }
+
+    /// Position, starting at 1, of a waiting Job, Jobs with the same tag running in the order
+    /// they were queued.
+    fn position(state: &State, tag: f64, ticket: u64) -> usize {
+        state
+            .waiting
+            .iter()
+            .filter(|waiting| (waiting.tag, waiting.ticket) < (tag, ticket))
+            .count()
+            + 1
+    }
+
+    /// Removes the waiting Jobs of the execution of the principal, returning whether there were
+    /// any.
+    pub fn cancel(&self, principal: &str, execution_id: &str) -> bool {
+        let mut state = self.lock();
+        let (cancelled, waiting) = std::mem::take(&mut state.waiting)
+            .into_iter()
+            .partition::<Vec<_>, _>(|waiting| {
+                waiting.principal == principal && waiting.execution_id == execution_id
+            });
+        state.waiting = waiting;
+        for waiting in &cancelled {
+            info!("Cancelled queued Job of execution {}", waiting.execution_id);
+        }
+        let found = !cancelled.is_empty();
+        for waiting in cancelled {
+            let _ = waiting.ready.send(false);
+        }
+        found
+    }
+
+    /// Whether the Jobs of the execution of the principal are running or, if none is, the
+    /// position of the first one in the queue.
+    pub fn job(&self, principal: &str, execution_id: &str) -> Option<JobInfo> {
+        let state = self.lock();
+        if state
+            .running
+            .values()
+            .any(|running| running.principal == principal && running.execution_id == execution_id)
+        {
+            return Some(JobInfo {
+                execution_id: execution_id.to_string(),
+                state: JobState::Running,
+                position: None,
+            });
+        }
+
+        state
+            .waiting
+            .iter()
+            .filter(|waiting| {
+                waiting.principal == principal && waiting.execution_id == execution_id
+            })
+            .map(|waiting| Self::position(&state, waiting.tag, waiting.ticket))
+            .min()
+            .map(|position| JobInfo {
+                execution_id: execution_id.to_string(),
+                state: JobState::Queued,
+                position: Some(position),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::Arc,
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn test_fair_order_and_backpressure() {
+        let queue: &'static ExecutionQueue =
+            Box::leak(Box::new(ExecutionQueue::new(QueueConfig {
+                max_running: 1,
+                max_running_per_language: HashMap::new(),
+                max_waiting: 3,
+                weights: HashMap::new(),
+            })));
+        let first = queue.acquire_for("a", "e0", "python").await.unwrap();
+        assert!(first.is_some());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (principal, execution_id) in [("a", "e1"), ("a", "e2"), ("b", "e3")] {
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let slot = queue
+                    .acquire_for(principal, execution_id, "python")
+                    .await
+                    .unwrap();
+                if slot.is_some() {
+                    order.lock().unwrap().push(execution_id);
+                }
+                slot.is_some()
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(queue.job("a", "e0").unwrap().state, JobState::Running);
+        // The Job of `b` goes before the second one of `a`.
+        assert_eq!(queue.job("b", "e3").unwrap().position, Some(2));
+        assert_eq!(queue.job("a", "e2").unwrap().position, Some(3));
+        // Execution ids are only looked up among the ones of the principal.
+        assert!(queue.job("b", "e0").is_none());
+        assert!(!queue.cancel("b", "e2"));
+        assert!(matches!(
+            queue.acquire_for("c", "e4", "python").await,
+            Err(Error::QueueFull(_))
+        ));
+
+        assert!(queue.cancel("a", "e2"));
+        assert!(!queue.cancel("a", "e2"));
+        drop(first);
+        let mut granted = Vec::new();
+        for task in tasks {
+            granted.push(task.await.unwrap());
+        }
+        assert_eq!(granted, [true, false, true]);
+        assert_eq!(*order.lock().unwrap(), ["e1", "e3"]);
+        assert!(queue.job("a", "e1").is_none());
+    }
+}
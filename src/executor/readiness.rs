@@ -0,0 +1,386 @@
+use futures_util::future::join_all;
+use k8s_openapi::api::{
+    apps::v1::DaemonSet,
+    authorization::v1::{
+        ResourceAttributes,
+        SelfSubjectAccessReview,
+        SelfSubjectAccessReviewSpec,
+    },
+    core::v1::Pod,
+};
+use kube::{
+    api::{
+        ListParams,
+        PostParams,
+    },
+    Api,
+    Client,
+};
+use std::{
+    future::Future,
+    path::Path,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use super::{
+    K8sExecutor,
+    LANGUAGES,
+};
+use crate::types::{
+    ReadinessCheck,
+    ReadinessReport,
+};
+
+/// Each check fails once this is over, so that a hanging dependency does not hang the probe.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reasons a container waits for when its image cannot be pulled.
+const IMAGE_PULL_FAILURES: [&str; 3] = ["ErrImagePull", "ImagePullBackOff", "InvalidImageName"];
+/// DaemonSet of `executor-images.yaml`, pulling the executor images on every node.
+const IMAGES_DAEMON_SET: &str = "dyno-code-executor-images";
+
+/// `(group, resource, subresource, verb)` the executor needs in its namespace.
+const REQUIRED_PERMISSIONS: [(&str, &str, &str, &str); 9] = [
+    ("batch", "jobs", "", "create"),
+    ("batch", "jobs", "", "get"),
+    ("batch", "jobs", "", "list"),
+    ("batch", "jobs", "", "delete"),
+    ("", "pods", "", "list"),
+    ("", "pods", "", "delete"),
+    ("", "pods", "log", "get"),
+    ("", "pods", "", "get"),
+    ("apps", "daemonsets", "", "get"),
+];
+
+/// Checks that executions can run: the Kubernetes API answers, the service account may manage
+/// Jobs and pods, the shared volume is writable and the executor images were pulled.
+pub async fn check(shared_dir: &Path) -> ReadinessReport {
+    let (api_check, client) = timed("kubernetes_api", async {
+        let client = Client::try_default()
+            .await
+            .map_err(|e| format!("No Kubernetes configuration: {}", e))?;
+        let version = client
+            .apiserver_version()
+            .await
+            .map_err(|e| format!("API server unreachable: {}", e))?;
+        Ok((client, format!("API server {}", version.git_version)))
+    })
+    .await;
+
+    let skipped = |name: &str| ReadinessCheck {
+        name: name.to_string(),
+        ok: false,
+        detail: "Skipped, the Kubernetes API is unreachable".to_string(),
+        duration_ms: 0,
+    };
+
+    let (permissions, images, volume) = futures_util::join!(
+        async {
+            match &client {
+                Some(client) => timed("rbac", check_permissions(client)).await.0,
+                None => skipped("rbac"),
+            }
+        },
+        async {
+            match &client {
+                Some(client) => timed("executor_images", check_images(client)).await.0,
+                None => skipped("executor_images"),
+            }
+        },
+        timed("shared_volume", check_shared_volume(shared_dir)),
+    );
+
+    let checks = vec![api_check, permissions, volume.0, images];
+    ReadinessReport {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+/// Runs a check within [`CHECK_TIMEOUT`], returning its outcome and the value it produced.
+async fn timed<T>(
+    name: &str,
+    check: impl Future<Output = Result<(T, String), String>>,
+) -> (ReadinessCheck, Option<T>) {
+    let start = Instant::now();
+    let (ok, detail, value) = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok((value, detail))) => (true, detail, Some(value)),
+        Ok(Err(detail)) => (false, detail, None),
+        Err(_) => (
+            false,
+            format!("Timed out after {} seconds", CHECK_TIMEOUT.as_secs()),
+            None,
+        ),
+    };
+    let check = ReadinessCheck {
+        name: name.to_string(),
+        ok,
+        detail,
+        duration_ms: start.elapsed().as_millis() as u64,
+    };
+    (check, value)
+}
+
+async fn check_permissions(client: &Client) -> Result<((), String), String> {
+    let reviews: Api<SelfSubjectAccessReview> = Api::all(client.clone());
+    let namespace = client.default_namespace().to_string();
+    let reviews = REQUIRED_PERMISSIONS
+        .iter()
+        .map(|&(group, resource, subresource, verb)| {
+            let reviews = reviews.clone();
+            let review = SelfSubjectAccessReview {
+                spec: SelfSubjectAccessReviewSpec {
+                    resource_attributes: Some(ResourceAttributes {
+                        namespace: Some(namespace.clone()),
+                        group: Some(group.to_string()),
+                        resource: Some(resource.to_string()),
+                        subresource: (!subresource.is_empty()).then(|| subresource.to_string()),
+                        verb: Some(verb.to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            async move {
+                let allowed = reviews
+                    .create(&PostParams::default(), &review)
+                    .await
+                    .map_err(|e| format!("Access review failed: {}", e))?
+                    .status
+                    .is_some_and(|status| status.allowed);
+                let mut permission = format!("{} {}", verb, resource);
+                if !subresource.is_empty() {
+                    permission = format!("{}/{}", permission, subresource);
+                }
+                Ok::<_, String>((permission, allowed))
+            }
+        });
+
+    let mut denied = Vec::new();
+    for review in join_all(reviews).await {
+        let (permission, allowed) = review?;
+        if !allowed {
+            denied.push(permission);
+        }
+    }
+    if denied.is_empty() {
+        Ok(((), format!("Allowed in namespace {}", namespace)))
+    } else {
+        Err(format!(
+            "Denied in namespace {}: {}",
+            namespace,
+            denied.join(", ")
+        ))
+    }
+}
+
+/// Images cannot be looked up in the registry without its credentials, so the check relies on the
+/// [`IMAGES_DAEMON_SET`], which pulls the image of every language on every node: it must list all
+/// of them and be ready on at least one node. The pods of the executions, which run user code,
+/// play no part.
+async fn check_images(client: &Client) -> Result<((), String), String> {
+    let project_id = K8sExecutor::project_id().map_err(|e| e.to_string())?;
+    let mut images = Vec::new();
+    for language in LANGUAGES {
+        let image =
+            K8sExecutor::image_name(&project_id, language.name).map_err(|e| e.to_string())?;
+        if !images.contains(&image) {
+            images.push(image);
+        }
+    }
+
+    let daemon_sets: Api<DaemonSet> = Api::default_namespaced(client.clone());
+    let daemon_set = daemon_sets
+        .get_opt(IMAGES_DAEMON_SET)
+        .await
+        .map_err(|e| format!("Getting DaemonSet {} failed: {}", IMAGES_DAEMON_SET, e))?
+        .ok_or_else(|| {
+            format!(
+                "DaemonSet {} is missing, apply executor-images.yaml",
+                IMAGES_DAEMON_SET
+            )
+        })?;
+    let pods: Api<Pod> = Api::default_namespaced(client.clone());
+    let pods = pods
+        .list(&ListParams::default().labels(&format!("app={}", IMAGES_DAEMON_SET)))
+        .await
+        .map_err(|e| format!("Listing the pods of {} failed: {}", IMAGES_DAEMON_SET, e))?;
+    check_pulled_images(&images, &daemon_set, &pods.items).map(|detail| ((), detail))
+}
+
+/// Checks that the DaemonSet pulls every image and that its pods could pull them.
+fn check_pulled_images(
+    images: &[String],
+    daemon_set: &DaemonSet,
+    pods: &[Pod],
+) -> Result<String, String> {
+    let pulled = daemon_set
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.template.spec.as_ref())
+        .map(|spec| {
+            spec.init_containers
+                .iter()
+                .flatten()
+                .chain(&spec.containers)
+                .filter_map(|container| container.image.as_deref())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let missing = images
+        .iter()
+        .filter(|image| !pulled.contains(&image.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(format!(
+            "DaemonSet {} does not pull {}",
+            IMAGES_DAEMON_SET,
+            missing.join(", ")
+        ));
+    }
+
+    let failures = image_pull_failures(pods);
+    if !failures.is_empty() {
+        return Err(format!("Cannot pull {}", failures.join(", ")));
+    }
+
+    let (ready, desired) = daemon_set
+        .status
+        .as_ref()
+        .map(|status| (status.number_ready, status.desired_number_scheduled))
+        .unwrap_or_default();
+    if ready == 0 {
+        return Err(format!(
+            "The {} images are not pulled on any node yet",
+            images.len()
+        ));
+    }
+    Ok(format!(
+        "{} images pulled on {} of {} nodes",
+        images.len(),
+        ready,
+        desired
+    ))
+}
+
+/// Images the pods wait for because they cannot be pulled.
+fn image_pull_failures(pods: &[Pod]) -> Vec<String> {
+    let mut images = Vec::new();
+    let statuses = pods
+        .iter()
+        .filter_map(|pod| pod.status.as_ref())
+        .flat_map(|status| {
+            status
+                .init_container_statuses
+                .iter()
+                .flatten()
+                .chain(status.container_statuses.iter().flatten())
+        });
+    for status in statuses {
+        let reason = status
+            .state
+            .as_ref()
+            .and_then(|state| state.waiting.as_ref())
+            .and_then(|waiting| waiting.reason.as_deref());
+        if reason.is_some_and(|reason| IMAGE_PULL_FAILURES.contains(&reason))
+            && !images.contains(&status.image)
+        {
+            images.push(status.image.clone());
+        }
+    }
+    images
+}
+
+async fn check_shared_volume(dir: &Path) -> Result<((), String), String> {
+    let path = dir.join(format!(".ready-{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&path, b"ready")
+        .await
+        .map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    tokio::fs::remove_file(&path)
+        .await
+        .map_err(|e| format!("Cannot delete files in {}: {}", dir.display(), e))?;
+    Ok(((), format!("{} is writable", dir.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shared_volume_and_pulled_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let (check, _) = timed("shared_volume", check_shared_volume(dir.path())).await;
+        assert!(check.ok, "{}", check.detail);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        let (check, _) = timed(
+            "shared_volume",
+            check_shared_volume(&dir.path().join("missing")),
+        )
+        .await;
+        assert!(!check.ok);
+
+        let images = [
+            "gcr.io/p/python:latest".to_string(),
+            "gcr.io/p/rust:latest".to_string(),
+        ];
+        let daemon_set = |images: &[&str], ready: i32| -> DaemonSet {
+            serde_json::from_value(serde_json::json!({
+                "metadata": { "name": IMAGES_DAEMON_SET },
+                "spec": {
+                    "selector": {},
+                    "template": { "spec": {
+                        "initContainers": images
+                            .iter()
+                            .map(|image| serde_json::json!({ "name": "pull", "image": image }))
+                            .collect::<Vec<_>>(),
+                        "containers": [{ "name": "pause", "image": "registry.k8s.io/pause:3.9" }],
+                    } },
+                },
+                "status": {
+                    "currentNumberScheduled": 2, "desiredNumberScheduled": 2,
+                    "numberMisscheduled": 0, "numberReady": ready,
+                },
+            }))
+            .unwrap()
+        };
+        let pulled = daemon_set(&["gcr.io/p/python:latest", "gcr.io/p/rust:latest"], 1);
+        assert_eq!(
+            check_pulled_images(&images, &pulled, &[]),
+            Ok("2 images pulled on 1 of 2 nodes".to_string())
+        );
+        assert!(
+            check_pulled_images(&images, &daemon_set(&["gcr.io/p/python:latest"], 2), &[])
+                .unwrap_err()
+                .contains("does not pull gcr.io/p/rust:latest")
+        );
+        assert!(check_pulled_images(
+            &images,
+            &daemon_set(&["gcr.io/p/python:latest", "gcr.io/p/rust:latest"], 0),
+            &[]
+        )
+        .is_err());
+
+        let pods: Vec<Pod> = serde_json::from_value(serde_json::json!([
+            { "status": { "initContainerStatuses": [{
+                "name": "python", "image": "gcr.io/p/python:latest", "imageID": "",
+                "ready": false, "restartCount": 0,
+                "state": { "waiting": { "reason": "ImagePullBackOff" } },
+            }] } },
+            { "status": { "initContainerStatuses": [{
+                "name": "rust", "image": "gcr.io/p/rust:latest", "imageID": "",
+                "ready": true, "restartCount": 0,
+                "state": { "terminated": { "exitCode": 0 } },
+            }] } },
+        ]))
+        .unwrap();
+        assert_eq!(image_pull_failures(&pods), ["gcr.io/p/python:latest"]);
+        assert_eq!(
+            check_pulled_images(&images, &pulled, &pods),
+            Err("Cannot pull gcr.io/p/python:latest".to_string())
+        );
+    }
+}
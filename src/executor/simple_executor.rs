@@ -1,66 +1,199 @@
-use crate::types::{
-    ExecutionPayload,
-    ExecutionResult,
+use crate::{
+    executor::queue::current_principal,
+    types::{
+        ExecutionPayload,
+        ExecutionResult,
+        ExecutionStatus,
+    },
 };
 use std::{
     io::{
         Error,
+        Read,
         Write,
     },
-    process::Command,
+    process::{
+        Command,
+        Output,
+        Stdio,
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    thread::{
+        self,
+        JoinHandle,
+    },
+    time::Duration,
 };
 use tempfile::NamedTempFile;
 
+/// How often running processes are checked for completion or cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Executions running locally, by principal and execution id, with the flag cancelling them.
+static RUNNING: Mutex<Vec<(String, String, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+
 pub struct SimpleExecutor;
 
 impl SimpleExecutor {
     pub fn execute(payload: &ExecutionPayload) -> ExecutionResult {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(execution_id) = &payload.execution_id {
+            RUNNING.lock().unwrap().push((
+                current_principal(),
+                execution_id.clone(),
+                cancelled.clone(),
+            ));
+        }
+
         let output = match payload.language.as_str() {
-            "python" => Command::new("python3")
-                .arg("-c")
-                .arg(&payload.code)
-                .output(),
-            "lua" => Command::new("lua").arg("-e").arg(&payload.code).output(),
-            "rust" => Self::compile_and_run_rust_code(&payload.code),
-            _ => Err(Error::new(
-                std::io::ErrorKind::Other,
-                "Language not supported",
-            )),
+            "python" => Self::run(
+                Command::new("python3").arg("-c").arg(&payload.code),
+                &cancelled,
+            ),
+            "lua" => Self::run(Command::new("lua").arg("-e").arg(&payload.code), &cancelled),
+            "rust" => Self::compile_and_run_rust_code(&payload.code, &cancelled),
+            _ => Err(Error::other("Language not supported")),
         };
 
+        RUNNING
+            .lock()
+            .unwrap()
+            .retain(|(_, _, flag)| !Arc::ptr_eq(flag, &cancelled));
+        if cancelled.load(Ordering::SeqCst) {
+            return ExecutionResult {
+                output: "".to_string(),
+                error: "Execution cancelled".to_string(),
+                output_file_path: Option::None,
+                output_file_content: Option::None,
+                status: ExecutionStatus::Cancelled,
+                exit_code: Option::None,
+                time_ms: Option::None,
+                billed_ms: None,
+                output_file: None,
+                output_files: Vec::new(),
+                output_files_truncated: false,
+            };
+        }
+
         match output {
             Ok(output) => ExecutionResult {
                 output: String::from_utf8_lossy(&output.stdout).to_string(),
                 error: String::from_utf8_lossy(&output.stderr).to_string(),
                 output_file_path: Option::None,
                 output_file_content: Option::None,
+                status: if output.status.success() {
+                    ExecutionStatus::Completed
+                } else {
+                    ExecutionStatus::Failed
+                },
+                exit_code: output.status.code(),
+                time_ms: Option::None,
+                billed_ms: None,
+                output_file: None,
+                output_files: Vec::new(),
+                output_files_truncated: false,
             },
             Err(e) => ExecutionResult {
                 output: "".to_string(),
                 error: e.to_string(),
                 output_file_path: Option::None,
                 output_file_content: Option::None,
+                status: ExecutionStatus::Failed,
+                exit_code: Option::None,
+                time_ms: Option::None,
+                billed_ms: None,
+                output_file: None,
+                output_files: Vec::new(),
+                output_files_truncated: false,
             },
         }
     }
 
-    fn compile_and_run_rust_code(code: &str) -> std::io::Result<std::process::Output> {
+    /// Kills the processes of the execution of the principal, returning whether it was running.
+    pub fn cancel(principal: &str, execution_id: &str) -> bool {
+        let running = RUNNING.lock().unwrap();
+        let mut found = false;
+        for (_, _, cancelled) in running
+            .iter()
+            .filter(|(owner, id, _)| owner == principal && id == execution_id)
+        {
+            cancelled.store(true, Ordering::SeqCst);
+            found = true;
+        }
+        found
+    }
+
+    /// Runs the command like [`Command::output`], killing it once `cancelled` is set.
+    fn run(command: &mut Command, cancelled: &AtomicBool) -> std::io::Result<Output> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Pipes are drained while waiting so that the process never blocks on a full one.
+        let stdout = child.stdout.take().map(Self::read_in_background);
+        let stderr = child.stderr.take().map(Self::read_in_background);
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if cancelled.load(Ordering::SeqCst) {
+                child.kill()?;
+                break child.wait()?;
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        let collect = |reader: Option<JoinHandle<Vec<u8>>>| {
+            reader
+                .map(|reader| reader.join().unwrap_or_default())
+                .unwrap_or_default()
+        };
+        Ok(Output {
+            status,
+            stdout: collect(stdout),
+            stderr: collect(stderr),
+        })
+    }
+
+    fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = pipe.read_to_end(&mut buffer);
+            buffer
+        })
+    }
+
+    fn compile_and_run_rust_code(
+        code: &str,
+        cancelled: &AtomicBool,
+    ) -> std::io::Result<std::process::Output> {
         let mut temp_file = NamedTempFile::new()?;
         writeln!(temp_file, "{}", code)?;
 
         let crate_name = "temp_crate";
         let binary_path = temp_file.path().with_extension("exe");
 
-        let compile_output = Command::new("rustc")
-            .arg(temp_file.path())
-            .arg("-o")
-            .arg(&binary_path)
-            .arg("--crate-name")
-            .arg(crate_name)
-            .output()?;
+        let compile_output = Self::run(
+            Command::new("rustc")
+                .arg(temp_file.path())
+                .arg("-o")
+                .arg(&binary_path)
+                .arg("--crate-name")
+                .arg(crate_name),
+            cancelled,
+        )?;
 
         if compile_output.status.success() {
-            Command::new(binary_path).output()
+            Self::run(&mut Command::new(binary_path), cancelled)
         } else {
             Ok(compile_output)
         }
@@ -76,12 +209,18 @@ mod tests {
         let payload = ExecutionPayload {
             language: "python".to_string(),
             code: "print('Hello, world!')".to_string(),
+            output_extension: ".txt".to_string(),
             input_file_path: Option::None,
+            input_file: Option::None,
+            input_file_id: Option::None,
+            execution_id: Option::None,
+            tags: Vec::new(),
         };
 
         let result = SimpleExecutor::execute(&payload);
 
-        assert_eq!(result.output, "Hello, world!\r\n");
+        let line_ending = if cfg!(windows) { "\r\n" } else { "\n" };
+        assert_eq!(result.output, format!("Hello, world!{}", line_ending));
         assert_eq!(result.error, "");
     }
 
@@ -90,7 +229,12 @@ mod tests {
         let payload = ExecutionPayload {
             language: "lua".to_string(),
             code: "print('Hello, world!\n')".to_string(),
+            output_extension: ".txt".to_string(),
             input_file_path: Option::None,
+            input_file: Option::None,
+            input_file_id: Option::None,
+            execution_id: Option::None,
+            tags: Vec::new(),
         };
 
         let result = SimpleExecutor::execute(&payload);
@@ -104,7 +248,12 @@ mod tests {
         let payload = ExecutionPayload {
             language: "rust".to_string(),
             code: "fn main() { println!(\"Hello, world!\"); }".to_string(),
+            output_extension: ".txt".to_string(),
             input_file_path: Option::None,
+            input_file: Option::None,
+            input_file_id: Option::None,
+            execution_id: Option::None,
+            tags: Vec::new(),
         };
 
         let result = SimpleExecutor::execute(&payload);
@@ -118,7 +267,12 @@ mod tests {
         let payload = ExecutionPayload {
             language: "unsupported".to_string(),
             code: "print('Hello, world!')".to_string(),
+            output_extension: ".txt".to_string(),
             input_file_path: Option::None,
+            input_file: Option::None,
+            input_file_id: Option::None,
+            execution_id: Option::None,
+            tags: Vec::new(),
         };
 
         let result = SimpleExecutor::execute(&payload);
@@ -126,4 +280,31 @@ mod tests {
         assert_eq!(result.output, "");
         assert!(result.error.contains("Language not supported"));
     }
+
+    #[test]
+    fn test_cancel_kills_the_process() {
+        let payload = ExecutionPayload {
+            language: "python".to_string(),
+            code: "import time; time.sleep(30)".to_string(),
+            output_extension: ".txt".to_string(),
+            input_file_path: Option::None,
+            input_file: Option::None,
+            input_file_id: Option::None,
+            execution_id: Some("cancelled-execution".to_string()),
+            tags: Vec::new(),
+        };
+
+        let start = std::time::Instant::now();
+        let execution = thread::spawn(move || SimpleExecutor::execute(&payload));
+        // Only the principal running the execution can cancel it.
+        while !SimpleExecutor::cancel("anonymous", "cancelled-execution") {
+            assert!(!SimpleExecutor::cancel("other", "cancelled-execution"));
+            thread::sleep(POLL_INTERVAL);
+        }
+        let result = execution.join().unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::Cancelled);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!SimpleExecutor::cancel("anonymous", "cancelled-execution"));
+    }
 }
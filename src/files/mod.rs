@@ -0,0 +1,335 @@
+use crate::{
+    artifacts::unix_time,
+    error::{
+        Error,
+        Result,
+    },
+    types::StoredFile,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    env,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+use tokio::sync::Mutex;
+
+/// Only mounted into the server, executions getting a copy of the files they use.
+const DEFAULT_FILES_DIR: &str = "files";
+const DEFAULT_FILE_TTL_SECS: u64 = 7 * 24 * 3600;
+const DEFAULT_FILES_QUOTA_BYTES: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Held while checking the quota and storing a file, so that concurrent uploads cannot both fit
+/// in the space left for one.
+static STORE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Input files uploaded once and referenced by id in any number of executions. Each principal has
+/// its own files, in a directory named after the SHA-256 of its id, and only sees these. Files are
+/// addressed by the SHA-256 of their content, so uploading the same content twice stores it once
+/// and only pushes its expiry back. Each file is stored as `{sha256}` next to its metadata,
+/// `{sha256}.json`.
+pub struct FileStore {
+    dir: PathBuf,
+    ttl: Duration,
+    /// Total size the unexpired files of every principal may take.
+    quota_bytes: u64,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration, quota_bytes: u64) -> Self {
+        FileStore {
+            dir: dir.into(),
+            ttl,
+            quota_bytes,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            env::var("FILES_DIR").unwrap_or_else(|_| DEFAULT_FILES_DIR.to_string()),
+            Duration::from_secs(number("FILE_TTL_SECS", DEFAULT_FILE_TTL_SECS)),
+            number("FILES_QUOTA_BYTES", DEFAULT_FILES_QUOTA_BYTES),
+        )
+    }
+
+    /// Ids are the hex encoded SHA-256 of the content.
+    pub fn is_valid_id(id: &str) -> bool {
+        id.len() == 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    }
+
+    fn owner_dir(&self, owner: &str) -> PathBuf {
+        self.dir.join(format!("{:x}", Sha256::digest(owner)))
+    }
+
+    fn content_path(dir: &Path, id: &str) -> PathBuf {
+        dir.join(id)
+    }
+
+    fn metadata_path(dir: &Path, id: &str) -> PathBuf {
+        dir.join(format!("{}.json", id))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves the file `owner` uploaded at `source`, in the directory of the store, into the
+    /// store. Its `size` and `sha256` are the ones computed while receiving it. Returns the stored
+    /// file and whether the owner already had the same content.
+    pub async fn store(
+        &self,
+        owner: &str,
+        source: &Path,
+        name: &str,
+        size: u64,
+        sha256: &str,
+    ) -> Result<(StoredFile, bool)> {
+        let dir = self.owner_dir(owner);
+        let id = sha256.to_string();
+        let path = Self::content_path(&dir, &id);
+
+        let _lock = STORE_LOCK.lock().await;
+        let existed = tokio::fs::try_exists(&path).await?;
+        // Content already stored takes no more space.
+        let available = self.quota_bytes.saturating_sub(self.usage().await?);
+        if !existed && size > available {
+            tokio::fs::remove_file(source).await?;
+            return Err(Error::QuotaExceeded(format!(
+                "Uploaded files may not take more than {} bytes",
+                self.quota_bytes
+            )));
+        }
+        if existed {
+            tokio::fs::remove_file(source).await?;
+        } else {
+            tokio::fs::create_dir_all(&dir).await?;
+            tokio::fs::rename(source, &path).await?;
+        }
+
+        let file = StoredFile {
+            file_id: id.clone(),
+            name: name.to_string(),
+            size,
+            sha256: id.clone(),
+            expires_at: unix_time(SystemTime::now() + self.ttl),
+            owner: owner.to_string(),
+        };
+        // Written aside then renamed, so that a concurrent lookup never reads a partial file.
+        let temporary_metadata_path = dir.join(format!(".{}.json.tmp", uuid::Uuid::new_v4()));
+        tokio::fs::write(&temporary_metadata_path, serde_json::to_vec(&file)?).await?;
+        tokio::fs::rename(&temporary_metadata_path, Self::metadata_path(&dir, &id)).await?;
+        Ok((file, existed))
+    }
+
+    /// Returns the metadata of a file of the owner that has not expired.
+    pub async fn get(&self, owner: &str, id: &str) -> io::Result<Option<StoredFile>> {
+        Self::get_in(&self.owner_dir(owner), id).await
+    }
+
+    async fn get_in(dir: &Path, id: &str) -> io::Result<Option<StoredFile>> {
+        if !Self::is_valid_id(id) {
+            return Ok(None);
+        }
+
+        let file = match tokio::fs::read(Self::metadata_path(dir, id)).await {
+            Ok(metadata) => serde_json::from_slice::<StoredFile>(&metadata)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if file.expires_at <= unix_time(SystemTime::now()) {
+            Self::delete_in(dir, id).await?;
+            return Ok(None);
+        }
+        Ok(Some(file))
+    }
+
+    /// Path of the file of the owner with the given id, if it has not expired. The executor
+    /// copies it for the Jobs, which never see the store.
+    pub async fn path(&self, owner: &str, id: &str) -> io::Result<Option<String>> {
+        let dir = self.owner_dir(owner);
+        Ok(Self::get_in(&dir, id)
+            .await?
+            .map(|_| Self::content_path(&dir, id).to_string_lossy().to_string()))
+    }
+
+    /// Deletes the file of the owner, returning whether it existed.
+    pub async fn delete(&self, owner: &str, id: &str) -> io::Result<bool> {
+        Self::delete_in(&self.owner_dir(owner), id).await
+    }
+
+    async fn delete_in(dir: &Path, id: &str) -> io::Result<bool> {
+        if !Self::is_valid_id(id) {
+            return Ok(false);
+        }
+
+        let mut existed = false;
+        for path in [Self::metadata_path(dir, id), Self::content_path(dir, id)] {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => existed = true,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(existed)
+    }
+
+    /// Size of the unexpired files of every owner.
+    async fn usage(&self) -> io::Result<u64> {
+        let mut usage = 0;
+        for dir in self.owner_dirs().await? {
+            for id in Self::ids(&dir).await? {
+                if let Some(file) = Self::get_in(&dir, &id).await? {
+                    usage += file.size;
+                }
+            }
+        }
+        Ok(usage)
+    }
+
+    async fn owner_dirs(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(Self::ids(&self.dir)
+            .await?
+            .into_iter()
+            .map(|key| self.dir.join(key))
+            .filter(|dir| dir.is_dir())
+            .collect())
+    }
+
+    /// Entries of the directory named like an id, the uploads in progress having other names.
+    async fn ids(dir: &Path) -> io::Result<Vec<String>> {
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut ids = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            if let Some(id) = file_name.to_str().filter(|id| Self::is_valid_id(id)) {
+                ids.push(id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Deletes the expired files, returning how many there were.
+    pub async fn purge_expired(&self) -> io::Result<usize> {
+        let mut purged = 0;
+        for dir in self.owner_dirs().await? {
+            for id in Self::ids(&dir).await? {
+                // Content without metadata is left by an interrupted upload.
+                if Self::get_in(&dir, &id).await?.is_none() {
+                    Self::delete_in(&dir, &id).await?;
+                    purged += 1;
+                }
+            }
+        }
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn upload(
+        store: &FileStore,
+        owner: &str,
+        name: &str,
+        content: &str,
+    ) -> Result<(StoredFile, bool)> {
+        let path = store.dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::write(&path, content).unwrap();
+        let sha256 = format!("{:x}", Sha256::digest(content));
+        store
+            .store(owner, &path, name, content.len() as u64, &sha256)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_store_deduplicates_and_expires() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path(), Duration::from_secs(60), 8);
+
+        let (file, existed) = upload(&store, "alice", "a.txt", "hello").await.unwrap();
+        assert!(!existed);
+        assert_eq!(file.size, 5);
+        assert_eq!(file.owner, "alice");
+        assert_eq!(
+            file.file_id,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        let (again, existed) = upload(&store, "alice", "b.txt", "hello").await.unwrap();
+        assert!(existed);
+        assert_eq!(again.file_id, file.file_id);
+        assert_eq!(
+            store
+                .get("alice", &file.file_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .name,
+            "b.txt"
+        );
+        let path = store.path("alice", &file.file_id).await.unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+
+        // The files of other principals are out of reach.
+        assert!(store.get("bob", &file.file_id).await.unwrap().is_none());
+        assert!(store.path("bob", &file.file_id).await.unwrap().is_none());
+        assert!(!store.delete("bob", &file.file_id).await.unwrap());
+
+        // The quota is shared by every principal.
+        assert!(matches!(
+            upload(&store, "bob", "c.txt", "world").await,
+            Err(Error::QuotaExceeded(_))
+        ));
+        assert!(store.get("alice", "../a.txt").await.unwrap().is_none());
+
+        let expired = FileStore::new(dir.path(), Duration::ZERO, 8);
+        upload(&expired, "alice", "a.txt", "hello").await.unwrap();
+        assert_eq!(expired.purge_expired().await.unwrap(), 1);
+        assert!(store.get("alice", &file.file_id).await.unwrap().is_none());
+        // Uploads that were not kept are removed.
+        assert_eq!(
+            std::fs::read_dir(store.owner_dir("alice")).unwrap().count(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_uploads_respect_the_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path(), Duration::from_secs(60), 8);
+
+        let uploads =
+            ["hello", "world", "there"].map(|content| upload(&store, "alice", "a.txt", content));
+        let stored = futures_util::future::join_all(uploads)
+            .await
+            .into_iter()
+            .filter(Result::is_ok)
+            .count();
+        assert_eq!(stored, 1);
+        assert_eq!(store.usage().await.unwrap(), 5);
+    }
+}
@@ -0,0 +1,394 @@
+use rusqlite::{
+    params,
+    params_from_iter,
+    types::Value,
+    Connection,
+    OptionalExtension,
+    Row,
+};
+use serde::Deserialize;
+use std::{
+    env,
+    io,
+    path::Path,
+    sync::{
+        Mutex,
+        MutexGuard,
+    },
+    time::Duration,
+};
+use utoipa::IntoParams;
+
+use crate::{
+    error::{
+        Error,
+        Result,
+    },
+    types::{
+        ExecutionKind,
+        ExecutionPage,
+        ExecutionRecord,
+    },
+};
+
+/// Kept out of the shared volume by default, which the executions can read.
+const DEFAULT_HISTORY_DB: &str = "history.db";
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+pub const MAX_PAGE_SIZE: u64 = 200;
+const MAX_TAGS: usize = 16;
+const MAX_TAG_LENGTH: usize = 64;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS executions (
+        execution_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        principal TEXT NOT NULL,
+        language TEXT NOT NULL,
+        language_version TEXT,
+        code_sha256 TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        time_limit_ms INTEGER,
+        memory_limit_mb INTEGER,
+        outcome TEXT NOT NULL,
+        exit_code INTEGER,
+        started_at INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        run_ms INTEGER,
+        output_bytes INTEGER NOT NULL,
+        cases INTEGER,
+        PRIMARY KEY (principal, execution_id)
+    );
+    CREATE INDEX IF NOT EXISTS executions_by_start ON executions (started_at);
+    CREATE INDEX IF NOT EXISTS executions_by_principal ON executions (principal, started_at);
+";
+const COLUMNS: &str = "execution_id, kind, principal, language, language_version, code_sha256, \
+                       tags, time_limit_ms, memory_limit_mb, outcome, exit_code, started_at, \
+                       duration_ms, run_ms, output_bytes, cases";
+
+/// Filters and page of `GET /executions`.
+#[derive(Deserialize, Debug, Default, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryQuery {
+    /// Id of the principal the executions ran for, only admins may see the others'.
+    pub principal: Option<String>,
+    pub language: Option<String>,
+    /// `completed`, `cancelled` or an error code, like `compile_error`.
+    pub outcome: Option<String>,
+    /// Executions having this tag.
+    pub tag: Option<String>,
+    /// Executions started at or after this Unix timestamp, in seconds.
+    pub since: Option<u64>,
+    /// Executions started before this Unix timestamp, in seconds.
+    pub until: Option<u64>,
+    /// Executions per page, 50 by default and 200 at most.
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+/// Past executions, stored in the SQLite database of `HISTORY_DB_PATH`.
+pub struct ExecutionHistory {
+    connection: Mutex<Connection>,
+}
+
+impl ExecutionHistory {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path).map_err(io::Error::other)?;
+        // Requests wait for each other rather than fail when the database is busy.
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .and_then(|()| connection.execute_batch(SCHEMA))
+            .map_err(io::Error::other)?;
+        Ok(ExecutionHistory {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn from_env() -> io::Result<Self> {
+        Self::open(env::var("HISTORY_DB_PATH").unwrap_or_else(|_| DEFAULT_HISTORY_DB.to_string()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stores the execution, which fails when its principal already has one with the same id:
+    /// execution ids are chosen by the clients, each of which may only use one once.
+    pub fn record(&self, record: &ExecutionRecord) -> Result<()> {
+        self.lock().execute(
+            &format!(
+                "INSERT INTO executions ({}) VALUES \
+                 (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                COLUMNS
+            ),
+            params![
+                record.execution_id,
+                record.kind.as_str(),
+                record.principal,
+                record.language,
+                record.language_version,
+                record.code_sha256,
+                serde_json::to_string(&record.tags)?,
+                record.time_limit_ms.map(|limit| limit as i64),
+                record.memory_limit_mb.map(|limit| limit as i64),
+                record.outcome,
+                record.exit_code,
+                record.started_at as i64,
+                record.duration_ms as i64,
+                record.run_ms.map(|time| time as i64),
+                record.output_bytes as i64,
+                record.cases.map(|cases| cases as i64),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The execution of `principal` with this id, or the latest one of any principal with
+    /// `None`.
+    pub fn get(
+        &self,
+        principal: Option<&str>,
+        execution_id: &str,
+    ) -> Result<Option<ExecutionRecord>> {
+        Ok(self
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT {} FROM executions WHERE execution_id = ?1 \
+                     AND (?2 IS NULL OR principal = ?2) ORDER BY started_at DESC LIMIT 1",
+                    COLUMNS
+                ),
+                params![execution_id, principal],
+                record_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Executions matching the filters of the query, newest first.
+    pub fn list(&self, query: &HistoryQuery) -> Result<ExecutionPage> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let offset = query.offset.unwrap_or(0);
+
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        for (column, value) in [
+            ("principal", &query.principal),
+            ("language", &query.language),
+            ("outcome", &query.outcome),
+        ] {
+            if let Some(value) = value {
+                conditions.push(format!("{} = ?", column));
+                values.push(Value::Text(value.clone()));
+            }
+        }
+        if let Some(tag) = &query.tag {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?)".to_string());
+            values.push(Value::Text(tag.clone()));
+        }
+        if let Some(since) = query.since {
+            conditions.push("started_at >= ?".to_string());
+            values.push(Value::Integer(since as i64));
+        }
+        if let Some(until) = query.until {
+            conditions.push("started_at < ?".to_string());
+            values.push(Value::Integer(until as i64));
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+
+        let connection = self.lock();
+        let total: i64 = connection.query_row(
+            &format!("SELECT COUNT(*) FROM executions{}", filter),
+            params_from_iter(&values),
+            |row| row.get(0),
+        )?;
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(offset as i64));
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM executions{} ORDER BY started_at DESC, rowid DESC LIMIT ? OFFSET ?",
+            COLUMNS, filter
+        ))?;
+        let executions = statement
+            .query_map(params_from_iter(&values), record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(ExecutionPage {
+            executions,
+            total: total as u64,
+            limit,
+            offset,
+        })
+    }
+}
+
+/// Tags are matched exactly by `GET /executions`, so they are kept short and free of
+/// separators.
+pub fn validate_tags(tags: &[String]) -> Result<()> {
+    if tags.len() > MAX_TAGS {
+        return Err(Error::Validation(format!(
+            "At most {} tags are allowed",
+            MAX_TAGS
+        )));
+    }
+    let valid = |tag: &String| {
+        !tag.is_empty()
+            && tag.len() <= MAX_TAG_LENGTH
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '='))
+    };
+    match tags.iter().find(|tag| !valid(tag)) {
+        Some(tag) => Err(Error::Validation(format!(
+            "Tag '{}' must be 1 to {} letters, digits, '-', '_', '.', ':' or '='",
+            tag, MAX_TAG_LENGTH
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn record_from_row(row: &Row) -> rusqlite::Result<ExecutionRecord> {
+    let unsigned = |index: usize| -> rusqlite::Result<Option<u64>> {
+        Ok(row.get::<_, Option<i64>>(index)?.map(|value| value as u64))
+    };
+    let tags: String = row.get(6)?;
+    Ok(ExecutionRecord {
+        execution_id: row.get(0)?,
+        kind: match row.get::<_, String>(1)?.as_str() {
+            "batch" => ExecutionKind::Batch,
+            "judge" => ExecutionKind::Judge,
+            "interactive" => ExecutionKind::Interactive,
+            "stress" => ExecutionKind::Stress,
+            "submission" => ExecutionKind::Submission,
+            _ => ExecutionKind::Single,
+        },
+        principal: row.get(2)?,
+        language: row.get(3)?,
+        language_version: row.get(4)?,
+        code_sha256: row.get(5)?,
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        time_limit_ms: unsigned(7)?,
+        memory_limit_mb: unsigned(8)?,
+        outcome: row.get(9)?,
+        exit_code: row.get(10)?,
+        started_at: unsigned(11)?.unwrap_or_default(),
+        duration_ms: unsigned(12)?.unwrap_or_default(),
+        run_ms: unsigned(13)?,
+        output_bytes: unsigned(14)?.unwrap_or_default(),
+        cases: unsigned(15)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(execution_id: &str, principal: &str, started_at: u64) -> ExecutionRecord {
+        ExecutionRecord {
+            execution_id: execution_id.to_string(),
+            kind: ExecutionKind::Single,
+            principal: principal.to_string(),
+            language: "python".to_string(),
+            language_version: Some("3.9".to_string()),
+            code_sha256: "ab".repeat(32),
+            tags: vec!["course:algo".to_string()],
+            time_limit_ms: None,
+            memory_limit_mb: Some(1024),
+            outcome: "completed".to_string(),
+            exit_code: None,
+            started_at,
+            duration_ms: 1200,
+            run_ms: Some(35),
+            output_bytes: 12,
+            cases: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ExecutionHistory::open(dir.path().join("data/history.db")).unwrap();
+
+        let first = record("1", "ci", 100);
+        history.record(&first).unwrap();
+        history
+            .record(&ExecutionRecord {
+                kind: ExecutionKind::Submission,
+                ..record("2", "ci", 200)
+            })
+            .unwrap();
+        history
+            .record(&ExecutionRecord {
+                kind: ExecutionKind::Batch,
+                language: "rust".to_string(),
+                outcome: "compile_error".to_string(),
+                tags: Vec::new(),
+                cases: Some(3),
+                ..record("3", "alice", 300)
+            })
+            .unwrap();
+        assert_eq!(history.get(Some("ci"), "1").unwrap(), Some(first.clone()));
+        assert_eq!(history.get(Some("ci"), "4").unwrap(), None);
+        assert_eq!(
+            history.get(None, "2").unwrap().unwrap().kind,
+            ExecutionKind::Submission
+        );
+        // A principal may not reuse one of its ids, which does not replace the first execution.
+        assert!(history.record(&record("1", "ci", 400)).is_err());
+        assert_eq!(history.get(Some("ci"), "1").unwrap(), Some(first));
+        // Another principal choosing the same id does not see the first execution.
+        history.record(&record("1", "bob", 50)).unwrap();
+        assert_eq!(
+            history.get(Some("bob"), "1").unwrap().unwrap().started_at,
+            50
+        );
+        assert_eq!(history.get(Some("alice"), "1").unwrap(), None);
+        assert_eq!(history.get(None, "1").unwrap().unwrap().principal, "ci");
+
+        let page = history.list(&HistoryQuery::default()).unwrap();
+        assert_eq!(page.total, 4);
+        let ids = |page: &ExecutionPage| {
+            page.executions
+                .iter()
+                .map(|record| record.execution_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&page), ["3", "2", "1", "1"]);
+
+        let query = |query: HistoryQuery| history.list(&query).unwrap();
+        let page = query(HistoryQuery {
+            principal: Some("ci".to_string()),
+            limit: Some(1),
+            offset: Some(1),
+            ..Default::default()
+        });
+        assert_eq!((page.total, ids(&page)), (2, vec!["1".to_string()]));
+        let page = query(HistoryQuery {
+            tag: Some("course:algo".to_string()),
+            since: Some(150),
+            ..Default::default()
+        });
+        assert_eq!(ids(&page), ["2"]);
+        let page = query(HistoryQuery {
+            outcome: Some("compile_error".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(page.executions[0].kind, ExecutionKind::Batch);
+
+        assert!(validate_tags(&["team=a".to_string()]).is_ok());
+        assert!(validate_tags(&["a,b".to_string()]).is_err());
+        assert!(validate_tags(&vec!["a".to_string(); MAX_TAGS + 1]).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use super::summarize;
+use crate::{
+    error::Result,
+    executor::CodeExecutor,
+    types::{
+        BatchPayload,
+        ExecutionResult,
+        JudgeResult,
+        Program,
+        TestCase,
+        Verdict,
+        DEFAULT_OUTPUT_EXTENSION,
+    },
+};
+
+const CHECKER_TIME_LIMIT_MS: u64 = 10_000;
+
+/// Runs the checker on every case the submission completed, i.e. judged as accepted or wrong
+/// answer by the comparison, and replaces their verdict and score with the checker's. The checker
+/// runs as its own `checker` stage, so the expected outputs, handed to it as arguments, only
+/// reach the Jobs of the checker and never the ones of the submission.
+pub async fn check<E: CodeExecutor>(
+    checker: &Program,
+    batch: &BatchPayload,
+    expected_outputs: &[String],
+    result: JudgeResult,
+) -> Result<JudgeResult> {
+    let JudgeResult {
+        execution_id,
+        compile_error,
+        mut cases,
+        ..
+    } = result;
+
+    let checked = cases
+        .iter()
+        .enumerate()
+        .filter(|(_, case)| matches!(case.verdict, Verdict::Accepted | Verdict::WrongAnswer))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if checked.is_empty() {
+        return Ok(summarize(execution_id, compile_error, cases));
+    }
+
+    let checker_batch = BatchPayload {
+        language: checker.language.clone(),
+        code: checker.code.clone(),
+        output_extension: DEFAULT_OUTPUT_EXTENSION.to_string(),
+        cases: checked
+            .iter()
+            .map(|&index| TestCase {
+                stdin: batch.cases[index].stdin.clone(),
+                input_file_path: batch.cases[index].input_file_path.clone(),
+                input_file: None,
+                input_file_id: None,
+                arguments: vec![
+                    cases[index].result.output.clone(),
+                    expected_outputs[index].clone(),
+                ],
+            })
+            .collect(),
+        parallelism: batch.parallelism,
+        time_limit_ms: Some(CHECKER_TIME_LIMIT_MS),
+        memory_limit_mb: None,
+        execution_id: batch.execution_id.clone(),
+        tags: Vec::new(),
+        interactor: None,
+        stage: Some("checker".to_string()),
+    };
+    let checker_result = E::execute_batch(&checker_batch).await?;
+
+    for (&index, checker_case) in checked.iter().zip(&checker_result.results) {
+        let case = &mut cases[index];
+        let outcome = match &checker_result.compile_error {
+            Some(error) => Err(format!("Checker failed to compile: {}", error)),
+            None => parse_checker_output(checker_case),
+        };
+        match outcome {
+            Ok((verdict, score, message)) => {
+                case.verdict = verdict;
+                case.score = score;
+                case.checker_message = message;
+            }
+            Err(message) => {
+                case.verdict = Verdict::CheckerError;
+                case.score = 0.0;
+                case.checker_message = Some(message);
+            }
+        }
+        case.diff = None;
+    }
+
+    Ok(summarize(execution_id, compile_error, cases))
+}
+
+/// Reads the verdict, the score and the message printed by the checker, see [`Program`].
+pub(super) fn parse_checker_output(
+    result: &ExecutionResult,
+) -> Result<(Verdict, f64, Option<String>), String> {
+    if result.exit_code != Some(0) {
+        return Err(format!(
+            "Checker exited with code {:?}: {}",
+            result.exit_code,
+            result.error.trim()
+        ));
+    }
+
+    let mut lines = result.output.lines();
+    let verdict = match lines
+        .next()
+        .map(|line| line.trim().to_lowercase())
+        .as_deref()
+    {
+        Some("accepted") | Some("ac") | Some("ok") => Verdict::Accepted,
+        Some("wrong_answer") | Some("wa") => Verdict::WrongAnswer,
+        other => {
+            return Err(format!(
+                "Unknown checker verdict: {:?}",
+                other.unwrap_or("")
+            ))
+        }
+    };
+
+    let score = match lines.next().map(str::trim).filter(|line| !line.is_empty()) {
+        Some(score) => match score.parse::<f64>() {
+            Ok(score) if (0.0..=1.0).contains(&score) => score,
+            _ => return Err(format!("Invalid checker score: {}", score)),
+        },
+        None if verdict == Verdict::Accepted => 1.0,
+        None => 0.0,
+    };
+
+    let message = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    Ok((verdict, score, (!message.is_empty()).then_some(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        BatchResult,
+        ExecutionPayload,
+        ExecutionStatus,
+        JudgeCaseResult,
+    };
+    use std::sync::Mutex;
+
+    /// Stages and cases of the batches the checker ran.
+    static CHECKED: Mutex<Vec<(Option<String>, Vec<TestCase>)>> = Mutex::new(Vec::new());
+
+    /// Accepts every case it is given.
+    struct FakeExecutor;
+
+    #[async_trait::async_trait]
+    impl CodeExecutor for FakeExecutor {
+        async fn execute(_: &ExecutionPayload) -> Result<ExecutionResult> {
+            unreachable!()
+        }
+
+        async fn execute_batch(payload: &BatchPayload) -> Result<BatchResult> {
+            CHECKED
+                .lock()
+                .unwrap()
+                .push((payload.stage.clone(), payload.cases.clone()));
+            Ok(BatchResult {
+                execution_id: payload.execution_id.clone().unwrap_or_default(),
+                status: ExecutionStatus::Completed,
+                compile_error: None,
+                results: payload
+                    .cases
+                    .iter()
+                    .map(|_| checker_result("accepted", 0))
+                    .collect(),
+                interactor_results: Vec::new(),
+            })
+        }
+    }
+
+    fn checker_result(output: &str, exit_code: i32) -> ExecutionResult {
+        ExecutionResult {
+            error: String::new(),
+            output: output.to_string(),
+            output_file_path: None,
+            output_file_content: None,
+            status: ExecutionStatus::Completed,
+            exit_code: Some(exit_code),
+            time_ms: Some(10),
+            billed_ms: None,
+            output_file: None,
+            output_files: Vec::new(),
+            output_files_truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_checker_verdict_only() {
+        assert_eq!(
+            parse_checker_output(&checker_result("accepted\n", 0)),
+            Ok((Verdict::Accepted, 1.0, None))
+        );
+        assert_eq!(
+            parse_checker_output(&checker_result("WA", 0)),
+            Ok((Verdict::WrongAnswer, 0.0, None))
+        );
+    }
+
+    #[test]
+    fn test_parse_checker_score_and_message() {
+        assert_eq!(
+            parse_checker_output(&checker_result("wrong_answer\n0.5\npath is not minimal", 0)),
+            Ok((
+                Verdict::WrongAnswer,
+                0.5,
+                Some("path is not minimal".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_checker_failures() {
+        assert!(parse_checker_output(&checker_result("accepted", 1)).is_err());
+        assert!(parse_checker_output(&checker_result("maybe", 0)).is_err());
+        assert!(parse_checker_output(&checker_result("accepted\n2", 0)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expected_outputs_only_reach_the_checker() {
+        let batch = BatchPayload {
+            language: "python".to_string(),
+            code: "print(input())".to_string(),
+            output_extension: DEFAULT_OUTPUT_EXTENSION.to_string(),
+            cases: vec![
+                TestCase {
+                    stdin: Some("1".to_string()),
+                    ..Default::default()
+                },
+                TestCase {
+                    stdin: Some("2".to_string()),
+                    ..Default::default()
+                },
+            ],
+            parallelism: None,
+            time_limit_ms: None,
+            memory_limit_mb: None,
+            execution_id: Some("e1".to_string()),
+            tags: Vec::new(),
+            interactor: None,
+            stage: None,
+        };
+        let case = |verdict, output: &str| JudgeCaseResult {
+            verdict,
+            score: 0.0,
+            diff: Some("diff".to_string()),
+            checker_message: None,
+            result: checker_result(output, 0),
+        };
+        let result = summarize(
+            "e1".to_string(),
+            None,
+            vec![
+                case(Verdict::WrongAnswer, "1.0"),
+                case(Verdict::RuntimeError, ""),
+            ],
+        );
+        let checker = Program {
+            language: "python".to_string(),
+            code: "print('accepted')".to_string(),
+        };
+
+        let checked = check::<FakeExecutor>(
+            &checker,
+            &batch,
+            &["1".to_string(), "2".to_string()],
+            result,
+        )
+        .await
+        .unwrap();
+        // Only the case the submission completed is checked.
+        assert_eq!(checked.verdict, Verdict::RuntimeError);
+        assert_eq!(checked.cases[0].verdict, Verdict::Accepted);
+        assert_eq!(checked.cases[0].diff, None);
+
+        let runs = CHECKED.lock().unwrap();
+        assert_eq!(runs.len(), 1);
+        let (stage, cases) = &runs[0];
+        assert_eq!(stage.as_deref(), Some("checker"));
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].stdin.as_deref(), Some("1"));
+        assert_eq!(cases[0].arguments, ["1.0", "1"]);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::types::ComparisonMode;
+
+/// Maximum number of mismatching lines or tokens reported in a diff.
+const MAX_DIFF_ENTRIES: usize = 10;
+
+/// Compares the output of a program with the expected one. Returns `None` when they match and a
+/// short human readable diff otherwise.
+///
+/// Line endings are normalized and trailing newlines ignored in every mode.
+pub fn compare(expected: &str, output: &str, mode: ComparisonMode) -> Option<String> {
+    let expected = normalize(expected);
+    let output = normalize(output);
+
+    match mode {
+        ComparisonMode::Exact => diff_lines(&expected, &output),
+        ComparisonMode::IgnoreWhitespace => diff_tokens(&expected, &output, |a, b| a == b),
+        ComparisonMode::FloatTolerance { epsilon } => {
+            diff_tokens(&expected, &output, |a, b| floats_match(a, b, epsilon))
+        }
+        ComparisonMode::UnorderedLines => diff_unordered_lines(&expected, &output),
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+fn floats_match(expected: &str, found: &str, epsilon: f64) -> bool {
+    match (expected.parse::<f64>(), found.parse::<f64>()) {
+        (Ok(a), Ok(b)) => {
+            let difference = (a - b).abs();
+            difference <= epsilon || difference <= epsilon * a.abs().max(b.abs())
+        }
+        _ => expected == found,
+    }
+}
+
+fn diff_lines(expected: &str, output: &str) -> Option<String> {
+    let expected_lines = expected.split('\n').collect::<Vec<_>>();
+    let output_lines = output.split('\n').collect::<Vec<_>>();
+
+    let mut entries = Vec::new();
+    for index in 0..expected_lines.len().max(output_lines.len()) {
+        let (expected_line, output_line) = (expected_lines.get(index), output_lines.get(index));
+        if expected_line == output_line {
+            continue;
+        }
+        entries.push(format!("@@ line {} @@", index + 1));
+        if let Some(line) = expected_line {
+            entries.push(format!("-{}", line));
+        }
+        if let Some(line) = output_line {
+            entries.push(format!("+{}", line));
+        }
+        if entries.len() >= MAX_DIFF_ENTRIES * 3 {
+            entries.push("...".to_string());
+            break;
+        }
+    }
+
+    (!entries.is_empty()).then(|| entries.join("\n"))
+}
+
+fn diff_tokens(
+    expected: &str,
+    output: &str,
+    matches: impl Fn(&str, &str) -> bool,
+) -> Option<String> {
+    let expected_tokens = expected.split_whitespace().collect::<Vec<_>>();
+    let output_tokens = output.split_whitespace().collect::<Vec<_>>();
+
+    let mut entries = expected_tokens
+        .iter()
+        .zip(&output_tokens)
+        .enumerate()
+        .filter(|(_, (a, b))| !matches(a, b))
+        .take(MAX_DIFF_ENTRIES)
+        .map(|(index, (a, b))| format!("token {}: expected `{}`, found `{}`", index + 1, a, b))
+        .collect::<Vec<_>>();
+
+    if expected_tokens.len() != output_tokens.len() {
+        entries.push(format!(
+            "expected {} tokens, found {}",
+            expected_tokens.len(),
+            output_tokens.len()
+        ));
+    }
+
+    (!entries.is_empty()).then(|| entries.join("\n"))
+}
+
+fn diff_unordered_lines(expected: &str, output: &str) -> Option<String> {
+    let mut missing = expected.split('\n').map(str::trim_end).collect::<Vec<_>>();
+    let mut unexpected = Vec::new();
+
+    for line in output.split('\n').map(str::trim_end) {
+        match missing
+            .iter()
+            .position(|expected_line| *expected_line == line)
+        {
+            Some(index) => {
+                missing.swap_remove(index);
+            }
+            None => unexpected.push(line),
+        }
+    }
+
+    let entries = missing
+        .iter()
+        .map(|line| format!("-{}", line))
+        .chain(unexpected.iter().map(|line| format!("+{}", line)))
+        .take(MAX_DIFF_ENTRIES)
+        .collect::<Vec<_>>();
+
+    (!entries.is_empty()).then(|| entries.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_ignores_line_endings_and_trailing_newlines() {
+        assert_eq!(compare("1\n2\n", "1\r\n2", ComparisonMode::Exact), None);
+    }
+
+    #[test]
+    fn test_exact_reports_mismatching_lines() {
+        let diff = compare("1\n2\n3", "1\n4\n3", ComparisonMode::Exact).unwrap();
+
+        assert_eq!(diff, "@@ line 2 @@\n-2\n+4");
+    }
+
+    #[test]
+    fn test_ignore_whitespace() {
+        assert_eq!(
+            compare("1 2\n3", "  1\t2 3  ", ComparisonMode::IgnoreWhitespace),
+            None
+        );
+        assert!(compare("1 2 3", "1 2", ComparisonMode::IgnoreWhitespace).is_some());
+    }
+
+    #[test]
+    fn test_float_tolerance() {
+        let mode = ComparisonMode::FloatTolerance { epsilon: 1e-6 };
+
+        assert_eq!(compare("0.3333333 abc", "0.33333331 abc", mode), None);
+        assert_eq!(
+            compare("0.5", "0.6", mode).unwrap(),
+            "token 1: expected `0.5`, found `0.6`"
+        );
+    }
+
+    #[test]
+    fn test_unordered_lines() {
+        assert_eq!(
+            compare("a\nb\nb", "b\na\nb", ComparisonMode::UnorderedLines),
+            None
+        );
+        assert_eq!(
+            compare("a\nb", "b\nc", ComparisonMode::UnorderedLines).unwrap(),
+            "-a\n+c"
+        );
+    }
+}
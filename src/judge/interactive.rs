@@ -0,0 +1,103 @@
+use super::{
+    checker::parse_checker_output,
+    summarize,
+    timed_out,
+    SIGKILL_EXIT_CODE,
+};
+use crate::types::{
+    BatchResult,
+    ExecutionResult,
+    JudgeCaseResult,
+    JudgeResult,
+    Verdict,
+};
+
+/// Printed by the executor script when the interactor does not compile.
+const INTERACTOR_COMPILE_ERROR: &str = "Interactor compilation failed";
+
+/// Turns the results of an interactive batch run into verdicts. Limits and crashes of the
+/// submission come first, the verdict written by the interactor decides otherwise.
+pub fn evaluate_interactive(batch: BatchResult, time_limit_ms: Option<u64>) -> JudgeResult {
+    let compile_verdict = batch.compile_error.as_ref().map(|error| {
+        if error.contains(INTERACTOR_COMPILE_ERROR) {
+            Verdict::CheckerError
+        } else {
+            Verdict::CompileError
+        }
+    });
+
+    let cases = batch
+        .results
+        .into_iter()
+        .zip(batch.interactor_results)
+        .map(|(result, interactor_result)| {
+            let (verdict, score, checker_message) = match compile_verdict {
+                Some(verdict) => (verdict, 0.0, None),
+                None => case_verdict(&result, &interactor_result, time_limit_ms),
+            };
+            JudgeCaseResult {
+                verdict,
+                score,
+                diff: None,
+                checker_message,
+                result,
+            }
+        })
+        .collect();
+
+    summarize(batch.execution_id, batch.compile_error, cases)
+}
+
+fn case_verdict(
+    result: &ExecutionResult,
+    interactor_result: &ExecutionResult,
+    time_limit_ms: Option<u64>,
+) -> (Verdict, f64, Option<String>) {
+    match result.exit_code {
+        _ if timed_out(result, time_limit_ms) => (Verdict::TimeLimitExceeded, 0.0, None),
+        Some(SIGKILL_EXIT_CODE) => (Verdict::MemoryLimitExceeded, 0.0, None),
+        Some(0) => match parse_checker_output(interactor_result) {
+            Ok((verdict, score, message)) => (verdict, score, message),
+            Err(message) => (Verdict::CheckerError, 0.0, Some(message)),
+        },
+        _ => (Verdict::RuntimeError, 0.0, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::MAX_TIME_LIMIT_MS,
+        types::ExecutionStatus,
+    };
+
+    fn result(output: &str, exit_code: i32, time_ms: u64) -> ExecutionResult {
+        ExecutionResult {
+            output: output.to_string(),
+            error: String::new(),
+            output_file_path: None,
+            output_file_content: None,
+            status: ExecutionStatus::Completed,
+            exit_code: Some(exit_code),
+            time_ms: Some(time_ms),
+            billed_ms: None,
+            output_file: None,
+            output_files: Vec::new(),
+            output_files_truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_submission_killed_at_the_default_limit() {
+        let accepted = result("accepted", 0, 10);
+        assert_eq!(
+            case_verdict(&result("", 124, MAX_TIME_LIMIT_MS), &accepted, None).0,
+            Verdict::TimeLimitExceeded
+        );
+        assert_eq!(
+            case_verdict(&result("", 0, 10), &accepted, None).0,
+            Verdict::Accepted
+        );
+    }
+}
@@ -0,0 +1,154 @@
+mod checker;
+mod compare;
+mod interactive;
+mod stress;
+pub use checker::check;
+pub use compare::compare;
+pub use interactive::evaluate_interactive;
+pub use stress::{
+    stress,
+    MAX_STRESS_BUDGET_MS,
+    MAX_TESTS_PER_ROUND,
+};
+
+use crate::{
+    executor::MAX_TIME_LIMIT_MS,
+    types::{
+        BatchResult,
+        ComparisonMode,
+        ExecutionResult,
+        JudgeCaseResult,
+        JudgeResult,
+        Verdict,
+    },
+};
+
+/// Exit code of a process killed by `SIGKILL`, which is what the OOM killer sends.
+const SIGKILL_EXIT_CODE: i32 = 137;
+
+/// Turns the results of a batch run into per case verdicts. `expected_outputs` is indexed like
+/// the cases of the batch.
+pub fn evaluate(
+    batch: BatchResult,
+    expected_outputs: &[String],
+    comparison: ComparisonMode,
+    time_limit_ms: Option<u64>,
+) -> JudgeResult {
+    let cases = batch
+        .results
+        .into_iter()
+        .zip(expected_outputs)
+        .map(|(result, expected_output)| {
+            let (verdict, diff) = if batch.compile_error.is_some() {
+                (Verdict::CompileError, None)
+            } else {
+                case_verdict(&result, expected_output, comparison, time_limit_ms)
+            };
+            JudgeCaseResult {
+                verdict,
+                score: if verdict == Verdict::Accepted {
+                    1.0
+                } else {
+                    0.0
+                },
+                diff,
+                checker_message: None,
+                result,
+            }
+        })
+        .collect();
+
+    summarize(batch.execution_id, batch.compile_error, cases)
+}
+
+fn summarize(
+    execution_id: String,
+    compile_error: Option<String>,
+    cases: Vec<JudgeCaseResult>,
+) -> JudgeResult {
+    let verdict = cases
+        .iter()
+        .map(|case| case.verdict)
+        .find(|verdict| *verdict != Verdict::Accepted)
+        .unwrap_or(Verdict::Accepted);
+
+    JudgeResult {
+        execution_id,
+        verdict,
+        passed: cases
+            .iter()
+            .filter(|case| case.verdict == Verdict::Accepted)
+            .count(),
+        total: cases.len(),
+        score: cases.iter().map(|case| case.score).sum(),
+        compile_error,
+        cases,
+    }
+}
+
+/// Whether the case ran into its time limit, cases without one being killed after
+/// [`MAX_TIME_LIMIT_MS`].
+fn timed_out(result: &ExecutionResult, time_limit_ms: Option<u64>) -> bool {
+    result
+        .time_ms
+        .is_some_and(|time| time >= time_limit_ms.unwrap_or(MAX_TIME_LIMIT_MS))
+}
+
+fn case_verdict(
+    result: &ExecutionResult,
+    expected_output: &str,
+    comparison: ComparisonMode,
+    time_limit_ms: Option<u64>,
+) -> (Verdict, Option<String>) {
+    match result.exit_code {
+        _ if timed_out(result, time_limit_ms) => (Verdict::TimeLimitExceeded, None),
+        Some(SIGKILL_EXIT_CODE) => (Verdict::MemoryLimitExceeded, None),
+        Some(0) => match compare(expected_output, &result.output, comparison) {
+            Some(diff) => (Verdict::WrongAnswer, Some(diff)),
+            None => (Verdict::Accepted, None),
+        },
+        _ => (Verdict::RuntimeError, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExecutionStatus;
+
+    fn result(exit_code: i32, time_ms: u64) -> ExecutionResult {
+        ExecutionResult {
+            output: "1\n".to_string(),
+            error: String::new(),
+            output_file_path: None,
+            output_file_content: None,
+            status: ExecutionStatus::Completed,
+            exit_code: Some(exit_code),
+            time_ms: Some(time_ms),
+            billed_ms: None,
+            output_file: None,
+            output_files: Vec::new(),
+            output_files_truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_time_limit_verdicts() {
+        let verdict = |result: &ExecutionResult, time_limit_ms| {
+            case_verdict(result, "1", ComparisonMode::default(), time_limit_ms).0
+        };
+
+        assert_eq!(
+            verdict(&result(124, 1_000), Some(1_000)),
+            Verdict::TimeLimitExceeded
+        );
+        assert_eq!(verdict(&result(0, 999), Some(1_000)), Verdict::Accepted);
+        // Cases without a limit are killed after the maximum one.
+        assert_eq!(
+            verdict(&result(124, MAX_TIME_LIMIT_MS), None),
+            Verdict::TimeLimitExceeded
+        );
+        assert_eq!(verdict(&result(0, 5_000), None), Verdict::Accepted);
+        assert_eq!(verdict(&result(1, 5_000), None), Verdict::RuntimeError);
+    }
+}
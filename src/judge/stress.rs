@@ -0,0 +1,466 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use futures_util::future::join;
+
+use super::evaluate;
+use crate::{
+    error::{
+        Error,
+        Result,
+    },
+    executor::{
+        CodeExecutor,
+        MAX_TIME_LIMIT_MS,
+    },
+    types::{
+        BatchPayload,
+        BatchResult,
+        Counterexample,
+        ExecutionStatus,
+        Program,
+        StressPayload,
+        StressResult,
+        TestCase,
+        Verdict,
+        DEFAULT_OUTPUT_EXTENSION,
+    },
+};
+
+pub const DEFAULT_STRESS_BUDGET_MS: u64 = 60_000;
+pub const MAX_STRESS_BUDGET_MS: u64 = 300_000;
+pub const DEFAULT_TESTS_PER_ROUND: usize = 10;
+pub const MAX_TESTS_PER_ROUND: usize = 100;
+/// Rounds double in size up to this many tests, so that the programs of a long run are only
+/// compiled a few times.
+const MAX_TESTS_PER_BATCH: u64 = 800;
+/// Time a round spends outside of the programs, scheduling the pods and compiling, until the
+/// first round measured it.
+const ESTIMATED_ROUND_OVERHEAD: Duration = Duration::from_secs(10);
+
+/// Runs rounds of generated tests until the solution disagrees with the brute force, see
+/// [`StressPayload`]. Each round is three batches: the generator, then the brute force and the
+/// solution side by side. The time limit of every case is capped so that a round, whose cases run
+/// one after the other, fits in what remains of the budget once the overhead of the previous round
+/// is set aside. A round still running past the budget ends the run as if the budget was spent.
+pub async fn stress<E: CodeExecutor>(payload: &StressPayload) -> Result<StressResult> {
+    let execution_id = payload
+        .execution_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let budget = Duration::from_millis(payload.time_budget_ms.unwrap_or(DEFAULT_STRESS_BUDGET_MS));
+    let mut tests_per_round = payload
+        .tests_per_round
+        .unwrap_or(DEFAULT_TESTS_PER_ROUND)
+        .clamp(1, MAX_TESTS_PER_ROUND) as u64;
+    let solution_time_limit_ms = payload
+        .time_limit_ms
+        .unwrap_or(MAX_TIME_LIMIT_MS)
+        .min(MAX_TIME_LIMIT_MS);
+    let start = Instant::now();
+    // Per case limit of `time_limit_ms` at most, `None` once the budget is spent. `reserved` is
+    // the overhead of what remains of the round.
+    let capped = |time_limit_ms: u64, tests: u64, reserved: Duration| {
+        let remaining_ms = budget
+            .saturating_sub(start.elapsed() + reserved)
+            .as_millis() as u64;
+        Some(time_limit_ms.min(remaining_ms / tests)).filter(|&limit| limit > 0)
+    };
+
+    let finish = |status, tests_run, counterexample, error| StressResult {
+        execution_id: execution_id.clone(),
+        status,
+        tests_run,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+        counterexample,
+        error,
+    };
+
+    let mut tests_run = 0;
+    let mut round = 0;
+    let mut next_seed = 1;
+    let mut round_overhead = ESTIMATED_ROUND_OVERHEAD;
+    loop {
+        let round_start = Instant::now();
+        let seeds = (next_seed..next_seed + tests_per_round).collect::<Vec<_>>();
+        let Some(generator_time_limit_ms) =
+            capped(MAX_TIME_LIMIT_MS, tests_per_round, round_overhead)
+        else {
+            break;
+        };
+        let generated = E::execute_batch(&batch(
+            &payload.generator,
+            &execution_id,
+            format!("gen{}", round),
+            seeds.iter().map(|seed| format!("{}\n", seed)).collect(),
+            Some(generator_time_limit_ms),
+        ))
+        .await;
+        let Some(generated) = within_budget(generated, start, budget)? else {
+            break;
+        };
+        if cut_short(&generated, generator_time_limit_ms, MAX_TIME_LIMIT_MS) {
+            break;
+        }
+        if let Some(error) = failure("Generator", &generated, &seeds) {
+            return Ok(finish(
+                ExecutionStatus::Failed,
+                tests_run,
+                None,
+                Some(error),
+            ));
+        }
+        let generator_time = run_time(&generated);
+        let inputs = generated
+            .results
+            .into_iter()
+            .map(|result| result.output)
+            .collect::<Vec<_>>();
+
+        // The brute force and the solution run side by side, taking half of the overhead.
+        let (Some(brute_force_time_limit_ms), Some(time_limit_ms)) = (
+            capped(MAX_TIME_LIMIT_MS, tests_per_round, round_overhead / 2),
+            capped(solution_time_limit_ms, tests_per_round, round_overhead / 2),
+        ) else {
+            break;
+        };
+        let (brute_force, solution) = join(
+            E::execute_batch(&batch(
+                &payload.brute_force,
+                &execution_id,
+                format!("brute{}", round),
+                inputs.clone(),
+                Some(brute_force_time_limit_ms),
+            )),
+            E::execute_batch(&batch(
+                &payload.solution,
+                &execution_id,
+                format!("sol{}", round),
+                inputs.clone(),
+                Some(time_limit_ms),
+            )),
+        )
+        .await;
+        let (Some(brute_force), Some(solution)) = (
+            within_budget(brute_force, start, budget)?,
+            within_budget(solution, start, budget)?,
+        ) else {
+            break;
+        };
+        round_overhead = round_start
+            .elapsed()
+            .saturating_sub(generator_time + run_time(&brute_force).max(run_time(&solution)));
+        if cut_short(&brute_force, brute_force_time_limit_ms, MAX_TIME_LIMIT_MS) {
+            break;
+        }
+        if let Some(error) = failure("Brute force", &brute_force, &seeds) {
+            return Ok(finish(
+                ExecutionStatus::Failed,
+                tests_run,
+                None,
+                Some(error),
+            ));
+        }
+        if let Some(ref error) = solution.compile_error {
+            let error = format!("Solution failed to compile: {}", error);
+            return Ok(finish(
+                ExecutionStatus::Failed,
+                tests_run,
+                None,
+                Some(error),
+            ));
+        }
+
+        let expected_outputs = brute_force
+            .results
+            .into_iter()
+            .map(|result| result.output)
+            .collect::<Vec<_>>();
+        let judged = evaluate(
+            solution,
+            &expected_outputs,
+            payload.comparison,
+            Some(time_limit_ms),
+        );
+
+        if let Some(index) = judged
+            .cases
+            .iter()
+            .position(|case| case.verdict != Verdict::Accepted)
+        {
+            // Killed by the cap of the budget rather than by its own limit.
+            if judged.cases[index].verdict == Verdict::TimeLimitExceeded
+                && time_limit_ms < solution_time_limit_ms
+            {
+                tests_run += index;
+                break;
+            }
+
+            let counterexample = Counterexample {
+                seed: seeds[index],
+                input: inputs[index].clone(),
+                expected_output: expected_outputs[index].clone(),
+                case: judged.cases.into_iter().nth(index).unwrap(),
+            };
+            return Ok(finish(
+                ExecutionStatus::Completed,
+                tests_run + index + 1,
+                Some(counterexample),
+                None,
+            ));
+        }
+
+        tests_run += judged.total;
+        round += 1;
+        next_seed += tests_per_round;
+        tests_per_round = (tests_per_round * 2).min(MAX_TESTS_PER_BATCH);
+    }
+
+    Ok(finish(ExecutionStatus::Completed, tests_run, None, None))
+}
+
+fn batch(
+    program: &Program,
+    execution_id: &str,
+    stage: String,
+    stdins: Vec<String>,
+    time_limit_ms: Option<u64>,
+) -> BatchPayload {
+    BatchPayload {
+        language: program.language.clone(),
+        code: program.code.clone(),
+        output_extension: DEFAULT_OUTPUT_EXTENSION.to_string(),
+        cases: stdins
+            .into_iter()
+            .map(|stdin| TestCase {
+                stdin: Some(stdin),
+                ..Default::default()
+            })
+            .collect(),
+        parallelism: None,
+        time_limit_ms,
+        memory_limit_mb: None,
+        execution_id: Some(execution_id.to_string()),
+        tags: Vec::new(),
+        interactor: None,
+        stage: Some(stage),
+    }
+}
+
+/// Turns the timeout of a batch that ran past the budget into the end of the run.
+fn within_budget<T>(result: Result<T>, start: Instant, budget: Duration) -> Result<Option<T>> {
+    match result {
+        Err(Error::Timeout) if start.elapsed() >= budget => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Time the cases of the batch ran for, one after the other.
+fn run_time(batch: &BatchResult) -> Duration {
+    Duration::from_millis(
+        batch
+            .results
+            .iter()
+            .filter_map(|result| result.time_ms)
+            .sum(),
+    )
+}
+
+/// Whether a case of the batch timed out only because its limit was capped below `time_limit_ms`
+/// by the budget, which is then spent.
+fn cut_short(batch: &BatchResult, capped_time_limit_ms: u64, time_limit_ms: u64) -> bool {
+    capped_time_limit_ms < time_limit_ms
+        && batch.results.iter().any(|result| {
+            result
+                .time_ms
+                .is_some_and(|time| time >= capped_time_limit_ms)
+        })
+}
+
+/// Describes why a judge side program could not produce the data of the round.
+fn failure(name: &str, batch: &BatchResult, seeds: &[u64]) -> Option<String> {
+    if let Some(ref error) = batch.compile_error {
+        return Some(format!("{} failed to compile: {}", name, error));
+    }
+
+    batch
+        .results
+        .iter()
+        .zip(seeds)
+        .find(|(result, _)| result.exit_code != Some(0))
+        .map(|(result, seed)| {
+            format!(
+                "{} failed on seed {} with exit code {:?}: {}",
+                name,
+                seed,
+                result.exit_code,
+                result.error.trim()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ComparisonMode,
+        ExecutionPayload,
+        ExecutionResult,
+    };
+    use std::sync::Mutex;
+
+    /// Stage, number of cases and time limit of a batch.
+    type Run = (String, usize, Option<u64>);
+
+    /// Batches that ran, by execution id.
+    static RUNS: Mutex<Vec<(String, Run)>> = Mutex::new(Vec::new());
+
+    /// Programs echo their stdin, except the `wrong` solution on seed 25 and the `slow` one
+    /// running out of time on seed 3.
+    struct FakeExecutor;
+
+    #[async_trait::async_trait]
+    impl CodeExecutor for FakeExecutor {
+        async fn execute(_: &ExecutionPayload) -> Result<ExecutionResult> {
+            unreachable!()
+        }
+
+        async fn execute_batch(payload: &BatchPayload) -> Result<BatchResult> {
+            let execution_id = payload.execution_id.clone().unwrap_or_default();
+            RUNS.lock().unwrap().push((
+                execution_id.clone(),
+                (
+                    payload.stage.clone().unwrap_or_default(),
+                    payload.cases.len(),
+                    payload.time_limit_ms,
+                ),
+            ));
+            let result = |stdin: &str| {
+                let (output, exit_code, time_ms) = match (payload.code.as_str(), stdin) {
+                    ("wrong", "25\n") => ("26\n".to_string(), 0, 1),
+                    ("slow", "3\n") => (String::new(), 124, payload.time_limit_ms.unwrap()),
+                    _ => (stdin.to_string(), 0, 1),
+                };
+                ExecutionResult {
+                    output,
+                    error: String::new(),
+                    output_file_path: None,
+                    output_file_content: None,
+                    status: ExecutionStatus::Completed,
+                    exit_code: Some(exit_code),
+                    time_ms: Some(time_ms),
+                    billed_ms: None,
+                    output_file: None,
+                    output_files: Vec::new(),
+                    output_files_truncated: false,
+                }
+            };
+            Ok(BatchResult {
+                execution_id,
+                status: ExecutionStatus::Completed,
+                compile_error: None,
+                results: payload
+                    .cases
+                    .iter()
+                    .map(|case| result(case.stdin.as_deref().unwrap_or_default()))
+                    .collect(),
+                interactor_results: Vec::new(),
+            })
+        }
+    }
+
+    fn payload(execution_id: &str, solution: &str, budget_ms: u64) -> StressPayload {
+        let program = |code: &str| Program {
+            language: "python".to_string(),
+            code: code.to_string(),
+        };
+        StressPayload {
+            generator: program("generator"),
+            brute_force: program("brute_force"),
+            solution: program(solution),
+            comparison: ComparisonMode::default(),
+            time_budget_ms: Some(budget_ms),
+            tests_per_round: Some(10),
+            time_limit_ms: Some(500),
+            execution_id: Some(execution_id.to_string()),
+        }
+    }
+
+    fn runs(execution_id: &str) -> Vec<Run> {
+        RUNS.lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| id == execution_id)
+            .map(|(_, run)| run.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rounds_grow_and_fit_in_the_budget() {
+        let result = stress::<FakeExecutor>(&payload("e-grow", "wrong", 60_000))
+            .await
+            .unwrap();
+
+        let counterexample = result.counterexample.unwrap();
+        assert_eq!(counterexample.seed, 25);
+        assert_eq!(counterexample.case.verdict, Verdict::WrongAnswer);
+        assert_eq!(result.tests_run, 25);
+
+        // The second round runs twice as many tests, so the programs are compiled once per
+        // round only.
+        let runs = runs("e-grow");
+        let stages = runs
+            .iter()
+            .map(|(stage, ..)| stage.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(stages, ["gen0", "brute0", "sol0", "gen1", "brute1", "sol1"]);
+        for (stage, cases, time_limit_ms) in &runs {
+            assert_eq!(*cases, if stage.ends_with('0') { 10 } else { 20 });
+            assert!(time_limit_ms.unwrap() * *cases as u64 <= 60_000);
+        }
+        // The overhead of the round is set aside from the budget.
+        assert!((4_900..=5_000).contains(&runs[0].2.unwrap()));
+        assert!((5_400..=5_500).contains(&runs[1].2.unwrap()));
+        // The solution keeps its own limit while the budget allows it.
+        assert_eq!(runs[2].2, Some(500));
+        assert_eq!(runs[5].2, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_capped_by_the_remaining_budget() {
+        let mut payload = payload("e-capped", "slow", 11_000);
+        payload.time_limit_ms = Some(5_000);
+        let result = stress::<FakeExecutor>(&payload).await.unwrap();
+
+        // The solution only ran out of the time left, which is no counterexample.
+        assert!(result.counterexample.is_none());
+        assert!(result.error.is_none());
+        assert_eq!(result.tests_run, 2);
+
+        let runs = runs("e-capped");
+        assert_eq!(runs.len(), 3);
+        for (_, cases, time_limit_ms) in &runs {
+            assert!(time_limit_ms.unwrap() * *cases as u64 <= 11_000 - 5_000);
+        }
+    }
+
+    #[test]
+    fn test_rounds_past_the_budget_end_the_run() {
+        let start = Instant::now() - Duration::from_secs(2);
+        assert!(matches!(
+            within_budget::<()>(Err(Error::Timeout), start, Duration::from_secs(1)),
+            Ok(None)
+        ));
+        // A Job timing out well within the budget is still an error.
+        assert!(matches!(
+            within_budget::<()>(Err(Error::Timeout), start, Duration::from_secs(60)),
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            within_budget(Ok(1), start, Duration::from_secs(1)),
+            Ok(Some(1))
+        ));
+    }
+}
@@ -1,4 +1,15 @@
 pub mod api;
+pub mod artifacts;
+pub mod auth;
+pub mod error;
 pub mod executor;
+pub mod files;
+pub mod history;
+pub mod judge;
+pub mod logging;
+pub mod metrics;
+pub mod problems;
+pub mod quotas;
+pub mod telemetry;
 pub mod types;
 pub mod web;
@@ -0,0 +1,174 @@
+use log::{
+    kv::{
+        Key,
+        Value,
+        VisitSource,
+    },
+    Level,
+    Record,
+};
+use serde_json::{
+    json,
+    Map,
+};
+use std::{
+    env,
+    fmt,
+    future::Future,
+    io::Write,
+    sync::OnceLock,
+};
+
+tokio::task_local! {
+    /// Id of the request being served, added to every log line written while serving it.
+    static REQUEST_ID: String;
+}
+
+/// Runs `future` on behalf of the request `request_id`.
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Id of the request being served, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Format of the log lines, read from `LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// One JSON object per line, the key-values of the record being fields of the object.
+    Json,
+    /// The human readable format of `env_logger`.
+    Text,
+}
+
+impl LogFormat {
+    pub fn from_env() -> Self {
+        match env::var("LOG_FORMAT").as_deref().map(str::trim) {
+            Ok("text") => LogFormat::Text,
+            _ => LogFormat::Json,
+        }
+    }
+}
+
+/// Installs the logger, filtered by `RUST_LOG` (`info` by default).
+pub fn init() {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if LogFormat::from_env() == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json_line(
+                &buf.timestamp_millis().to_string(),
+                record,
+                current_request_id(),
+            );
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+fn json_line(timestamp: &str, record: &Record, request_id: Option<String>) -> serde_json::Value {
+    let mut line = Map::new();
+    line.insert("timestamp".to_string(), json!(timestamp));
+    line.insert("level".to_string(), json!(record.level().as_str()));
+    line.insert("target".to_string(), json!(record.target()));
+    line.insert("message".to_string(), json!(record.args().to_string()));
+    if let Some(request_id) = request_id {
+        line.insert("request_id".to_string(), json!(request_id));
+    }
+    let _ = record.key_values().visit(&mut JsonFields(&mut line));
+    serde_json::Value::Object(line)
+}
+
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(number) = value.to_u64() {
+            json!(number)
+        } else if let Some(number) = value.to_i64() {
+            json!(number)
+        } else if let Some(number) = value.to_f64() {
+            json!(number)
+        } else if let Some(flag) = value.to_bool() {
+            json!(flag)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Whether user code, inputs and outputs may be logged, which `LOG_USER_CONTENT=true` allows
+/// at the debug level only.
+fn user_content_logged() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| env::var("LOG_USER_CONTENT").is_ok_and(|value| value.trim() == "true"))
+        && log::log_enabled!(Level::Debug)
+}
+
+/// Code, input or output of a user, logged as its size unless [`user_content_logged`].
+pub struct UserContent<'a>(&'a str);
+
+pub fn user_content(text: &str) -> UserContent<'_> {
+    UserContent(text)
+}
+
+impl fmt::Display for UserContent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if user_content_logged() {
+            f.write_str(self.0)
+        } else {
+            write!(f, "<redacted {} bytes>", self.0.len())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines_and_redaction() {
+        let kvs = [("job_name", "job-1")];
+        let record = Record::builder()
+            .args(format_args!("Creating Job"))
+            .level(Level::Info)
+            .target("dyno_code::executor")
+            .key_values(&kvs)
+            .build();
+        assert_eq!(
+            json_line(
+                "2024-05-01T10:00:00.000Z",
+                &record,
+                Some("req-1".to_string())
+            ),
+            json!({
+                "timestamp": "2024-05-01T10:00:00.000Z",
+                "level": "INFO",
+                "target": "dyno_code::executor",
+                "message": "Creating Job",
+                "request_id": "req-1",
+                "job_name": "job-1",
+            })
+        );
+
+        let durations = [("duration_ms", 42u64)];
+        let record = Record::builder()
+            .args(format_args!("Execution completed"))
+            .key_values(&durations)
+            .build();
+        let line = json_line("", &record, None);
+        assert_eq!(line["duration_ms"], json!(42));
+        assert!(line.get("request_id").is_none());
+
+        // No logger is installed in the tests, so the debug level is disabled.
+        assert_eq!(
+            user_content("print('secret')").to_string(),
+            "<redacted 15 bytes>"
+        );
+    }
+}
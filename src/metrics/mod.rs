@@ -0,0 +1,248 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        MutexGuard,
+    },
+};
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+const SIZE_BUCKETS: &[f64] = &[
+    256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0, 67108864.0,
+];
+
+pub static EXECUTIONS: Counter = Counter {
+    name: "dyno_code_executions_total",
+    help: "Executions by kind, language and outcome.",
+};
+pub static EXECUTION_DURATION: Histogram = Histogram {
+    name: "dyno_code_execution_duration_seconds",
+    help: "Time to answer an execution, from the request to the result.",
+    buckets: DURATION_BUCKETS,
+};
+pub static QUEUE_WAIT: Histogram = Histogram {
+    name: "dyno_code_queue_wait_seconds",
+    help: "Time Jobs waited for a slot in the execution queue.",
+    buckets: DURATION_BUCKETS,
+};
+pub static POD_SCHEDULING: Histogram = Histogram {
+    name: "dyno_code_pod_scheduling_seconds",
+    help: "Time from the creation of a Job to its pod leaving the Pending phase.",
+    buckets: DURATION_BUCKETS,
+};
+pub static COMPILE_TIME: Histogram = Histogram {
+    name: "dyno_code_compile_seconds",
+    help: "Time compiling the programs of compiled languages.",
+    buckets: DURATION_BUCKETS,
+};
+pub static RUN_TIME: Histogram = Histogram {
+    name: "dyno_code_run_seconds",
+    help: "Time the programs ran for, batch cases included.",
+    buckets: DURATION_BUCKETS,
+};
+pub static OUTPUT_SIZE: Histogram = Histogram {
+    name: "dyno_code_output_bytes",
+    help: "Size of the standard output and of the files written by the programs.",
+    buckets: SIZE_BUCKETS,
+};
+pub static KUBERNETES_API_ERRORS: Counter = Counter {
+    name: "dyno_code_kubernetes_api_errors_total",
+    help: "Failed calls to the Kubernetes API by operation.",
+};
+
+type Labels = Vec<(&'static str, String)>;
+
+enum Series {
+    Counter(u64),
+    Histogram {
+        /// Observations in each bucket, not cumulated.
+        counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    buckets: &'static [f64],
+    series: BTreeMap<Labels, Series>,
+}
+
+/// Every metric observed since the start, rendered by `GET /metrics`.
+static REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+
+fn registry() -> MutexGuard<'static, BTreeMap<&'static str, Family>> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect()
+}
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+}
+
+impl Counter {
+    pub fn inc(&self, label_values: &[(&'static str, &str)]) {
+        let mut registry = registry();
+        let family = registry.entry(self.name).or_insert_with(|| Family {
+            help: self.help,
+            kind: "counter",
+            buckets: &[],
+            series: BTreeMap::new(),
+        });
+        if let Series::Counter(count) = family
+            .series
+            .entry(labels(label_values))
+            .or_insert(Series::Counter(0))
+        {
+            *count += 1;
+        }
+    }
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    buckets: &'static [f64],
+}
+
+impl Histogram {
+    pub fn observe(&self, label_values: &[(&'static str, &str)], value: f64) {
+        let mut registry = registry();
+        let family = registry.entry(self.name).or_insert_with(|| Family {
+            help: self.help,
+            kind: "histogram",
+            buckets: self.buckets,
+            series: BTreeMap::new(),
+        });
+        let series = family
+            .series
+            .entry(labels(label_values))
+            .or_insert_with(|| Series::Histogram {
+                counts: vec![0; self.buckets.len()],
+                sum: 0.0,
+                count: 0,
+            });
+        if let Series::Histogram { counts, sum, count } = series {
+            if let Some(bucket) = self.buckets.iter().position(|bound| value <= *bound) {
+                counts[bucket] += 1;
+            }
+            *sum += value;
+            *count += 1;
+        }
+    }
+}
+
+/// Metrics in the Prometheus text format.
+pub fn render() -> String {
+    let registry = registry();
+    let mut text = String::new();
+    for (name, family) in registry.iter() {
+        let _ = writeln!(text, "# HELP {} {}", name, family.help);
+        let _ = writeln!(text, "# TYPE {} {}", name, family.kind);
+        for (labels, series) in &family.series {
+            match series {
+                Series::Counter(count) => {
+                    let _ = writeln!(text, "{}{} {}", name, format_labels(labels, None), count);
+                }
+                Series::Histogram { counts, sum, count } => {
+                    let mut cumulated = 0;
+                    for (bound, bucket_count) in family.buckets.iter().zip(counts) {
+                        cumulated += bucket_count;
+                        let le = bound.to_string();
+                        let _ = writeln!(
+                            text,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(labels, Some(&le)),
+                            cumulated
+                        );
+                    }
+                    let _ = writeln!(
+                        text,
+                        "{}_bucket{} {}",
+                        name,
+                        format_labels(labels, Some("+Inf")),
+                        count
+                    );
+                    let _ = writeln!(text, "{}_sum{} {}", name, format_labels(labels, None), sum);
+                    let _ = writeln!(
+                        text,
+                        "{}_count{} {}",
+                        name,
+                        format_labels(labels, None),
+                        count
+                    );
+                }
+            }
+        }
+    }
+    text
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect::<Vec<_>>();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        static REQUESTS: Counter = Counter {
+            name: "test_requests_total",
+            help: "Requests.",
+        };
+        static SIZES: Histogram = Histogram {
+            name: "test_sizes_bytes",
+            help: "Sizes.",
+            buckets: &[10.0, 100.0],
+        };
+        REQUESTS.inc(&[("language", "rust")]);
+        REQUESTS.inc(&[("language", "rust")]);
+        REQUESTS.inc(&[("language", "py\"thon")]);
+        SIZES.observe(&[], 5.0);
+        SIZES.observe(&[], 50.0);
+        SIZES.observe(&[], 500.0);
+
+        let text = render();
+        assert!(text.contains("# TYPE test_requests_total counter\n"));
+        assert!(text.contains("test_requests_total{language=\"rust\"} 2\n"));
+        assert!(text.contains("test_requests_total{language=\"py\\\"thon\"} 1\n"));
+        assert!(text.contains("# TYPE test_sizes_bytes histogram\n"));
+        assert!(text.contains("test_sizes_bytes_bucket{le=\"10\"} 1\n"));
+        assert!(text.contains("test_sizes_bytes_bucket{le=\"100\"} 2\n"));
+        assert!(text.contains("test_sizes_bytes_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("test_sizes_bytes_sum 555\n"));
+        assert!(text.contains("test_sizes_bytes_count 3\n"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    env,
+    io,
+    path::PathBuf,
+};
+
+use crate::types::Problem;
+
+/// Kept out of the shared volume by default, which the submissions can read.
+const DEFAULT_PROBLEMS_DIR: &str = "problems";
+const MAX_PROBLEM_ID_LENGTH: usize = 64;
+
+/// Problems stored as one JSON file each in `PROBLEMS_DIR`.
+pub struct ProblemStore {
+    dir: PathBuf,
+}
+
+impl ProblemStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ProblemStore { dir: dir.into() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env::var("PROBLEMS_DIR").unwrap_or_else(|_| DEFAULT_PROBLEMS_DIR.to_string()))
+    }
+
+    /// Ids are used as file names, so they are restricted to lowercase letters, digits, `-` and
+    /// `_`.
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_PROBLEM_ID_LENGTH
+            && id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Creates or replaces the problem, returning whether it already existed.
+    pub async fn save(&self, problem: &Problem) -> io::Result<bool> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(&problem.id);
+        let existed = tokio::fs::try_exists(&path).await?;
+
+        // Written aside then renamed, so that a submission never reads a partial file.
+        let temporary_path = self.dir.join(format!(".{}.json.tmp", problem.id));
+        tokio::fs::write(&temporary_path, serde_json::to_vec(problem)?).await?;
+        tokio::fs::rename(&temporary_path, &path).await?;
+        Ok(existed)
+    }
+
+    pub async fn load(&self, id: &str) -> io::Result<Option<Problem>> {
+        if !Self::is_valid_id(id) {
+            return Ok(None);
+        }
+
+        match tokio::fs::read(self.path(id)).await {
+            Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Every stored problem, sorted by id.
+    pub async fn list(&self) -> io::Result<Vec<Problem>> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut problems = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(id) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            if let Some(problem) = self.load(id).await? {
+                problems.push(problem);
+            }
+        }
+        problems.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(problems)
+    }
+
+    /// Returns whether the problem existed.
+    pub async fn delete(&self, id: &str) -> io::Result<bool> {
+        if !Self::is_valid_id(id) {
+            return Ok(false);
+        }
+
+        match tokio::fs::remove_file(self.path(id)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ComparisonMode,
+        JudgeTestCase,
+        TestCase,
+    };
+
+    fn problem(id: &str) -> Problem {
+        Problem {
+            id: id.to_string(),
+            title: format!("Problem {}", id),
+            statement: String::new(),
+            time_limit_ms: Some(1000),
+            memory_limit_mb: None,
+            comparison: ComparisonMode::IgnoreWhitespace,
+            checker: None,
+            test_cases: vec![JudgeTestCase {
+                input: TestCase {
+                    stdin: Some("1 2\n".to_string()),
+                    ..Default::default()
+                },
+                expected_output: "3\n".to_string(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProblemStore::new(dir.path());
+
+        assert!(!store.save(&problem("b-sum")).await.unwrap());
+        assert!(!store.save(&problem("a_sum")).await.unwrap());
+        assert!(store.save(&problem("b-sum")).await.unwrap());
+
+        let loaded = store.load("b-sum").await.unwrap().unwrap();
+        assert_eq!(loaded.comparison, ComparisonMode::IgnoreWhitespace);
+        assert_eq!(loaded.test_cases[0].input.stdin.as_deref(), Some("1 2\n"));
+        assert_eq!(loaded.test_cases[0].expected_output, "3\n");
+
+        let ids = store
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|problem| problem.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a_sum", "b-sum"]);
+
+        assert!(store.delete("a_sum").await.unwrap());
+        assert!(!store.delete("a_sum").await.unwrap());
+        assert!(store.load("a_sum").await.unwrap().is_none());
+        assert!(store.load("../a_sum").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_is_valid_id() {
+        assert!(ProblemStore::is_valid_id("two-sum_2"));
+        assert!(!ProblemStore::is_valid_id(""));
+        assert!(!ProblemStore::is_valid_id("Two-Sum"));
+        assert!(!ProblemStore::is_valid_id("../etc"));
+        assert!(!ProblemStore::is_valid_id(&"a".repeat(65)));
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    env,
+    str::FromStr,
+    sync::Mutex,
+    time::{
+        Instant,
+        SystemTime,
+    },
+};
+
+use crate::{
+    artifacts::unix_time,
+    error::{
+        Error,
+        Result,
+    },
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 3600;
+/// Buckets kept before the full ones, which hold no information, are dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// Requests allowed per minute, in bursts of up to `burst` requests. Zero disables the limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl Rate {
+    fn is_enabled(&self) -> bool {
+        self.per_minute > 0 && self.burst > 0
+    }
+
+    fn per_second(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaConfig {
+    /// Rate of each authenticated principal.
+    pub principal_rate: Rate,
+    /// Rate of each client address, whoever the principal is.
+    pub ip_rate: Rate,
+    /// Executions each principal may start per UTC day. Zero is unlimited.
+    pub daily_executions: u64,
+    /// Seconds of execution time each principal may use per UTC day. Zero is unlimited.
+    pub daily_cpu_seconds: u64,
+}
+
+impl QuotaConfig {
+    pub fn from_env() -> Self {
+        QuotaConfig {
+            principal_rate: Rate {
+                per_minute: env_number("RATE_LIMIT_PER_MINUTE").unwrap_or(30),
+                burst: env_number("RATE_LIMIT_BURST").unwrap_or(10),
+            },
+            ip_rate: Rate {
+                per_minute: env_number("IP_RATE_LIMIT_PER_MINUTE").unwrap_or(60),
+                burst: env_number("IP_RATE_LIMIT_BURST").unwrap_or(20),
+            },
+            daily_executions: env_number("DAILY_EXECUTIONS_QUOTA").unwrap_or(1000),
+            daily_cpu_seconds: env_number("DAILY_CPU_SECONDS_QUOTA").unwrap_or(3600),
+        }
+    }
+}
+
+fn env_number<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Time an execution ran for, in milliseconds, attached to its response to be counted against
+/// the daily quota.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionTime(pub u64);
+
+/// What a client has left, each field being `None` when the limit is disabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QuotaStatus {
+    pub rate_limit: Option<u32>,
+    pub rate_remaining: Option<u32>,
+    pub executions_remaining: Option<u64>,
+    pub cpu_seconds_remaining: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second()).min(rate.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// Seconds until a token is available.
+    fn wait_secs(&self, rate: &Rate) -> u64 {
+        ((1.0 - self.tokens) / rate.per_second()).ceil().max(1.0) as u64
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DailyUsage {
+    executions: u64,
+    cpu_ms: u64,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: HashMap<String, TokenBucket>,
+    /// UTC day the usage is counted for.
+    day: u64,
+    usage: HashMap<String, DailyUsage>,
+}
+
+/// Token-bucket rate limits and daily quotas of executions, kept in memory and so counted per
+/// server instance.
+pub struct Quotas {
+    config: QuotaConfig,
+    state: Mutex<State>,
+}
+
+impl Quotas {
+    pub fn new(config: QuotaConfig) -> Self {
+        Quotas {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Takes a token from the buckets of the principal and of the address and counts an
+    /// execution, or fails without taking anything if any of the limits is reached.
+    /// `principal` is `None` for anonymous requests, which are only limited by address and
+    /// whose daily quotas are counted by address.
+    pub fn admit(&self, principal: Option<&str>, ip: &str) -> Result<QuotaStatus> {
+        self.admit_at(principal, ip, Instant::now(), unix_time(SystemTime::now()))
+    }
+
+    fn admit_at(
+        &self,
+        principal: Option<&str>,
+        ip: &str,
+        now: Instant,
+        unix_secs: u64,
+    ) -> Result<QuotaStatus> {
+        let mut state = self.lock(unix_secs);
+        let ip_key = format!("ip:{}", ip);
+        let usage_key = usage_key(principal, ip);
+        let mut limited = vec![(ip_key, self.config.ip_rate)];
+        if let Some(principal) = principal {
+            limited.push((
+                format!("principal:{}", principal),
+                self.config.principal_rate,
+            ));
+        }
+        limited.retain(|(_, rate)| rate.is_enabled());
+
+        if state.buckets.len() > MAX_TRACKED_BUCKETS {
+            let config = &self.config;
+            state.buckets.retain(|key, bucket| {
+                let rate = if key.starts_with("ip:") {
+                    config.ip_rate
+                } else {
+                    config.principal_rate
+                };
+                bucket.refill(&rate, now);
+                bucket.tokens < rate.burst as f64
+            });
+        }
+
+        for (key, rate) in &limited {
+            let bucket = state.buckets.entry(key.clone()).or_insert(TokenBucket {
+                tokens: rate.burst as f64,
+                updated_at: now,
+            });
+            bucket.refill(rate, now);
+            if bucket.tokens < 1.0 {
+                return Err(Error::RateLimited {
+                    message: format!(
+                        "Rate limit of {} requests per minute exceeded",
+                        rate.per_minute
+                    ),
+                    retry_after_secs: bucket.wait_secs(rate),
+                });
+            }
+        }
+
+        let usage = state.usage.get(&usage_key).copied().unwrap_or_default();
+        let retry_after_secs = SECONDS_PER_DAY - unix_secs % SECONDS_PER_DAY;
+        if self.config.daily_executions > 0 && usage.executions >= self.config.daily_executions {
+            return Err(Error::DailyQuotaExceeded {
+                message: format!(
+                    "Daily quota of {} executions exceeded",
+                    self.config.daily_executions
+                ),
+                retry_after_secs,
+            });
+        }
+        if self.config.daily_cpu_seconds > 0 && usage.cpu_ms >= self.config.daily_cpu_seconds * 1000
+        {
+            return Err(Error::DailyQuotaExceeded {
+                message: format!(
+                    "Daily quota of {} CPU seconds exceeded",
+                    self.config.daily_cpu_seconds
+                ),
+                retry_after_secs,
+            });
+        }
+
+        for (key, _) in &limited {
+            if let Some(bucket) = state.buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        state.usage.entry(usage_key.clone()).or_default().executions += 1;
+        Ok(self.status_of(&state, principal, ip))
+    }
+
+    /// Counts the execution time of an admitted execution against the daily quota.
+    pub fn record_cpu(&self, principal: Option<&str>, ip: &str, cpu_ms: u64) -> QuotaStatus {
+        self.record_cpu_at(principal, ip, cpu_ms, unix_time(SystemTime::now()))
+    }
+
+    fn record_cpu_at(
+        &self,
+        principal: Option<&str>,
+        ip: &str,
+        cpu_ms: u64,
+        unix_secs: u64,
+    ) -> QuotaStatus {
+        let mut state = self.lock(unix_secs);
+        state
+            .usage
+            .entry(usage_key(principal, ip))
+            .or_default()
+            .cpu_ms += cpu_ms;
+        self.status_of(&state, principal, ip)
+    }
+
+    pub fn status(&self, principal: Option<&str>, ip: &str) -> QuotaStatus {
+        let state = self.lock(unix_time(SystemTime::now()));
+        self.status_of(&state, principal, ip)
+    }
+
+    /// Locks the state, starting over the daily usage on a new day.
+    fn lock(&self, unix_secs: u64) -> std::sync::MutexGuard<'_, State> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let day = unix_secs / SECONDS_PER_DAY;
+        if state.day != day {
+            state.day = day;
+            state.usage.clear();
+        }
+        state
+    }
+
+    fn status_of(&self, state: &State, principal: Option<&str>, ip: &str) -> QuotaStatus {
+        // The tightest of the buckets applying to the request.
+        let (rate, key) = match principal {
+            Some(principal) if self.config.principal_rate.is_enabled() => (
+                self.config.principal_rate,
+                format!("principal:{}", principal),
+            ),
+            _ => (self.config.ip_rate, format!("ip:{}", ip)),
+        };
+        let usage = state
+            .usage
+            .get(&usage_key(principal, ip))
+            .copied()
+            .unwrap_or_default();
+        let limit = |quota: u64, used: u64| (quota > 0).then(|| quota.saturating_sub(used));
+
+        QuotaStatus {
+            rate_limit: rate.is_enabled().then_some(rate.burst),
+            rate_remaining: rate.is_enabled().then(|| {
+                state
+                    .buckets
+                    .get(&key)
+                    .map_or(rate.burst, |bucket| bucket.tokens.max(0.0) as u32)
+            }),
+            executions_remaining: limit(self.config.daily_executions, usage.executions),
+            cpu_seconds_remaining: limit(self.config.daily_cpu_seconds, usage.cpu_ms / 1000),
+        }
+    }
+}
+
+fn usage_key(principal: Option<&str>, ip: &str) -> String {
+    match principal {
+        Some(principal) => format!("principal:{}", principal),
+        None => format!("ip:{}", ip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limits_and_daily_quotas() {
+        let quotas = Quotas::new(QuotaConfig {
+            principal_rate: Rate {
+                per_minute: 60,
+                burst: 2,
+            },
+            ip_rate: Rate {
+                per_minute: 60,
+                burst: 3,
+            },
+            daily_executions: 4,
+            daily_cpu_seconds: 10,
+        });
+        let start = Instant::now();
+        let day = 20_000 * SECONDS_PER_DAY;
+
+        let status = quotas.admit_at(Some("ci"), "1.2.3.4", start, day).unwrap();
+        assert_eq!(status.rate_limit, Some(2));
+        assert_eq!(status.rate_remaining, Some(1));
+        assert_eq!(status.executions_remaining, Some(3));
+        quotas.admit_at(Some("ci"), "1.2.3.4", start, day).unwrap();
+        match quotas.admit_at(Some("ci"), "1.2.3.4", start, day) {
+            Err(Error::RateLimited {
+                retry_after_secs, ..
+            }) => assert_eq!(retry_after_secs, 1),
+            result => panic!("unexpected {:?}", result),
+        }
+        // The address has one token left, the rejected request having taken none.
+        quotas.admit_at(None, "1.2.3.4", start, day).unwrap();
+        assert!(quotas.admit_at(None, "1.2.3.4", start, day).is_err());
+
+        let later = start + Duration::from_secs(2);
+        quotas.admit_at(Some("ci"), "5.6.7.8", later, day).unwrap();
+        assert_eq!(
+            quotas
+                .record_cpu_at(Some("ci"), "5.6.7.8", 10_500, day)
+                .cpu_seconds_remaining,
+            Some(0)
+        );
+        match quotas.admit_at(Some("ci"), "5.6.7.8", later, day + 60) {
+            Err(Error::DailyQuotaExceeded {
+                retry_after_secs, ..
+            }) => assert_eq!(retry_after_secs, SECONDS_PER_DAY - 60),
+            result => panic!("unexpected {:?}", result),
+        }
+        // Usage starts over the next day.
+        let status = quotas
+            .admit_at(Some("ci"), "5.6.7.8", later, day + SECONDS_PER_DAY)
+            .unwrap();
+        assert_eq!(status.executions_remaining, Some(3));
+        assert_eq!(status.cpu_seconds_remaining, Some(10));
+    }
+}
@@ -0,0 +1,311 @@
+use futures_util::future::BoxFuture;
+use opentelemetry::{
+    global,
+    propagation::{
+        Extractor,
+        Injector,
+    },
+    trace::{
+        TraceContextExt,
+        TracerProvider as _,
+    },
+    KeyValue,
+};
+use opentelemetry_sdk::{
+    export::trace::{
+        ExportResult,
+        SpanData,
+        SpanExporter,
+    },
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::TracerProvider,
+    Resource,
+};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{
+        self,
+        Write as _,
+    },
+    io::{
+        self,
+        Write,
+    },
+    time::UNIX_EPOCH,
+};
+use tracing::field::{
+    Field,
+    Visit,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    layer::{
+        self,
+        SubscriberExt,
+    },
+    Layer,
+};
+
+const DEFAULT_SERVICE_NAME: &str = "dyno_code";
+
+/// Where the spans are sent, read from `OTEL_TRACES_EXPORTER`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exporter {
+    /// Spans are not recorded.
+    None,
+    /// Batches sent to the OTLP gRPC collector of `OTEL_EXPORTER_OTLP_ENDPOINT`, by default
+    /// `http://localhost:4317`.
+    Otlp,
+    /// One JSON line per span on the standard output.
+    Stdout,
+}
+
+impl Exporter {
+    pub fn from_env() -> io::Result<Self> {
+        match env::var("OTEL_TRACES_EXPORTER").unwrap_or_default().trim() {
+            "" | "none" => Ok(Exporter::None),
+            "otlp" => Ok(Exporter::Otlp),
+            "stdout" => Ok(Exporter::Stdout),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "OTEL_TRACES_EXPORTER must be none, otlp or stdout, not '{}'",
+                    other
+                ),
+            )),
+        }
+    }
+}
+
+/// Flushes the spans left when the server stops.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            // Logged with `log` rather than `tracing`: the global logger lives for the whole
+            // process, while the spans of `tracing` would go to the provider shutting down.
+            if let Err(e) = provider.shutdown() {
+                log::warn!("Failed to flush the remaining spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Records the `tracing` spans of the server as OpenTelemetry spans sent to the configured
+/// exporter, W3C trace context headers of the requests linking them to their callers.
+pub fn init() -> io::Result<Telemetry> {
+    let exporter = Exporter::from_env()?;
+    if exporter == Exporter::None {
+        // Without a subscriber, `tracing` would write the spans to the logs as well.
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(LogEvents))
+            .map_err(io::Error::other)?;
+        return Ok(Telemetry { provider: None });
+    }
+
+    let service_name =
+        env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    let builder = TracerProvider::builder()
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]));
+    let provider = match exporter {
+        Exporter::Otlp => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .build()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            builder.with_batch_exporter(exporter, runtime::Tokio)
+        }
+        _ => builder.with_simple_exporter(StdoutExporter),
+    }
+    .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let subscriber = tracing_subscriber::registry()
+        .with(LogEvents)
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME)));
+    tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)?;
+
+    Ok(Telemetry {
+        provider: Some(provider),
+    })
+}
+
+/// Makes `span` a child of the span of the caller, if the headers carry one.
+pub fn set_remote_parent(span: &tracing::Span, headers: &actix_web::http::header::HeaderMap) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    if parent.span().span_context().is_valid() {
+        span.set_parent(parent);
+    }
+}
+
+/// Trace id of the current span, `None` when tracing is disabled.
+pub fn current_trace_id() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+/// `traceparent` header of the current span, for the processes continuing the trace.
+pub fn current_traceparent() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let mut fields = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut FieldInjector(&mut fields))
+    });
+    fields.remove("traceparent")
+}
+
+/// Writes the events of the dependencies using `tracing`, like actix-server and kube, to the
+/// logs.
+struct LogEvents;
+
+impl<S: tracing::Subscriber> Layer<S> for LogEvents {
+    fn on_event(&self, event: &tracing::Event<'_>, _: layer::Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            tracing::Level::ERROR => log::Level::Error,
+            tracing::Level::WARN => log::Level::Warn,
+            tracing::Level::INFO => log::Level::Info,
+            tracing::Level::DEBUG => log::Level::Debug,
+            tracing::Level::TRACE => log::Level::Trace,
+        };
+        let log_metadata = log::Metadata::builder()
+            .level(level)
+            .target(metadata.target())
+            .build();
+        if !log::logger().enabled(&log_metadata) {
+            return;
+        }
+
+        let mut message = EventMessage::default();
+        event.record(&mut message);
+        log::logger().log(
+            &log::Record::builder()
+                .metadata(log_metadata)
+                .args(format_args!("{}", message.0))
+                .module_path(metadata.module_path())
+                .file(metadata.file())
+                .line(metadata.line())
+                .build(),
+        );
+    }
+}
+
+/// Message of an event followed by its other fields, as `name=value`.
+#[derive(Default)]
+struct EventMessage(String);
+
+impl Visit for EventMessage {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+struct FieldInjector<'a>(&'a mut HashMap<String, String>);
+
+impl Injector for FieldInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+/// Writes the spans as JSON lines, for running without a collector.
+#[derive(Debug)]
+struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let mut stdout = std::io::stdout().lock();
+        for span in &batch {
+            let _ = writeln!(stdout, "{}", span_json(span));
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn span_json(span: &SpanData) -> serde_json::Value {
+    let micros = |time: std::time::SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_micros() as u64)
+    };
+    let attributes = span
+        .attributes
+        .iter()
+        .map(|attribute| (attribute.key.to_string(), attribute.value.to_string()))
+        .collect::<HashMap<_, _>>();
+    json!({
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "name": span.name,
+        "start_time_us": micros(span.start_time),
+        "duration_us": micros(span.end_time).saturating_sub(micros(span.start_time)),
+        "attributes": attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+    };
+
+    #[test]
+    fn test_trace_context_propagation() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(current_trace_id(), None);
+
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                HeaderName::from_static("traceparent"),
+                HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            );
+            let span = tracing::info_span!("request");
+            set_remote_parent(&span, &headers);
+            let _entered = span.enter();
+            assert_eq!(
+                current_trace_id().as_deref(),
+                Some("4bf92f3577b34da6a3ce929d0e0e4736")
+            );
+            let traceparent = current_traceparent().unwrap();
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert!(!traceparent.contains("00f067aa0ba902b7"));
+        });
+    }
+}
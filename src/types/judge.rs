@@ -0,0 +1,228 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use utoipa::ToSchema;
+
+use super::{
+    default_output_extension,
+    BatchPayload,
+    ExecutionResult,
+    ExecutionStatus,
+    TestCase,
+};
+
+/// How the output of a test case is compared with the expected one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ComparisonMode {
+    #[default]
+    Exact,
+    IgnoreWhitespace,
+    /// Tokens parsed as floats match when their absolute or relative difference is within
+    /// `epsilon`, other tokens must be equal.
+    FloatTolerance {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+    },
+    UnorderedLines,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct JudgePayload {
+    pub language: String,
+    pub code: String,
+    pub test_cases: Vec<JudgeTestCase>,
+    #[serde(default)]
+    pub comparison: ComparisonMode,
+    /// Program deciding the verdict instead of `comparison`, for problems accepting several
+    /// answers. It runs once per case with the test case on its stdin and `INPUT_PATH`, like the
+    /// submission, and with the paths of the contestant output and of the expected output as
+    /// arguments. It prints its result in the [`Program`] verdict format.
+    #[serde(default)]
+    pub checker: Option<Program>,
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    #[serde(default)]
+    pub execution_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JudgeTestCase {
+    #[serde(flatten)]
+    pub input: TestCase,
+    pub expected_output: String,
+}
+
+/// Judge side program, checker or interactor. Its verdict is made of `accepted` or
+/// `wrong_answer` on a first line, optionally a score between 0 and 1 on the second one and a
+/// message on the following ones.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Program {
+    pub language: String,
+    pub code: String,
+}
+
+/// Problem where the submission talks with an interactor over its stdin and stdout. The
+/// interactor gets the path of the case input and of the file to write its verdict to as
+/// arguments. It runs in its own container, as another user, so the submission can neither read
+/// these files nor stop the interactor, and may be written in another language.
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct InteractivePayload {
+    pub language: String,
+    pub code: String,
+    pub interactor: Program,
+    pub test_cases: Vec<InteractiveTestCase>,
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    #[serde(default)]
+    pub execution_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct InteractiveTestCase {
+    /// Read by the interactor only, never shown to the submission.
+    pub input: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    RuntimeError,
+    CompileError,
+    /// The checker or the interactor failed, or printed an invalid verdict.
+    CheckerError,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JudgeCaseResult {
+    pub verdict: Verdict,
+    pub score: f64,
+    pub diff: Option<String>,
+    pub checker_message: Option<String>,
+    pub result: ExecutionResult,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JudgeResult {
+    pub execution_id: String,
+    /// Verdict of the first failing case, `accepted` when every case passed.
+    pub verdict: Verdict,
+    pub passed: usize,
+    pub total: usize,
+    pub score: f64,
+    pub compile_error: Option<String>,
+    pub cases: Vec<JudgeCaseResult>,
+}
+
+impl JudgePayload {
+    /// Splits the payload into the batch to execute and the expected outputs of its cases.
+    pub fn into_batch(self) -> (BatchPayload, Vec<String>, Option<Program>) {
+        let (cases, expected_outputs) = self
+            .test_cases
+            .into_iter()
+            .map(|test_case| (test_case.input, test_case.expected_output))
+            .unzip();
+
+        let batch = BatchPayload {
+            language: self.language,
+            code: self.code,
+            output_extension: default_output_extension(),
+            cases,
+            parallelism: self.parallelism,
+            time_limit_ms: self.time_limit_ms,
+            memory_limit_mb: self.memory_limit_mb,
+            execution_id: self.execution_id,
+            tags: Vec::new(),
+            interactor: None,
+            stage: None,
+        };
+        (batch, expected_outputs, self.checker)
+    }
+}
+
+impl InteractivePayload {
+    /// Builds the batch running the submission against the interactor, the input of each case
+    /// being handed to the interactor through the stdin file of the case.
+    pub fn into_batch(self) -> BatchPayload {
+        BatchPayload {
+            language: self.language,
+            code: self.code,
+            output_extension: default_output_extension(),
+            cases: self
+                .test_cases
+                .into_iter()
+                .map(|test_case| TestCase {
+                    stdin: Some(test_case.input),
+                    ..Default::default()
+                })
+                .collect(),
+            parallelism: self.parallelism,
+            time_limit_ms: self.time_limit_ms,
+            memory_limit_mb: self.memory_limit_mb,
+            execution_id: self.execution_id,
+            tags: Vec::new(),
+            interactor: Some(self.interactor),
+            stage: None,
+        }
+    }
+}
+
+fn default_epsilon() -> f64 {
+    1e-6
+}
+
+/// Runs `generator`, `brute_force` and `solution` in rounds until their outputs differ or the
+/// time budget is spent. The generator gets a seed on its stdin and prints a test input, which is
+/// then given to both other programs on their stdin.
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct StressPayload {
+    pub generator: Program,
+    pub brute_force: Program,
+    pub solution: Program,
+    #[serde(default)]
+    pub comparison: ComparisonMode,
+    #[serde(default)]
+    pub time_budget_ms: Option<u64>,
+    /// Tests of the first round, each following round running twice as many.
+    #[serde(default)]
+    pub tests_per_round: Option<usize>,
+    /// Time limit of the solution on each test, the brute force is only bounded by the budget.
+    /// Both are capped so that each round fits in what remains of the budget.
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub execution_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Counterexample {
+    pub seed: u64,
+    pub input: String,
+    pub expected_output: String,
+    pub case: JudgeCaseResult,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct StressResult {
+    pub execution_id: String,
+    pub status: ExecutionStatus,
+    pub tests_run: usize,
+    pub elapsed_ms: u64,
+    /// First test on which the solution did not match the brute force, if any.
+    pub counterexample: Option<Counterexample>,
+    /// Why the run stopped early, e.g. the generator failing.
+    pub error: Option<String>,
+}
@@ -1,20 +1,321 @@
 use serde::{
+    de::Error as _,
     Deserialize,
+    Deserializer,
     Serialize,
 };
+use utoipa::ToSchema;
 
-#[derive(Deserialize, Debug)]
+mod judge;
+mod problem;
+pub use judge::*;
+pub use problem::*;
+
+pub const DEFAULT_OUTPUT_EXTENSION: &str = ".txt";
+
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct ExecutionPayload {
     pub language: String,
     pub code: String,
+    #[serde(default = "default_output_extension")]
+    pub output_extension: String,
+    /// Where the server put the input file for the executor, see [`reject_input_file_path`].
+    #[serde(default, deserialize_with = "reject_input_file_path")]
+    #[schema(ignore)]
+    pub input_file_path: Option<String>,
+    #[serde(default)]
+    pub input_file: Option<InputFile>,
+    /// Id of a file uploaded to `POST /files`.
+    #[serde(default)]
+    pub input_file_id: Option<String>,
+    /// Client chosen UUID, so that a running execution can be cancelled before it answers.
+    #[serde(default)]
+    pub execution_id: Option<String>,
+    /// Labels kept in the execution history, to find the execution with `GET /executions`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Input file sent inline in a JSON request, `content` being base64 encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct InputFile {
+    pub filename: Option<String>,
+    pub content: String,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct BatchPayload {
+    pub language: String,
+    pub code: String,
+    #[serde(default = "default_output_extension")]
     pub output_extension: String,
+    /// At most 1000 cases.
+    pub cases: Vec<TestCase>,
+    /// Number of pods the cases are spread over, each of them compiling the program once.
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    /// Wall time allowed to each case, the program is killed once it is exceeded. Defaults to
+    /// the maximum, 30 seconds.
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default)]
+    pub execution_id: Option<String>,
+    /// Labels kept in the execution history, see [`ExecutionPayload::tags`].
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Program run alongside every case, see [`InteractivePayload`].
+    #[serde(skip)]
+    pub interactor: Option<Program>,
+    /// Distinguishes the Jobs of the batches run under the same execution id, e.g. the checker
+    /// of a judged submission.
+    #[serde(skip)]
+    pub stage: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct TestCase {
+    #[serde(default)]
+    pub stdin: Option<String>,
+    /// Where the server put the input file for the executor, see [`reject_input_file_path`].
+    #[serde(default, deserialize_with = "reject_input_file_path")]
+    #[schema(ignore)]
     pub input_file_path: Option<String>,
+    #[serde(default)]
+    pub input_file: Option<InputFile>,
+    #[serde(default)]
+    pub input_file_id: Option<String>,
+    /// Contents written to files whose paths are given to the program as arguments.
+    #[serde(skip)]
+    pub arguments: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Outcome of one of the checks of `GET /ready`.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub ok: bool,
+    /// What was found, or why the check failed.
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ReadinessReport {
+    /// Whether every check passed.
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+/// Route an execution was requested through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionKind {
+    /// `POST /execute`.
+    Single,
+    /// `POST /execute/batch`.
+    Batch,
+    /// `POST /judge`.
+    Judge,
+    /// `POST /judge/interactive`.
+    Interactive,
+    /// `POST /stress`.
+    Stress,
+    /// `POST /problems/{id}/submissions`.
+    Submission,
+}
+
+impl ExecutionKind {
+    /// The kind as serialized, e.g. `batch`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionKind::Single => "single",
+            ExecutionKind::Batch => "batch",
+            ExecutionKind::Judge => "judge",
+            ExecutionKind::Interactive => "interactive",
+            ExecutionKind::Stress => "stress",
+            ExecutionKind::Submission => "submission",
+        }
+    }
+}
+
+/// Past execution kept in the history.
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ExecutionRecord {
+    pub execution_id: String,
+    pub kind: ExecutionKind,
+    /// Id of the principal the execution ran for.
+    pub principal: String,
+    pub language: String,
+    pub language_version: Option<String>,
+    /// Hex encoded SHA-256 of the code.
+    pub code_sha256: String,
+    pub tags: Vec<String>,
+    pub time_limit_ms: Option<u64>,
+    pub memory_limit_mb: Option<u64>,
+    /// `completed`, `cancelled`, or the code of the error the execution failed with.
+    pub outcome: String,
+    /// Exit code of the program, of the first failed case for a batch.
+    pub exit_code: Option<i32>,
+    /// Unix timestamp, in seconds.
+    pub started_at: u64,
+    /// Time from the request to the result.
+    pub duration_ms: u64,
+    /// Time the program ran for, summed over the cases of a batch.
+    pub run_ms: Option<u64>,
+    /// Size of the standard outputs and of the files written.
+    pub output_bytes: u64,
+    /// Number of cases of a batch.
+    pub cases: Option<u64>,
+}
+
+/// Page of `GET /executions`, newest first.
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ExecutionPage {
+    pub executions: Vec<ExecutionRecord>,
+    /// Executions matching the filters, across every page.
+    pub total: u64,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a slot in the execution queue.
+    Queued,
+    Running,
+}
+
+/// Progress of an execution that has not finished yet.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct JobInfo {
+    pub execution_id: String,
+    pub state: JobState,
+    /// Position in the execution queue, starting at 1, while queued.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct ExecutionResult {
     pub error: String,
     pub output: String,
+    /// Always null, kept for older clients: the output file is deleted once stored as
+    /// `output_file`.
     pub output_file_path: Option<String>,
+    /// Always null, kept for older clients: the output file is stored as `output_file`.
     pub output_file_content: Option<String>,
+    pub status: ExecutionStatus,
+    pub exit_code: Option<i32>,
+    pub time_ms: Option<u64>,
+    /// Time counted against the daily quota instead of `time_ms`, when the latter comes from the
+    /// container of the program, which could forge it.
+    #[serde(skip)]
+    pub billed_ms: Option<u64>,
+    /// File the program wrote to `OUTPUT_PATH`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_file: Option<OutputFile>,
+    /// Files the program wrote to `OUTPUT_DIR`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output_files: Vec<OutputFile>,
+    /// Whether files of `OUTPUT_DIR` were left out of `output_files`, being too many or too
+    /// large.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub output_files_truncated: bool,
+}
+
+/// Entry of the manifest of the files written by a program.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct OutputFile {
+    /// Path relative to `OUTPUT_DIR`.
+    pub name: String,
+    pub size: u64,
+    pub mime_type: String,
+    /// Hex encoded SHA-256 of the content.
+    pub sha256: String,
+    pub artifact_id: String,
+    /// Where the file can be downloaded from until `expires_at`.
+    pub url: String,
+    /// Unix timestamp, in seconds.
+    pub expires_at: u64,
+}
+
+/// Input file uploaded to `POST /files`, referenced by its `file_id` in executions.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct StoredFile {
+    pub file_id: String,
+    /// Name it was last uploaded with.
+    pub name: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the content, which is also the id of the file.
+    pub sha256: String,
+    /// Unix timestamp, in seconds, uploading the same content again pushing it back.
+    pub expires_at: u64,
+    /// Principal that uploaded it, the only one that may use it.
+    pub owner: String,
+}
+
+/// Response of a single execution.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ExecutionResponse {
+    pub execution_id: String,
+    pub status: ExecutionStatus,
+    pub output: String,
+    pub error: String,
+    /// Always null, kept for older clients: the output file is stored as `output_file`.
+    pub output_file_path: Option<String>,
+    /// File the program wrote to `OUTPUT_PATH`, to download from its `url`.
+    pub output_file: Option<OutputFile>,
+    pub output_files: Vec<OutputFile>,
+    /// Whether files of `OUTPUT_DIR` were left out of `output_files`, being too many or too
+    /// large.
+    pub output_files_truncated: bool,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BatchResult {
+    pub execution_id: String,
+    pub status: ExecutionStatus,
+    /// Error reported before any case could run, typically a compilation error.
+    pub compile_error: Option<String>,
+    pub results: Vec<ExecutionResult>,
+    /// Results of the interactor for each case, its output being the verdict it wrote.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub interactor_results: Vec<ExecutionResult>,
+}
+
+fn default_output_extension() -> String {
+    DEFAULT_OUTPUT_EXTENSION.to_string()
+}
+
+/// Input files are only ever placed by the server: clients send them inline or by id, a path
+/// would let them read any file of the shared volume.
+fn reject_input_file_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(_) => Err(D::Error::custom(
+            "field 'input_file_path' is not accepted, send the file in 'input_file' or upload it \
+             to POST /files and use 'input_file_id'",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Empty or `"null"` extensions sent by the front fall back to the default one.
+pub fn normalize_output_extension(extension: &str) -> String {
+    match extension.trim() {
+        "" | "null" => default_output_extension(),
+        extension => extension.to_string(),
+    }
 }
@@ -0,0 +1,125 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use utoipa::ToSchema;
+
+use super::{
+    ComparisonMode,
+    JudgePayload,
+    JudgeResult,
+    JudgeTestCase,
+    Program,
+    Verdict,
+};
+
+/// Problem of the catalog, uploaded by an admin. Its test cases and checker are hidden: only its
+/// [`ProblemSummary`] is shown to users, who submit solutions graded against it.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Problem {
+    /// Taken from the URL the problem is uploaded to.
+    #[serde(default)]
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub statement: String,
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default)]
+    pub comparison: ComparisonMode,
+    #[serde(default)]
+    pub checker: Option<Program>,
+    pub test_cases: Vec<JudgeTestCase>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ProblemSummary {
+    pub id: String,
+    pub title: String,
+    pub statement: String,
+    pub time_limit_ms: Option<u64>,
+    pub memory_limit_mb: Option<u64>,
+    pub test_count: usize,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct SubmissionPayload {
+    pub language: String,
+    pub code: String,
+    #[serde(default)]
+    pub execution_id: Option<String>,
+}
+
+/// [`JudgeResult`] without the outputs and diffs, which would reveal the hidden test cases.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SubmissionResult {
+    pub execution_id: String,
+    pub problem_id: String,
+    pub verdict: Verdict,
+    pub passed: usize,
+    pub total: usize,
+    pub score: f64,
+    pub compile_error: Option<String>,
+    pub cases: Vec<SubmissionCaseResult>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SubmissionCaseResult {
+    pub verdict: Verdict,
+    pub score: f64,
+    pub exit_code: Option<i32>,
+    pub time_ms: Option<u64>,
+}
+
+impl Problem {
+    pub fn summary(&self) -> ProblemSummary {
+        ProblemSummary {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            statement: self.statement.clone(),
+            time_limit_ms: self.time_limit_ms,
+            memory_limit_mb: self.memory_limit_mb,
+            test_count: self.test_cases.len(),
+        }
+    }
+
+    pub fn into_judge(self, submission: SubmissionPayload) -> JudgePayload {
+        JudgePayload {
+            language: submission.language,
+            code: submission.code,
+            test_cases: self.test_cases,
+            comparison: self.comparison,
+            checker: self.checker,
+            time_limit_ms: self.time_limit_ms,
+            memory_limit_mb: self.memory_limit_mb,
+            parallelism: None,
+            execution_id: submission.execution_id,
+        }
+    }
+}
+
+impl SubmissionResult {
+    pub fn new(problem_id: String, result: JudgeResult) -> Self {
+        SubmissionResult {
+            execution_id: result.execution_id,
+            problem_id,
+            verdict: result.verdict,
+            passed: result.passed,
+            total: result.total,
+            score: result.score,
+            compile_error: result.compile_error,
+            cases: result
+                .cases
+                .into_iter()
+                .map(|case| SubmissionCaseResult {
+                    verdict: case.verdict,
+                    score: case.score,
+                    exit_code: case.result.exit_code,
+                    time_ms: case.result.time_ms,
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{
+        forward_ready,
+        Payload,
+        Service,
+        ServiceRequest,
+        ServiceResponse,
+        Transform,
+    },
+    FromRequest,
+    HttpMessage,
+    HttpRequest,
+    ResponseError,
+};
+use futures_util::future::{
+    ready,
+    LocalBoxFuture,
+    Ready,
+};
+use std::{
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    auth::{
+        AuthConfig,
+        Principal,
+    },
+    error::Error,
+    executor::queue::with_principal,
+};
+
+/// Routes served without credentials, credentials sent to them being ignored.
+const PUBLIC_PATHS: [&str; 7] = [
+    "/health",
+    "/ready",
+    "/metrics",
+    "/version",
+    "/languages",
+    "/swagger-ui",
+    "/api-docs",
+];
+
+fn is_public(path: &str) -> bool {
+    PUBLIC_PATHS.iter().any(|public| {
+        path.strip_prefix(public)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Authenticates each request, attaching its [`Principal`] to it or answering 401.
+pub struct Authentication {
+    config: Arc<AuthConfig>,
+}
+
+impl Authentication {
+    pub fn new(config: Arc<AuthConfig>) -> Self {
+        Authentication { config }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+    config: Arc<AuthConfig>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let principal = if is_public(req.path()) {
+            Ok(Principal::anonymous())
+        } else {
+            match self.config.authenticate(req.headers()) {
+                Ok(Some(principal)) => Ok(principal),
+                Ok(None) if !self.config.required => Ok(Principal::anonymous()),
+                Ok(None) => Err(Error::Unauthorized(
+                    "An API key or a bearer token is required".to_string(),
+                )),
+                Err(e) => Err(e),
+            }
+        };
+
+        match principal {
+            Ok(principal) => {
+                let id = principal.id.clone();
+                req.extensions_mut().insert(principal);
+                let service = self.service.clone();
+                Box::pin(with_principal(id, async move {
+                    Ok(service.call(req).await?.map_into_left_body())
+                }))
+            }
+            // Answered rather than failed, so that the CORS headers are still added.
+            Err(e) => {
+                let response = req.into_response(e.error_response()).map_into_right_body();
+                Box::pin(ready(Ok(response)))
+            }
+        }
+    }
+}
+
+/// The principal attached by [`Authentication`], anonymous on routes it does not cover.
+impl FromRequest for Principal {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(req
+            .extensions()
+            .get::<Principal>()
+            .cloned()
+            .unwrap_or_else(Principal::anonymous)))
+    }
+}
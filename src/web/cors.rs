@@ -0,0 +1,267 @@
+use actix_cors::Cors;
+use actix_web::http::{
+    header::HeaderName,
+    Method,
+};
+use serde::Deserialize;
+
+use super::{
+    rate_limit::QUOTA_HEADERS,
+    request_id::REQUEST_ID_HEADER,
+};
+use std::{
+    collections::HashMap,
+    env,
+    io,
+};
+
+const DEFAULT_ALLOWED_ORIGINS: &str =
+    "http://localhost:5173,https://code-valley.xyz,https://*.code-valley.xyz";
+const DEFAULT_ALLOWED_METHODS: &str = "GET,POST,PUT,DELETE";
+const DEFAULT_ALLOWED_HEADERS: &str =
+    "authorization,x-api-key,accept,content-type,traceparent,x-request-id";
+const DEFAULT_MAX_AGE_SECS: usize = 3600;
+
+/// Cross-origin requests accepted by the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsPolicy {
+    /// Exact origins, like `https://code-valley.xyz`, origins of any subdomain, like
+    /// `https://*.code-valley.xyz`, or `*` for every origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods, or `*` for every method.
+    pub allowed_methods: Vec<String>,
+    /// Request headers, or `*` for every header.
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    pub max_age_secs: usize,
+}
+
+/// Settings of a tenant replacing the ones of the default policy.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CorsOverride {
+    pub allowed_origins: Option<Vec<String>>,
+    pub allowed_methods: Option<Vec<String>>,
+    pub allowed_headers: Option<Vec<String>>,
+    pub allow_credentials: Option<bool>,
+    pub max_age_secs: Option<usize>,
+}
+
+/// Default policy and the policies of the tenants, each tenant being a host name the API is
+/// reached at.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    pub default: CorsPolicy,
+    pub tenants: Vec<(String, CorsPolicy)>,
+}
+
+impl CorsConfig {
+    /// Reads the default policy from the `CORS_*` variables and the overrides of the tenants
+    /// from the JSON object of `CORS_TENANTS_FILE`, keyed by host name.
+    pub fn from_env() -> io::Result<Self> {
+        let list = |name: &str, default: &str| {
+            env::var(name)
+                .unwrap_or_else(|_| default.to_string())
+                .split(',')
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect()
+        };
+        let default = CorsPolicy {
+            allowed_origins: list("CORS_ALLOWED_ORIGINS", DEFAULT_ALLOWED_ORIGINS),
+            allowed_methods: list("CORS_ALLOWED_METHODS", DEFAULT_ALLOWED_METHODS),
+            allowed_headers: list("CORS_ALLOWED_HEADERS", DEFAULT_ALLOWED_HEADERS),
+            allow_credentials: env::var("CORS_ALLOW_CREDENTIALS")
+                .map(|value| value.trim() == "true")
+                .unwrap_or(true),
+            max_age_secs: env::var("CORS_MAX_AGE_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_AGE_SECS),
+        };
+
+        let overrides = match env::var("CORS_TENANTS_FILE") {
+            Ok(path) if !path.trim().is_empty() => {
+                serde_json::from_slice(&std::fs::read(path.trim())?)?
+            }
+            _ => HashMap::new(),
+        };
+        let config = Self::new(default, overrides);
+        config.default.validate()?;
+        for (_, policy) in &config.tenants {
+            policy.validate()?;
+        }
+        Ok(config)
+    }
+
+    pub fn new(default: CorsPolicy, overrides: HashMap<String, CorsOverride>) -> Self {
+        let mut tenants = overrides
+            .into_iter()
+            .map(|(host, tenant)| {
+                let policy = CorsPolicy {
+                    allowed_origins: tenant
+                        .allowed_origins
+                        .unwrap_or_else(|| default.allowed_origins.clone()),
+                    allowed_methods: tenant
+                        .allowed_methods
+                        .unwrap_or_else(|| default.allowed_methods.clone()),
+                    allowed_headers: tenant
+                        .allowed_headers
+                        .unwrap_or_else(|| default.allowed_headers.clone()),
+                    allow_credentials: tenant
+                        .allow_credentials
+                        .unwrap_or(default.allow_credentials),
+                    max_age_secs: tenant.max_age_secs.unwrap_or(default.max_age_secs),
+                };
+                (host.to_ascii_lowercase(), policy)
+            })
+            .collect::<Vec<_>>();
+        tenants.sort_by(|a, b| a.0.cmp(&b.0));
+        CorsConfig { default, tenants }
+    }
+}
+
+impl CorsPolicy {
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        self.allowed_origins
+            .iter()
+            .any(|rule| origin_matches(&rule.to_ascii_lowercase(), &origin))
+    }
+
+    /// Checks the methods and headers, which would otherwise only fail once the server starts.
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |kind: &str, value: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid CORS {}: {}", kind, value),
+            )
+        };
+        for method in self.allowed_methods.iter().filter(|method| *method != "*") {
+            Method::from_bytes(method.as_bytes()).map_err(|_| invalid("method", method))?;
+        }
+        for header in self.allowed_headers.iter().filter(|header| *header != "*") {
+            HeaderName::from_bytes(header.as_bytes()).map_err(|_| invalid("header", header))?;
+        }
+        // Browsers refuse credentials with `*`, and echoing any origin instead would let every
+        // site make credentialed requests.
+        if self.allow_credentials && self.allowed_origins.iter().any(|origin| origin == "*") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "CORS credentials cannot be allowed for any origin (`*`)",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn middleware(&self) -> Cors {
+        let mut cors = Cors::default().max_age(self.max_age_secs);
+
+        if self.allowed_origins.iter().any(|origin| origin == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            let policy = self.clone();
+            cors = cors.allowed_origin_fn(move |origin, _req_head| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| policy.allows_origin(origin))
+            });
+        }
+
+        if self.allowed_methods.iter().any(|method| method == "*") {
+            cors = cors.allow_any_method();
+        } else {
+            cors = cors.allowed_methods(self.allowed_methods.iter().map(String::as_str));
+        }
+
+        if self.allowed_headers.iter().any(|header| header == "*") {
+            cors = cors.allow_any_header();
+        } else {
+            cors = cors.allowed_headers(self.allowed_headers.iter().map(String::as_str));
+        }
+
+        cors = cors
+            .expose_headers(QUOTA_HEADERS)
+            .expose_headers([REQUEST_ID_HEADER]);
+
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+/// Whether the origin matches the rule, a rule like `https://*.example.com` matching the origins
+/// of the subdomains of `example.com` but not `https://example.com` itself.
+fn origin_matches(rule: &str, origin: &str) -> bool {
+    if rule == "*" {
+        return true;
+    }
+
+    match rule.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|origin| origin.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .and_then(|subdomain| subdomain.strip_suffix('.'))
+            .is_some_and(|subdomain| {
+                !subdomain.is_empty()
+                    && subdomain
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            }),
+        None => rule == origin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_rules_and_overrides() {
+        let default = CorsPolicy {
+            allowed_origins: vec![
+                "http://localhost:5173".to_string(),
+                "https://*.code-valley.xyz".to_string(),
+            ],
+            allowed_methods: vec!["GET".to_string()],
+            allowed_headers: vec!["content-type".to_string()],
+            allow_credentials: true,
+            max_age_secs: 60,
+        };
+        assert!(default.allows_origin("http://localhost:5173"));
+        assert!(default.allows_origin("https://app.code-valley.xyz"));
+        assert!(default.allows_origin("https://a.b.Code-Valley.xyz"));
+        assert!(!default.allows_origin("https://code-valley.xyz"));
+        assert!(!default.allows_origin("https://evilcode-valley.xyz"));
+        assert!(!default.allows_origin("http://app.code-valley.xyz"));
+        assert!(!default.allows_origin("https://code-valley.xyz.evil.com"));
+        assert!(!default.allows_origin("http://evil.com:5173"));
+
+        let overrides = serde_json::from_str(
+            r#"{ "API.tenant.com": { "allowed_origins": ["https://tenant.com"], "allow_credentials": false } }"#,
+        )
+        .unwrap();
+        let config = CorsConfig::new(default.clone(), overrides);
+        let (host, tenant) = &config.tenants[0];
+        assert_eq!(host, "api.tenant.com");
+        assert!(tenant.allows_origin("https://tenant.com"));
+        assert!(!tenant.allows_origin("http://localhost:5173"));
+        assert!(!tenant.allow_credentials);
+        assert_eq!(tenant.allowed_methods, default.allowed_methods);
+
+        assert!(default.validate().is_ok());
+        let any_origin = CorsPolicy {
+            allowed_origins: vec!["*".to_string()],
+            ..default
+        };
+        assert!(any_origin.validate().is_err());
+        assert!(CorsPolicy {
+            allow_credentials: false,
+            ..any_origin
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -0,0 +1,512 @@
+use actix_multipart::{
+    Field,
+    Multipart,
+};
+use actix_web::{
+    dev::Payload,
+    FromRequest,
+    HttpRequest,
+};
+use futures_util::{
+    future::LocalBoxFuture,
+    TryStreamExt,
+};
+use log::error;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    cell::Cell,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::{
+    field::Empty,
+    info_span,
+    Instrument,
+};
+use uuid::Uuid;
+
+use super::limits::{
+    UploadLimits,
+    MAX_FORM_FIELD_BYTES,
+};
+use crate::{
+    error::{
+        Error,
+        FieldError,
+        FieldProblem,
+        Result,
+    },
+    files::FileStore,
+    types::{
+        normalize_output_extension,
+        ExecutionPayload,
+        DEFAULT_OUTPUT_EXTENSION,
+    },
+};
+
+pub const SHARED_DIR: &str = "/mnt/shared";
+
+/// Unique path in the shared volume for an input file, keeping the extension of its name.
+pub fn input_file_path_for(filename: &str) -> String {
+    unique_path(Path::new(SHARED_DIR), filename)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn unique_path(dir: &Path, filename: &str) -> PathBuf {
+    match Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+        Some(extension) if !extension.is_empty() => {
+            dir.join(format!("{}.{}", Uuid::new_v4(), extension))
+        }
+        _ => dir.join(Uuid::new_v4().to_string()),
+    }
+}
+
+pub enum FieldKind {
+    /// Buffered in memory, up to the given size.
+    Text { max_bytes: usize },
+    /// Written to disk as it is received, within the [`UploadLimits`].
+    File,
+}
+
+/// Field accepted by a form.
+pub struct FormField {
+    name: &'static str,
+    kind: FieldKind,
+    required: bool,
+}
+
+impl FormField {
+    pub fn text(name: &'static str, max_bytes: usize) -> Self {
+        FormField {
+            name,
+            kind: FieldKind::Text { max_bytes },
+            required: false,
+        }
+    }
+
+    pub fn file(name: &'static str) -> Self {
+        FormField {
+            name,
+            kind: FieldKind::File,
+            required: false,
+        }
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+}
+
+/// File field, with the size and the SHA-256 computed while writing it.
+#[derive(Debug)]
+pub struct ReceivedFile {
+    pub path: PathBuf,
+    pub filename: Option<String>,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Fields of a multipart form, parsed against the fields it accepts.
+#[derive(Debug, Default)]
+pub struct Form {
+    texts: HashMap<&'static str, String>,
+    files: HashMap<&'static str, ReceivedFile>,
+}
+
+impl Form {
+    /// Reads every field of the form, file fields being written to `file_dir`. Unknown,
+    /// duplicated, missing and non UTF-8 fields are reported together as
+    /// [`Error::InvalidFields`], while exceeding the limits fails right away. The files written
+    /// so far are removed on any error.
+    pub async fn parse(
+        mut multipart: Multipart,
+        fields: &[FormField],
+        file_dir: &Path,
+        limits: &UploadLimits,
+    ) -> Result<Form> {
+        let mut form = Form::default();
+        let mut errors = Vec::new();
+        let mut result = form
+            .read(&mut multipart, fields, file_dir, limits, &mut errors)
+            .await;
+
+        for field in fields.iter().filter(|field| field.required) {
+            let received = form.texts.contains_key(field.name)
+                || form.files.contains_key(field.name)
+                || errors.iter().any(|error| error.field == field.name);
+            if !received {
+                errors.push(FieldError {
+                    field: field.name.to_string(),
+                    problem: FieldProblem::Missing,
+                });
+            }
+        }
+        if result.is_ok() && !errors.is_empty() {
+            result = Err(Error::InvalidFields(errors));
+        }
+
+        match result {
+            Ok(()) => Ok(form),
+            Err(e) => {
+                form.remove_files().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn read(
+        &mut self,
+        multipart: &mut Multipart,
+        fields: &[FormField],
+        file_dir: &Path,
+        limits: &UploadLimits,
+        errors: &mut Vec<FieldError>,
+    ) -> Result<()> {
+        let mut seen = HashSet::new();
+        while let Some(mut field) = multipart
+            .try_next()
+            .await
+            .map_err(|e| Error::Validation(e.to_string()))?
+        {
+            let name = field
+                .content_disposition()
+                .get_name()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            // Fields left unread are skipped by the next call to `try_next`.
+            let Some(spec) = fields.iter().find(|spec| spec.name == name) else {
+                errors.push(FieldError {
+                    field: name,
+                    problem: FieldProblem::Unknown,
+                });
+                continue;
+            };
+            if !seen.insert(spec.name) {
+                errors.push(FieldError {
+                    field: name,
+                    problem: FieldProblem::Duplicate,
+                });
+                continue;
+            }
+
+            match spec.kind {
+                FieldKind::Text { max_bytes } => {
+                    match read_text(&mut field, spec.name, max_bytes).await? {
+                        Some(text) => {
+                            self.texts.insert(spec.name, text);
+                        }
+                        None => errors.push(FieldError {
+                            field: name,
+                            problem: FieldProblem::InvalidEncoding,
+                        }),
+                    }
+                }
+                FieldKind::File => {
+                    let filename = field
+                        .content_disposition()
+                        .get_filename()
+                        .map(str::to_string);
+                    let path = unique_path(file_dir, filename.as_deref().unwrap_or_default());
+                    // Registered before writing, so that a partial file is removed as well.
+                    let file = self.files.entry(spec.name).or_insert(ReceivedFile {
+                        path,
+                        filename,
+                        size: 0,
+                        sha256: String::new(),
+                    });
+                    let span = info_span!("file_upload", field = spec.name, bytes = Empty);
+                    write_file(&mut field, file, limits)
+                        .instrument(span.clone())
+                        .await?;
+                    span.record("bytes", file.size);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn take_text(&mut self, name: &str) -> Option<String> {
+        self.texts.remove(name)
+    }
+
+    pub fn take_file(&mut self, name: &str) -> Option<ReceivedFile> {
+        self.files.remove(name)
+    }
+
+    async fn remove_files(&mut self) {
+        for (_, file) in self.files.drain() {
+            if let Err(e) = tokio::fs::remove_file(&file.path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Failed to delete uploaded file {:?}: {:?}", file.path, e);
+                }
+            }
+        }
+    }
+}
+
+/// Buffers a text field, `None` meaning that it is not valid UTF-8.
+async fn read_text(field: &mut Field, name: &str, max_bytes: usize) -> Result<Option<String>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field
+        .try_next()
+        .await
+        .map_err(|e| Error::Validation(e.to_string()))?
+    {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(Error::PayloadTooLarge(format!(
+                "Field '{}' must not exceed {} bytes",
+                name, max_bytes
+            )));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8(data).ok())
+}
+
+async fn write_file(
+    field: &mut Field,
+    file: &mut ReceivedFile,
+    limits: &UploadLimits,
+) -> Result<()> {
+    let mut writer = tokio::fs::File::create(&file.path).await?;
+    let mut hasher = Sha256::new();
+    let mut chunks = limits.limit_file(field);
+    while let Some(chunk) = chunks.try_next().await? {
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await?;
+        file.size += chunk.len() as u64;
+    }
+    writer.flush().await?;
+    if file.size == 0 {
+        return Err(Error::Validation("Empty file received".to_string()));
+    }
+    file.sha256 = format!("{:x}", hasher.finalize());
+    Ok(())
+}
+
+async fn parse_request(
+    req: HttpRequest,
+    payload: Payload,
+    fields: Vec<FormField>,
+    file_dir: PathBuf,
+) -> Result<Form> {
+    let limits = UploadLimits::from_env();
+    limits.check_content_length(&req)?;
+    // Chunked bodies announce no length, the bytes are counted as they arrive.
+    let exceeded = Rc::new(Cell::new(false));
+    let body = limits.limit_request(payload, exceeded.clone());
+    let result = Form::parse(
+        Multipart::new(req.headers(), body),
+        &fields,
+        &file_dir,
+        &limits,
+    )
+    .instrument(info_span!("parse_request"))
+    .await;
+    match result {
+        Err(_) if exceeded.get() => Err(limits.request_too_large()),
+        result => result,
+    }
+}
+
+/// Execution sent as the `multipart/form-data` form documented by
+/// [`ExecutionForm`](super::openapi::ExecutionForm), its input file being written to the shared
+/// volume.
+pub struct ExecutionMultipart(pub ExecutionPayload);
+
+impl FromRequest for ExecutionMultipart {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+        Box::pin(async move {
+            let fields = vec![
+                FormField::text("language", MAX_FORM_FIELD_BYTES).required(),
+                FormField::text("code", UploadLimits::from_env().max_code_bytes).required(),
+                FormField::text("output_extension", MAX_FORM_FIELD_BYTES),
+                FormField::text("execution_id", MAX_FORM_FIELD_BYTES),
+                FormField::text("input_file_id", MAX_FORM_FIELD_BYTES),
+                FormField::text("tags", MAX_FORM_FIELD_BYTES),
+                FormField::file("input_file"),
+            ];
+            let mut form = parse_request(req, payload, fields, PathBuf::from(SHARED_DIR)).await?;
+
+            let trimmed = |text: String| text.trim().to_string();
+            Ok(ExecutionMultipart(ExecutionPayload {
+                language: form.take_text("language").unwrap_or_default(),
+                code: form.take_text("code").unwrap_or_default(),
+                output_extension: form
+                    .take_text("output_extension")
+                    .map(|extension| normalize_output_extension(&extension))
+                    .unwrap_or_else(|| DEFAULT_OUTPUT_EXTENSION.to_string()),
+                input_file_path: form
+                    .take_file("input_file")
+                    .map(|file| file.path.to_string_lossy().to_string()),
+                input_file: None,
+                input_file_id: form.take_text("input_file_id").map(trimmed),
+                execution_id: form.take_text("execution_id").map(trimmed),
+                tags: form
+                    .take_text("tags")
+                    .map(|tags| {
+                        tags.split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            }))
+        })
+    }
+}
+
+/// File sent to `POST /files` as the `file` field of a `multipart/form-data` form, written next
+/// to the stored files.
+pub struct FileUpload(pub ReceivedFile);
+
+impl FromRequest for FileUpload {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+        Box::pin(async move {
+            let file_dir = FileStore::from_env().dir().to_path_buf();
+            tokio::fs::create_dir_all(&file_dir).await?;
+            let fields = vec![FormField::file("file").required()];
+            let mut form = parse_request(req, payload, fields, file_dir).await?;
+            form.take_file("file").map(FileUpload).ok_or_else(|| {
+                Error::Infrastructure("Required field 'file' was not kept".to_string())
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::header::{
+            self,
+            HeaderMap,
+            HeaderValue,
+        },
+        web::Bytes,
+    };
+
+    fn multipart(parts: &[(&str, Option<&str>, &[u8])]) -> Multipart {
+        let mut body = Vec::new();
+        for (name, filename, content) in parts {
+            body.extend_from_slice(b"--boundary\r\nContent-Disposition: form-data; name=\"");
+            body.extend_from_slice(name.as_bytes());
+            body.extend_from_slice(b"\"");
+            if let Some(filename) = filename {
+                body.extend_from_slice(format!("; filename=\"{}\"", filename).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n\r\n");
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--boundary--\r\n");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=boundary"),
+        );
+        let stream = futures_util::stream::iter([Ok::<_, actix_web::error::PayloadError>(
+            Bytes::from(body),
+        )]);
+        Multipart::new(&headers, stream)
+    }
+
+    fn fields() -> Vec<FormField> {
+        vec![
+            FormField::text("language", 16).required(),
+            FormField::text("code", 16).required(),
+            FormField::file("input_file"),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn test_parse_form() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = UploadLimits::from_env();
+
+        let form = multipart(&[
+            ("language", None, b"python"),
+            ("code", None, b"print(1)"),
+            ("input_file", Some("data.csv"), b"a,b\n"),
+        ]);
+        let mut form = Form::parse(form, &fields(), dir.path(), &limits)
+            .await
+            .unwrap();
+        assert_eq!(form.take_text("language").as_deref(), Some("python"));
+        let file = form.take_file("input_file").unwrap();
+        assert_eq!(file.size, 4);
+        assert_eq!(file.path.extension().unwrap(), "csv");
+        assert_eq!(std::fs::read(&file.path).unwrap(), b"a,b\n");
+
+        let form = multipart(&[
+            ("input_file", Some("data.csv"), b"a,b\n"),
+            ("code", None, b"\xff\xfe"),
+            ("language", None, b"python"),
+            ("language", None, b"lua"),
+            ("debug", None, b"1"),
+        ]);
+        let errors = match Form::parse(form, &fields(), dir.path(), &limits).await {
+            Err(Error::InvalidFields(errors)) => errors,
+            other => panic!("Unexpected result: {:?}", other),
+        };
+        let problems = errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.problem))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                ("code", FieldProblem::InvalidEncoding),
+                ("language", FieldProblem::Duplicate),
+                ("debug", FieldProblem::Unknown),
+            ]
+        );
+
+        let form = multipart(&[("code", None, b"print(1) and more")]);
+        assert!(matches!(
+            Form::parse(form, &fields(), dir.path(), &limits).await,
+            Err(Error::PayloadTooLarge(_))
+        ));
+
+        let form = multipart(&[("code", None, b"print(1)")]);
+        match Form::parse(form, &fields(), dir.path(), &limits).await {
+            Err(Error::InvalidFields(errors)) => assert_eq!(
+                errors,
+                [FieldError {
+                    field: "language".to_string(),
+                    problem: FieldProblem::Missing,
+                }]
+            ),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Only the file of the accepted form is left.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,313 @@
+use actix_web::{
+    error::PayloadError,
+    http::header,
+    web::{
+        Bytes,
+        BytesMut,
+    },
+    HttpRequest,
+};
+use futures_util::{
+    stream,
+    Stream,
+    StreamExt,
+};
+use std::{
+    cell::Cell,
+    env,
+    fmt::Display,
+    rc::Rc,
+    str::FromStr,
+};
+
+use crate::error::{
+    Error,
+    Result,
+};
+
+const DEFAULT_MAX_CODE_BYTES: usize = 1024 * 1024;
+const DEFAULT_MAX_FILE_BYTES: u64 = 100 * 1024 * 1024;
+/// Leaves room for a file of the maximum size sent base64 encoded in a JSON body.
+const DEFAULT_MAX_REQUEST_BYTES: usize = 150 * 1024 * 1024;
+/// Text fields of a multipart form other than `code`.
+pub const MAX_FORM_FIELD_BYTES: usize = 1024;
+/// Bytes of a file buffered before detecting its type, enough for every signature `infer` knows.
+const FILE_TYPE_HEAD_BYTES: usize = 8192;
+
+/// Sizes and types accepted in requests, checked before anything is buffered or written.
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    pub max_code_bytes: usize,
+    pub max_file_bytes: u64,
+    pub max_request_bytes: usize,
+    /// MIME types, or `type/*` patterns, accepted for input files. Types are detected from the
+    /// first bytes of the files, anything without a known signature being `text/plain` when it
+    /// is valid UTF-8 and `application/octet-stream` otherwise. Empty accepts every file.
+    pub allowed_file_types: Vec<String>,
+}
+
+impl UploadLimits {
+    pub fn from_env() -> Self {
+        UploadLimits {
+            max_code_bytes: env_number("MAX_CODE_BYTES").unwrap_or(DEFAULT_MAX_CODE_BYTES),
+            max_file_bytes: env_number("MAX_FILE_BYTES").unwrap_or(DEFAULT_MAX_FILE_BYTES),
+            max_request_bytes: env_number("MAX_REQUEST_BYTES").unwrap_or(DEFAULT_MAX_REQUEST_BYTES),
+            allowed_file_types: env::var("ALLOWED_FILE_TYPES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|file_type| file_type.trim().to_string())
+                .filter(|file_type| !file_type.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Passes the chunks of a request body through, failing once more than `max_request_bytes`
+    /// were received, whatever the `Content-Length` announced. `exceeded` is set when it does,
+    /// for the caller to tell this failure from the others.
+    pub fn limit_request<S>(
+        &self,
+        body: S,
+        exceeded: Rc<Cell<bool>>,
+    ) -> impl Stream<Item = std::result::Result<Bytes, PayloadError>>
+    where
+        S: Stream<Item = std::result::Result<Bytes, PayloadError>>,
+    {
+        let max_request_bytes = self.max_request_bytes;
+        let mut size = 0;
+        body.map(move |chunk| {
+            let chunk = chunk?;
+            size += chunk.len();
+            if size > max_request_bytes {
+                exceeded.set(true);
+                return Err(PayloadError::Overflow);
+            }
+            Ok(chunk)
+        })
+    }
+
+    /// Rejects requests announcing a body larger than allowed, before reading any of it.
+    pub fn check_content_length(&self, req: &HttpRequest) -> Result<()> {
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok());
+        match length {
+            Some(length) if length > self.max_request_bytes => Err(self.request_too_large()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn request_too_large(&self) -> Error {
+        Error::PayloadTooLarge(format!(
+            "Request body must not exceed {} bytes",
+            self.max_request_bytes
+        ))
+    }
+
+    pub fn check_code(&self, field: &str, code: &str) -> Result<()> {
+        if code.len() > self.max_code_bytes {
+            return Err(Error::PayloadTooLarge(format!(
+                "Field '{}' must not exceed {} bytes",
+                field, self.max_code_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    fn file_too_large(&self) -> Error {
+        Error::PayloadTooLarge(format!(
+            "Input files must not exceed {} bytes",
+            self.max_file_bytes
+        ))
+    }
+
+    /// Checks the type of a file from its first bytes.
+    pub fn check_file_type(&self, head: &[u8]) -> Result<()> {
+        if self.allowed_file_types.is_empty() {
+            return Ok(());
+        }
+
+        let file_type = detect_file_type(head);
+        let allowed =
+            self.allowed_file_types
+                .iter()
+                .any(|allowed| match allowed.strip_suffix("/*") {
+                    Some(prefix) => file_type
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.starts_with('/')),
+                    None => allowed == file_type,
+                });
+        if !allowed {
+            return Err(Error::UnsupportedMediaType(format!(
+                "Input files of type {} are not accepted",
+                file_type
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a file received whole, e.g. decoded from base64.
+    pub fn check_file(&self, data: &[u8]) -> Result<()> {
+        if data.len() as u64 > self.max_file_bytes {
+            return Err(self.file_too_large());
+        }
+        self.check_file_type(data)
+    }
+
+    /// Passes the chunks of an uploaded file through, failing as soon as the file gets too large
+    /// or its head shows a type that is not accepted. The first [`FILE_TYPE_HEAD_BYTES`] are
+    /// held back until the type is checked, however small the chunks they arrive in.
+    pub fn limit_file<S, E>(&self, chunks: S) -> impl Stream<Item = Result<Bytes>> + Unpin
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        struct State<S> {
+            chunks: S,
+            limits: UploadLimits,
+            size: u64,
+            /// Bytes held back until the type is checked, `None` once it is.
+            head: Option<BytesMut>,
+            done: bool,
+        }
+
+        let state = State {
+            chunks,
+            limits: self.clone(),
+            size: 0,
+            head: Some(BytesMut::new()),
+            done: false,
+        };
+        Box::pin(stream::unfold(state, |mut state| async move {
+            while !state.done {
+                let chunk = match state.chunks.next().await {
+                    Some(Ok(chunk)) => chunk,
+                    Some(Err(e)) => {
+                        state.done = true;
+                        state.head = None;
+                        return Some((Err(Error::Validation(e.to_string())), state));
+                    }
+                    None => {
+                        state.done = true;
+                        break;
+                    }
+                };
+                state.size += chunk.len() as u64;
+                if state.size > state.limits.max_file_bytes {
+                    state.done = true;
+                    state.head = None;
+                    return Some((Err(state.limits.file_too_large()), state));
+                }
+                match state.head {
+                    Some(ref mut head) => {
+                        head.extend_from_slice(&chunk);
+                        if head.len() >= FILE_TYPE_HEAD_BYTES {
+                            break;
+                        }
+                    }
+                    None => return Some((Ok(chunk), state)),
+                }
+            }
+
+            let head = state.head.take().filter(|head| !head.is_empty())?;
+            let checked = state.limits.check_file_type(&head).map(|()| head.freeze());
+            if checked.is_err() {
+                state.done = true;
+            }
+            Some((checked, state))
+        }))
+    }
+}
+
+fn env_number<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+fn detect_file_type(head: &[u8]) -> &'static str {
+    if let Some(file_type) = infer::get(head) {
+        return file_type.mime_type();
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => "text/plain",
+        // The head may end in the middle of a character.
+        Err(e) if e.error_len().is_none() => "text/plain",
+        Err(_) => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limit_file() {
+        let limits = UploadLimits {
+            max_code_bytes: 4,
+            max_file_bytes: 8,
+            max_request_bytes: 16,
+            allowed_file_types: vec!["text/*".to_string(), "image/png".to_string()],
+        };
+        assert!(limits.check_code("code", "1234").is_ok());
+        assert!(matches!(
+            limits.check_code("code", "12345"),
+            Err(Error::PayloadTooLarge(_))
+        ));
+
+        assert!(limits.check_file("a,b\n1,é".as_bytes()).is_ok());
+        assert!(limits.check_file(b"\x89PNG\r\n\x1a\n").is_ok());
+        assert!(matches!(
+            limits.check_file(b"%PDF-1.7"),
+            Err(Error::UnsupportedMediaType(_))
+        ));
+        assert!(matches!(
+            limits.check_file(b"\x00\xff\x00\xff"),
+            Err(Error::UnsupportedMediaType(_))
+        ));
+
+        let chunks = |parts: Vec<&'static [u8]>| {
+            futures_util::stream::iter(
+                parts
+                    .into_iter()
+                    .map(|part| Ok::<_, Error>(Bytes::from_static(part))),
+            )
+        };
+        let received = limits
+            .limit_file(chunks(vec![b"hello", b" you"]))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(received[..], [Err(Error::PayloadTooLarge(_))]));
+        let received = limits
+            .limit_file(chunks(vec![b"%PDF-1.7"]))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(received[0], Err(Error::UnsupportedMediaType(_))));
+        // The signature is found across chunks, and nothing is passed on before it is checked.
+        let received = limits
+            .limit_file(chunks(vec![b"%", b"PD", b"F-1.7"]))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            received[..],
+            [Err(Error::UnsupportedMediaType(_))]
+        ));
+        let received = limits
+            .limit_file(chunks(vec![b"a,", b"b\n"]))
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(received, [Bytes::from_static(b"a,b\n")]);
+
+        let exceeded = Rc::new(Cell::new(false));
+        let body = futures_util::stream::iter(
+            [b"0123456789", b"0123456789"].map(|part| Ok(Bytes::from_static(part))),
+        );
+        let received = limits
+            .limit_request(body, exceeded.clone())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(received[0].is_ok());
+        assert!(matches!(received[1], Err(PayloadError::Overflow)));
+        assert!(exceeded.get());
+    }
+}
@@ -1,2 +1,13 @@
+mod auth;
+mod cors;
+mod form;
+mod limits;
+mod openapi;
+mod rate_limit;
+mod request_id;
 mod server;
+mod trace;
+pub use form::SHARED_DIR;
+pub use limits::UploadLimits;
+pub use openapi::ApiDoc;
 pub use server::run_server;
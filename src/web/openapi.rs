@@ -0,0 +1,193 @@
+use utoipa::{
+    openapi::security::{
+        ApiKey,
+        ApiKeyValue,
+        HttpAuthScheme,
+        HttpBuilder,
+        SecurityScheme,
+    },
+    Modify,
+    OpenApi,
+    ToSchema,
+};
+
+use super::server;
+use crate::{
+    api,
+    error::ErrorResponse,
+    types::{
+        BatchPayload,
+        BatchResult,
+        ExecutionKind,
+        ExecutionPage,
+        ExecutionPayload,
+        ExecutionRecord,
+        ExecutionResponse,
+        ExecutionResult,
+        InteractivePayload,
+        JobInfo,
+        JudgePayload,
+        JudgeResult,
+        Problem,
+        ProblemSummary,
+        ReadinessReport,
+        StoredFile,
+        StressPayload,
+        StressResult,
+        SubmissionPayload,
+        SubmissionResult,
+    },
+};
+
+/// Multipart form accepted by `POST /execute`, the same as [`ExecutionPayload`] with the input
+/// file sent as a file field.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ExecutionForm {
+    language: String,
+    code: String,
+    output_extension: Option<String>,
+    execution_id: Option<String>,
+    #[schema(value_type = Option<String>, format = Binary)]
+    input_file: Option<Vec<u8>>,
+    /// Id of a file uploaded to `POST /files`, instead of `input_file`.
+    input_file_id: Option<String>,
+    /// Comma separated labels kept in the execution history.
+    tags: Option<String>,
+}
+
+/// Multipart form accepted by `POST /files`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct FileUploadForm {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+struct Credentials;
+
+impl Modify for Credentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+            );
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Dyno code"),
+    paths(
+        server::execute_json,
+        server::execute_batch,
+        server::judge_code,
+        server::judge_interactive,
+        server::stress_test,
+        server::list_problems,
+        server::get_problem,
+        server::upload_problem,
+        server::delete_problem,
+        server::submit_solution,
+        server::get_job,
+        server::cancel_job,
+        server::list_executions,
+        server::get_execution,
+        server::download_artifact,
+        server::upload_file,
+        server::get_file,
+        server::delete_file,
+        api::health_check,
+        api::ready_check,
+        api::export_metrics,
+        api::check_version,
+        api::list_languages,
+    ),
+    components(schemas(
+        ErrorResponse,
+        ExecutionForm,
+        ExecutionPayload,
+        ExecutionResponse,
+        ExecutionResult,
+        BatchPayload,
+        BatchResult,
+        JudgePayload,
+        JudgeResult,
+        InteractivePayload,
+        JobInfo,
+        ExecutionKind,
+        ExecutionRecord,
+        ExecutionPage,
+        StressPayload,
+        StressResult,
+        Problem,
+        ProblemSummary,
+        SubmissionPayload,
+        SubmissionResult,
+        FileUploadForm,
+        StoredFile,
+        ReadinessReport,
+    )),
+    modifiers(&Credentials),
+    security(("api_key" = []), ("bearer" = [])),
+    tags(
+        (name = "execution", description = "Running programs"),
+        (name = "judge", description = "Grading programs against expected outputs"),
+        (name = "files", description = "Input files reusable across executions"),
+        (name = "problems", description = "Catalog of problems with hidden test cases"),
+        (name = "service", description = "Service status"),
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documents_every_route() {
+        let openapi = ApiDoc::openapi();
+        for path in [
+            "/execute",
+            "/execute/batch",
+            "/judge",
+            "/judge/interactive",
+            "/stress",
+            "/problems",
+            "/problems/{id}",
+            "/problems/{id}/submissions",
+            "/jobs/{id}",
+            "/executions",
+            "/executions/{id}",
+            "/artifacts/{id}",
+            "/files",
+            "/files/{id}",
+            "/health",
+            "/ready",
+            "/metrics",
+            "/version",
+            "/languages",
+        ] {
+            assert!(
+                openapi.paths.paths.contains_key(path),
+                "{} is missing",
+                path
+            );
+        }
+
+        let schemas = openapi.components.unwrap().schemas;
+        assert!(schemas.contains_key("ComparisonMode"));
+        assert!(schemas.contains_key("Verdict"));
+    }
+}
@@ -0,0 +1,169 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{
+        forward_ready,
+        Service,
+        ServiceRequest,
+        ServiceResponse,
+        Transform,
+    },
+    http::header::{
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+    },
+    web,
+    HttpMessage,
+    ResponseError,
+};
+use futures_util::future::{
+    ready,
+    LocalBoxFuture,
+    Ready,
+};
+use std::{
+    env,
+    rc::Rc,
+};
+
+use crate::{
+    auth::{
+        AuthMethod,
+        Principal,
+    },
+    quotas::{
+        ExecutionTime,
+        QuotaStatus,
+        Quotas,
+    },
+};
+
+/// Headers telling clients what they have left, exposed to browsers by the CORS policy.
+pub const QUOTA_HEADERS: [&str; 5] = [
+    "retry-after",
+    "x-ratelimit-limit",
+    "x-ratelimit-remaining",
+    "x-quota-executions-remaining",
+    "x-quota-cpu-seconds-remaining",
+];
+
+/// Admits the requests of the wrapped routes against the [`Quotas`] of the app, counting the
+/// [`ExecutionTime`] their responses carry.
+pub struct RateLimit {
+    /// Whether the client address is read from `X-Forwarded-For`, which only a trusted proxy
+    /// in front of the server can guarantee.
+    trust_forwarded_for: bool,
+}
+
+impl RateLimit {
+    pub fn from_env() -> Self {
+        RateLimit {
+            trust_forwarded_for: env::var("TRUST_FORWARDED_FOR")
+                .is_ok_and(|value| value.trim() == "true"),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            trust_forwarded_for: self.trust_forwarded_for,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    trust_forwarded_for: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(quotas) = req.app_data::<web::Data<Quotas>>().cloned() else {
+            let service = self.service.clone();
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        };
+
+        let ip = if self.trust_forwarded_for {
+            req.connection_info()
+                .realip_remote_addr()
+                .map(str::to_string)
+        } else {
+            req.peer_addr().map(|addr| addr.ip().to_string())
+        }
+        .unwrap_or_default();
+        let principal = req
+            .extensions()
+            .get::<Principal>()
+            .filter(|principal| principal.method != AuthMethod::Anonymous)
+            .map(|principal| principal.id.clone());
+
+        let status = match quotas.admit(principal.as_deref(), &ip) {
+            Ok(status) => status,
+            // Answered rather than failed, so that the CORS headers are still added.
+            Err(e) => {
+                let status = quotas.status(principal.as_deref(), &ip);
+                let mut response = req.into_response(e.error_response()).map_into_right_body();
+                insert_quota_headers(response.headers_mut(), &status);
+                return Box::pin(ready(Ok(response)));
+            }
+        };
+
+        let service = self.service.clone();
+        Box::pin(async move {
+            let mut response = service.call(req).await?;
+            let time_ms = response
+                .response()
+                .extensions()
+                .get::<ExecutionTime>()
+                .map(|time| time.0);
+            let status = match time_ms {
+                Some(time_ms) => quotas.record_cpu(principal.as_deref(), &ip, time_ms),
+                None => status,
+            };
+            insert_quota_headers(response.headers_mut(), &status);
+            Ok(response.map_into_left_body())
+        })
+    }
+}
+
+fn insert_quota_headers(headers: &mut HeaderMap, status: &QuotaStatus) {
+    let values = [
+        ("x-ratelimit-limit", status.rate_limit.map(u64::from)),
+        (
+            "x-ratelimit-remaining",
+            status.rate_remaining.map(u64::from),
+        ),
+        ("x-quota-executions-remaining", status.executions_remaining),
+        (
+            "x-quota-cpu-seconds-remaining",
+            status.cpu_seconds_remaining,
+        ),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::{
+    dev::{
+        forward_ready,
+        Service,
+        ServiceRequest,
+        ServiceResponse,
+        Transform,
+    },
+    http::header::{
+        HeaderName,
+        HeaderValue,
+    },
+};
+use futures_util::future::{
+    ready,
+    LocalBoxFuture,
+    Ready,
+};
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::logging::with_request_id;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest id accepted from clients, longer ones being replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Gives each request an id, the one of its `X-Request-Id` header when it is a sensible one,
+/// added to the logs written while serving it, to its span and to its response.
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+        tracing::Span::current().record("request_id", request_id.as_str());
+
+        let service = self.service.clone();
+        Box::pin(with_request_id(request_id.clone(), async move {
+            let mut response = service.call(req).await?;
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response
+                    .headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(response)
+        }))
+    }
+}
+
+/// Ids are echoed in headers and logs, so only short ones made of safe characters are kept.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
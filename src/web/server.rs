@@ -1,262 +1,1684 @@
-use actix_cors::Cors;
-use actix_multipart::Multipart;
+use actix_files::NamedFile;
 use actix_web::{
-    http,
+    error::JsonPayloadError,
+    guard,
+    http::{
+        self,
+        header::{
+            ContentDisposition,
+            DispositionParam,
+            DispositionType,
+            TryIntoHeaderValue,
+        },
+    },
     web,
     App,
+    HttpRequest,
     HttpResponse,
     HttpServer,
-    Responder,
+    ResponseError,
 };
 use base64::{
     prelude::BASE64_STANDARD,
     Engine,
 };
-use futures_util::stream::TryStreamExt;
 use log::{
+    debug,
     error,
     info,
+    log,
+    warn,
+    Level,
 };
 
 use serde_json::json;
+use sha2::{
+    Digest,
+    Sha256,
+};
 
-use std::net::Ipv4Addr;
-use tokio::{
-    fs::File,
-    io::AsyncWriteExt,
+use std::{
+    net::Ipv4Addr,
+    sync::Arc,
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
 };
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
 
-use crate::executor::{
-    CodeExecutor,
-    K8sExecutor,
+use super::{
+    auth::Authentication,
+    cors::CorsConfig,
+    form::{
+        input_file_path_for,
+        ExecutionMultipart,
+        FileUpload,
+    },
+    openapi::{
+        ExecutionForm,
+        FileUploadForm,
+    },
+    rate_limit::RateLimit,
+    request_id::RequestId,
+    trace::Tracing,
+    ApiDoc,
+    UploadLimits,
+};
+
+use crate::{
+    artifacts::ArtifactStore,
+    auth::{
+        AuthConfig,
+        Principal,
+    },
+    error::{
+        Error,
+        ErrorCode,
+        ErrorResponse,
+        Result,
+    },
+    executor::{
+        queue::ExecutionQueue,
+        CodeExecutor,
+        K8sExecutor,
+        Language,
+        MAX_BATCH_CASES,
+        MAX_BATCH_PARALLELISM,
+        MAX_MEMORY_LIMIT_MB,
+        MAX_TIME_LIMIT_MS,
+    },
+    files::FileStore,
+    history::{
+        validate_tags,
+        ExecutionHistory,
+        HistoryQuery,
+    },
+    judge,
+    logging,
+    metrics,
+    problems::ProblemStore,
+    quotas::{
+        ExecutionTime,
+        QuotaConfig,
+        Quotas,
+    },
 };
 
 use crate::types::{
+    normalize_output_extension,
+    BatchPayload,
+    BatchResult,
+    ExecutionKind,
+    ExecutionPage,
     ExecutionPayload,
+    ExecutionRecord,
+    ExecutionResponse,
     ExecutionResult,
+    ExecutionStatus,
+    InputFile,
+    InteractivePayload,
+    JobInfo,
+    JudgePayload,
+    JudgeResult,
+    Problem,
+    ProblemSummary,
+    StoredFile,
+    StressPayload,
+    StressResult,
+    SubmissionPayload,
+    SubmissionResult,
 };
 
 use crate::api::{
     check_version,
+    export_metrics,
     get_server_port,
     health_check,
+    list_languages,
+    ready_check,
 };
 
-async fn execute_code(mut payload: Multipart) -> impl Responder {
-    let mut language = None;
-    let mut code = None;
-    let mut output_extension = Some(".txt".to_string());
-    let mut input_file_path = None;
-
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        let content_disposition = field.content_disposition();
-        let field_name = match content_disposition.get_name() {
-            Some(name) => name.trim().to_string(),
-            None => {
-                return HttpResponse::BadRequest().body("Missing field name");
+const PURGE_INTERVAL: Duration = Duration::from_secs(300);
+
+fn is_json_request(ctx: &guard::GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim_start().starts_with("application/json"))
+}
+
+/// Writes an inline input file to the shared volume or checks that an uploaded one, referenced by
+/// its id, exists and belongs to `owner`. Returns the path to hand to the executor and whether it
+/// was written for this request only.
+async fn resolve_input_file(
+    owner: &str,
+    input_file: Option<InputFile>,
+    input_file_id: Option<String>,
+) -> Result<(Option<String>, bool)> {
+    match (input_file, input_file_id) {
+        (Some(InputFile { filename, content }), None) => {
+            let data = BASE64_STANDARD.decode(content.trim()).map_err(|e| {
+                Error::Validation(format!("Invalid base64 in 'input_file.content': {}", e))
+            })?;
+            if data.is_empty() {
+                return Err(Error::Validation("Empty file received".to_string()));
             }
-        };
+            UploadLimits::from_env().check_file(&data)?;
+
+            let file_path = input_file_path_for(filename.as_deref().unwrap_or_default());
+            info!("Writing input file to: {:?}", file_path);
+            tokio::fs::write(&file_path, &data).await?;
+            Ok((Some(file_path), true))
+        }
+        (None, Some(id)) => {
+            let path = FileStore::from_env()
+                .path(owner, &id)
+                .await?
+                .ok_or_else(|| {
+                    Error::Validation(format!("Input file {} does not exist or has expired", id))
+                })?;
+            Ok((Some(path), false))
+        }
+        (None, None) => Ok((None, false)),
+        (Some(_), Some(_)) => Err(Error::Validation(
+            "Fields 'input_file' and 'input_file_id' are mutually exclusive".to_string(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/execute",
+    tag = "execution",
+    request_body(
+        description = "Also accepts the `multipart/form-data` fields of `ExecutionForm`",
+        content(
+            (ExecutionPayload = "application/json"),
+            (ExecutionForm = "multipart/form-data"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Program ran successfully", body = ExecutionResponse),
+        (status = 400, description = "Invalid request or unsupported language", body = ErrorResponse),
+        (status = 409, description = "Execution cancelled, or its id already used", body = ErrorResponse),
+        (status = 413, description = "Code, input file or request too large", body = ErrorResponse),
+        (status = 415, description = "Input file type not accepted", body = ErrorResponse),
+        (status = 422, description = "Program failed to compile or to run", body = ErrorResponse),
+        (status = 429, description = "Rate limit or daily quota exceeded, see `Retry-After`", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+        (status = 503, description = "Execution queue full", body = ErrorResponse),
+        (status = 504, description = "Execution did not finish in time", body = ErrorResponse),
+    )
+)]
+async fn execute_json(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    payload: web::Json<ExecutionPayload>,
+) -> Result<HttpResponse> {
+    let mut payload = payload.into_inner();
+    payload.output_extension = normalize_output_extension(&payload.output_extension);
+    UploadLimits::from_env().check_code("code", &payload.code)?;
+    validate_tags(&payload.tags)?;
+
+    let (input_file_path, owns_input_file) = resolve_input_file(
+        &principal.id,
+        payload.input_file.take(),
+        payload.input_file_id.take(),
+    )
+    .await?;
+    let owned_input_file = OwnedInputFiles(if owns_input_file {
+        input_file_path.iter().cloned().collect()
+    } else {
+        Vec::new()
+    });
+    payload.input_file_path = input_file_path;
+
+    run_execution(payload, owned_input_file, &principal, &history).await
+}
 
-        match field_name.as_str() {
-            "language" => {
-                info!("Received language");
-                let mut data = Vec::new();
-                while let Some(chunk) = field.try_next().await.unwrap() {
-                    data.extend_from_slice(&chunk);
+/// Validates the batch and writes its inline input files to the shared volume, the uploaded ones
+/// having to belong to `owner`. Returns the files to remove once the batch is done.
+async fn prepare_batch(owner: &str, payload: &mut BatchPayload) -> Result<OwnedInputFiles> {
+    payload.output_extension = normalize_output_extension(&payload.output_extension);
+    let limits = UploadLimits::from_env();
+    limits.check_code("code", &payload.code)?;
+    if let Some(ref interactor) = payload.interactor {
+        limits.check_code("interactor.code", &interactor.code)?;
+    }
+    if payload.cases.is_empty() {
+        return Err(Error::Validation(
+            "At least one case is required".to_string(),
+        ));
+    }
+    if payload.cases.len() > MAX_BATCH_CASES {
+        return Err(Error::Validation(format!(
+            "At most {} cases are allowed",
+            MAX_BATCH_CASES
+        )));
+    }
+    if payload
+        .parallelism
+        .is_some_and(|parallelism| parallelism > MAX_BATCH_PARALLELISM)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'parallelism' must not exceed {}",
+            MAX_BATCH_PARALLELISM
+        )));
+    }
+    if payload
+        .time_limit_ms
+        .is_some_and(|limit| limit == 0 || limit > MAX_TIME_LIMIT_MS)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'time_limit_ms' must be between 1 and {}",
+            MAX_TIME_LIMIT_MS
+        )));
+    }
+    if payload
+        .memory_limit_mb
+        .is_some_and(|limit| limit == 0 || limit > MAX_MEMORY_LIMIT_MB)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'memory_limit_mb' must be between 1 and {}",
+            MAX_MEMORY_LIMIT_MB
+        )));
+    }
+    match payload.execution_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(_)) => (),
+        Some(Err(_)) => {
+            return Err(Error::Validation(
+                "Field 'execution_id' must be a UUID".to_string(),
+            ));
+        }
+        None => payload.execution_id = Some(Uuid::new_v4().to_string()),
+    }
+
+    let mut owned_input_files = OwnedInputFiles(Vec::new());
+    for case in payload.cases.iter_mut() {
+        match resolve_input_file(owner, case.input_file.take(), case.input_file_id.take()).await {
+            Ok((input_file_path, owns_input_file)) => {
+                if owns_input_file {
+                    owned_input_files.0.extend(input_file_path.clone());
                 }
-                language = Some(String::from_utf8(data).unwrap());
+                case.input_file_path = input_file_path;
             }
-            "code" => {
-                info!("Received code");
-                let mut data = Vec::new();
-                while let Some(chunk) = field.try_next().await.unwrap() {
-                    data.extend_from_slice(&chunk);
-                }
-                code = Some(String::from_utf8(data).unwrap());
+            Err(e) => {
+                owned_input_files.remove().await;
+                return Err(e);
             }
-            "output_extension" => {
-                info!("Received output extension");
-                let mut data = Vec::new();
-                while let Some(chunk) = field.try_next().await.unwrap() {
-                    data.extend_from_slice(&chunk);
-                }
-                output_extension = Some(String::from_utf8(data).unwrap());
-                if output_extension == Some("".to_string())
-                    || output_extension == Some("null".to_string())
-                {
-                    output_extension = Some(".txt".to_string());
+        }
+    }
+
+    info!(
+        "Received request to execute {} cases in {}",
+        payload.cases.len(),
+        payload.language
+    );
+    Ok(owned_input_files)
+}
+
+#[utoipa::path(
+    post,
+    path = "/execute/batch",
+    tag = "execution",
+    request_body = BatchPayload,
+    responses(
+        (status = 200, description = "Every case ran", body = BatchResult),
+        (status = 400, description = "Invalid batch", body = ErrorResponse),
+        (status = 413, description = "Code, input file or request too large", body = ErrorResponse),
+        (status = 409, description = "Batch cancelled, or its id already used", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+    )
+)]
+async fn execute_batch(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    payload: web::Json<BatchPayload>,
+) -> Result<HttpResponse> {
+    let mut payload = payload.into_inner();
+    validate_tags(&payload.tags)?;
+    check_execution_id(&history, &principal, payload.execution_id.as_deref()).await?;
+    let owned_input_files = prepare_batch(&principal.id, &mut payload).await?;
+    // The id is needed to find the batch in the history.
+    let execution_id = payload
+        .execution_id
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+
+    let mut record = history_record(
+        ExecutionKind::Batch,
+        &principal,
+        execution_id,
+        &payload.language,
+        &payload.code,
+        &payload.tags,
+    );
+    let start = Instant::now();
+    let result = K8sExecutor::execute_batch(&payload).await;
+    owned_input_files.remove().await;
+
+    record.time_limit_ms = payload.time_limit_ms;
+    record.memory_limit_mb = Some(payload.memory_limit_mb.unwrap_or(MAX_MEMORY_LIMIT_MB));
+    let duration_ms = start.elapsed().as_millis() as u64;
+    record.duration_ms = duration_ms;
+    record.cases = Some(payload.cases.len() as u64);
+    record.outcome = batch_outcome(&result).to_string();
+    if let Ok(batch_result) = &result {
+        let results = &batch_result.results;
+        record.exit_code = results
+            .iter()
+            .filter_map(|result| result.exit_code)
+            .find(|&code| code != 0);
+        record.run_ms = results.iter().map(|result| result.time_ms).sum();
+        record.output_bytes = results.iter().map(output_bytes).sum();
+    }
+    finish_execution(&history, record).await;
+
+    let response = match result {
+        Ok(batch_result) => HttpResponse::Ok().json(batch_result),
+        Err(e) => {
+            error!(outcome = e.code().as_str(); "Error executing batch: {}", e);
+            e.response_for(payload.execution_id.as_deref())
+        }
+    };
+    Ok(with_execution_time(response, duration_ms))
+}
+
+/// Runs and grades the submission.
+async fn run_judge(principal: &Principal, payload: JudgePayload) -> Result<JudgeResult> {
+    let comparison = payload.comparison;
+    if let Some(ref checker) = payload.checker {
+        UploadLimits::from_env().check_code("checker.code", &checker.code)?;
+    }
+    let (mut batch, expected_outputs, checker) = payload.into_batch();
+    let owned_input_files = prepare_batch(&principal.id, &mut batch).await?;
+
+    let result = match K8sExecutor::execute_batch(&batch).await {
+        Ok(batch_result) => {
+            let judge_result = judge::evaluate(
+                batch_result,
+                &expected_outputs,
+                comparison,
+                batch.time_limit_ms,
+            );
+            match checker {
+                Some(ref checker) => {
+                    judge::check::<K8sExecutor>(checker, &batch, &expected_outputs, judge_result)
+                        .await
                 }
+                None => Ok(judge_result),
             }
-            "input_file" => {
-                info!("Received input file");
-                let filename = content_disposition
-                    .get_filename()
-                    .map(|name| name.to_string())
-                    .unwrap_or_else(|| Uuid::new_v4().to_string());
-
-                let extension = filename.split('.').last().unwrap_or("");
-                let file_path = if extension.is_empty() {
-                    format!("/mnt/shared/{}", Uuid::new_v4())
-                } else {
-                    format!("/mnt/shared/{}.{}", Uuid::new_v4(), extension)
-                };
-                info!("Writing input file to: {:?}", file_path);
-                let mut file = File::create(&file_path).await.unwrap();
-                let mut is_empty = true;
-
-                while let Some(chunk) = field.try_next().await.unwrap() {
-                    info!("Writing chunk to file");
-                    if !chunk.is_empty() {
-                        is_empty = false;
-                    }
-                    if let Err(e) = file.write_all(&chunk).await {
-                        error!("Failed to write chunk to file: {:?}", e);
-                        return HttpResponse::InternalServerError().body("Failed to write to file");
-                    }
-                }
+        }
+        Err(e) => Err(e),
+    };
+    owned_input_files.remove().await;
 
-                if let Err(e) = file.flush().await {
-                    error!("Failed to flush file: {:?}", e);
-                    return HttpResponse::InternalServerError().body("Failed to flush file");
-                }
+    if let Err(ref e) = result {
+        error!(outcome = e.code().as_str(); "Error judging code: {}", e);
+    }
+    result
+}
 
-                if is_empty {
-                    info!("Empty file received, deleting the file.");
-                    if let Err(e) = tokio::fs::remove_file(&file_path).await {
-                        error!("Failed to delete empty file: {:?}", e);
-                    }
-                    return HttpResponse::BadRequest().body("Empty file received");
-                }
+#[utoipa::path(
+    post,
+    path = "/judge",
+    tag = "judge",
+    request_body = JudgePayload,
+    responses(
+        (status = 200, description = "Submission judged", body = JudgeResult),
+        (status = 400, description = "Invalid submission", body = ErrorResponse),
+        (status = 409, description = "Judging cancelled, or its id already used", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+    )
+)]
+async fn judge_code(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    payload: web::Json<JudgePayload>,
+) -> Result<HttpResponse> {
+    let mut payload = payload.into_inner();
+    check_execution_id(&history, &principal, payload.execution_id.as_deref()).await?;
+    let execution_id = payload
+        .execution_id
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+    let mut record = history_record(
+        ExecutionKind::Judge,
+        &principal,
+        execution_id.clone(),
+        &payload.language,
+        &payload.code,
+        &[],
+    );
+    record.time_limit_ms = payload.time_limit_ms;
+    record.memory_limit_mb = Some(payload.memory_limit_mb.unwrap_or(MAX_MEMORY_LIMIT_MB));
+    record.cases = Some(payload.test_cases.len() as u64);
 
-                input_file_path = Some(file_path);
+    let start = Instant::now();
+    let result = run_judge(&principal, payload).await;
+    record.duration_ms = start.elapsed().as_millis() as u64;
+    record_judge_result(&mut record, &result);
+    let duration_ms = record.duration_ms;
+    finish_execution(&history, record).await;
 
-                let ls_output = std::process::Command::new("ls")
-                    .arg("-l")
-                    .arg("/mnt/shared")
-                    .output()
-                    .expect("Failed to execute ls command");
-                info!("ls -l /mnt/shared: {:?}", ls_output);
+    let response = match result {
+        Ok(judge_result) => HttpResponse::Ok().json(judge_result),
+        Err(e) => e.response_for(Some(&execution_id)),
+    };
+    Ok(with_execution_time(response, duration_ms))
+}
 
-                info!("Input file path: {:?}", input_file_path);
-            }
-            _ => (),
+#[utoipa::path(
+    post,
+    path = "/judge/interactive",
+    tag = "judge",
+    request_body = InteractivePayload,
+    responses(
+        (status = 200, description = "Submission judged", body = JudgeResult),
+        (status = 400, description = "Invalid submission", body = ErrorResponse),
+        (status = 409, description = "Judging cancelled, or its id already used", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+    )
+)]
+async fn judge_interactive(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    payload: web::Json<InteractivePayload>,
+) -> Result<HttpResponse> {
+    let mut batch = payload.into_inner().into_batch();
+    check_execution_id(&history, &principal, batch.execution_id.as_deref()).await?;
+    let owned_input_files = prepare_batch(&principal.id, &mut batch).await?;
+
+    let mut record = history_record(
+        ExecutionKind::Interactive,
+        &principal,
+        batch.execution_id.clone().unwrap_or_default(),
+        &batch.language,
+        &batch.code,
+        &[],
+    );
+    record.time_limit_ms = batch.time_limit_ms;
+    record.memory_limit_mb = Some(batch.memory_limit_mb.unwrap_or(MAX_MEMORY_LIMIT_MB));
+    record.cases = Some(batch.cases.len() as u64);
+
+    let start = Instant::now();
+    let result = K8sExecutor::execute_batch(&batch).await;
+    owned_input_files.remove().await;
+    record.duration_ms = start.elapsed().as_millis() as u64;
+    record.outcome = batch_outcome(&result).to_string();
+    if let Ok(batch_result) = &result {
+        record.run_ms = batch_result
+            .results
+            .iter()
+            .map(|result| result.time_ms)
+            .sum();
+    }
+    let duration_ms = record.duration_ms;
+    finish_execution(&history, record).await;
+
+    let response = match result {
+        Ok(batch_result) => HttpResponse::Ok().json(judge::evaluate_interactive(
+            batch_result,
+            batch.time_limit_ms,
+        )),
+        Err(e) => {
+            error!(outcome = e.code().as_str(); "Error judging interactive code: {}", e);
+            e.response_for(batch.execution_id.as_deref())
         }
+    };
+    Ok(with_execution_time(response, duration_ms))
+}
+
+#[utoipa::path(
+    post,
+    path = "/stress",
+    tag = "judge",
+    request_body = StressPayload,
+    responses(
+        (status = 200, description = "Budget spent or counterexample found", body = StressResult),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 409, description = "Stress test cancelled, or its id already used", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+    )
+)]
+async fn stress_test(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    payload: web::Json<StressPayload>,
+) -> Result<HttpResponse> {
+    let mut payload = payload.into_inner();
+    let limits = UploadLimits::from_env();
+    for (field, program) in [
+        ("generator.code", &payload.generator),
+        ("brute_force.code", &payload.brute_force),
+        ("solution.code", &payload.solution),
+    ] {
+        limits.check_code(field, &program.code)?;
+    }
+    if payload
+        .time_budget_ms
+        .is_some_and(|budget| budget == 0 || budget > judge::MAX_STRESS_BUDGET_MS)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'time_budget_ms' must be between 1 and {}",
+            judge::MAX_STRESS_BUDGET_MS
+        )));
+    }
+    if payload
+        .tests_per_round
+        .is_some_and(|tests| tests == 0 || tests > judge::MAX_TESTS_PER_ROUND)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'tests_per_round' must be between 1 and {}",
+            judge::MAX_TESTS_PER_ROUND
+        )));
     }
+    if payload
+        .time_limit_ms
+        .is_some_and(|limit| limit == 0 || limit > MAX_TIME_LIMIT_MS)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'time_limit_ms' must be between 1 and {}",
+            MAX_TIME_LIMIT_MS
+        )));
+    }
+    match payload.execution_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(_)) => (),
+        Some(Err(_)) => {
+            return Err(Error::Validation(
+                "Field 'execution_id' must be a UUID".to_string(),
+            ));
+        }
+        None => payload.execution_id = Some(Uuid::new_v4().to_string()),
+    }
+    check_execution_id(&history, &principal, payload.execution_id.as_deref()).await?;
 
-    info!("Final language: {:?}", language);
-    info!("Final code: {:?}", code);
-    info!("Final input file path: {:?}", input_file_path);
-    info!("Final output extension: {:?}", output_extension);
+    // Recorded under the solution, the program being tested.
+    let mut record = history_record(
+        ExecutionKind::Stress,
+        &principal,
+        payload.execution_id.clone().unwrap_or_default(),
+        &payload.solution.language,
+        &payload.solution.code,
+        &[],
+    );
+    record.time_limit_ms = payload.time_limit_ms;
+
+    let start = Instant::now();
+    let result = judge::stress::<K8sExecutor>(&payload).await;
+    record.duration_ms = start.elapsed().as_millis() as u64;
+    record.outcome = match &result {
+        Ok(stress_result) if stress_result.status == ExecutionStatus::Failed => "failed",
+        _ => outcome(&result),
+    }
+    .to_string();
+    if let Ok(stress_result) = &result {
+        record.cases = Some(stress_result.tests_run as u64);
+    }
+    let duration_ms = record.duration_ms;
+    finish_execution(&history, record).await;
 
-    match (language.is_none(), code.is_none()) {
-        (true, true) => {
-            return HttpResponse::BadRequest()
-                .body("Missing required fields: 'language' and 'code'");
+    let response = match result {
+        Ok(stress_result) => HttpResponse::Ok().json(stress_result),
+        Err(e) => {
+            error!(outcome = e.code().as_str(); "Error stress testing code: {}", e);
+            e.response_for(payload.execution_id.as_deref())
         }
-        (true, false) => {
-            return HttpResponse::BadRequest().body("Missing required field: 'language'");
+    };
+    Ok(with_execution_time(response, duration_ms))
+}
+
+/// Admin routes require a principal with the admin role, like the one of `ADMIN_TOKEN`.
+fn require_admin(principal: &Principal) -> Result<()> {
+    if principal.is_admin() {
+        Ok(())
+    } else {
+        Err(Error::Forbidden("Admin role required".to_string()))
+    }
+}
+
+fn validate_problem(problem: &Problem) -> Result<()> {
+    if problem.title.trim().is_empty() {
+        return Err(Error::Validation(
+            "Field 'title' must not be empty".to_string(),
+        ));
+    }
+    if problem.test_cases.is_empty() {
+        return Err(Error::Validation(
+            "At least one test case is required".to_string(),
+        ));
+    }
+    if problem.test_cases.len() > MAX_BATCH_CASES {
+        return Err(Error::Validation(format!(
+            "At most {} test cases are allowed",
+            MAX_BATCH_CASES
+        )));
+    }
+    if problem
+        .time_limit_ms
+        .is_some_and(|limit| limit == 0 || limit > MAX_TIME_LIMIT_MS)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'time_limit_ms' must be between 1 and {}",
+            MAX_TIME_LIMIT_MS
+        )));
+    }
+    if problem
+        .memory_limit_mb
+        .is_some_and(|limit| limit == 0 || limit > MAX_MEMORY_LIMIT_MB)
+    {
+        return Err(Error::Validation(format!(
+            "Field 'memory_limit_mb' must be between 1 and {}",
+            MAX_MEMORY_LIMIT_MB
+        )));
+    }
+
+    for (index, test_case) in problem.test_cases.iter().enumerate() {
+        // Files of the shared volume do not live as long as the problem, they must be embedded.
+        if test_case.input.input_file_id.is_some() {
+            return Err(Error::Validation(format!(
+                "Test case {} must embed its input file in 'input_file'",
+                index
+            )));
         }
-        (false, true) => {
-            return HttpResponse::BadRequest().body("Missing required field: 'code'");
+        if let Some(ref input_file) = test_case.input.input_file {
+            if BASE64_STANDARD.decode(input_file.content.trim()).is_err() {
+                return Err(Error::Validation(format!(
+                    "Invalid base64 in 'input_file.content' of test case {}",
+                    index
+                )));
+            }
         }
-        _ => (),
     }
+    Ok(())
+}
 
-    let payload = ExecutionPayload {
-        language: language.unwrap(),
-        code: code.unwrap(),
-        input_file_path,
-        output_extension: output_extension.unwrap(),
+#[utoipa::path(
+    get,
+    path = "/problems",
+    tag = "problems",
+    responses((status = 200, description = "Problems of the catalog", body = [ProblemSummary]))
+)]
+async fn list_problems() -> Result<HttpResponse> {
+    let problems = ProblemStore::from_env().list().await?;
+    Ok(HttpResponse::Ok().json(
+        problems
+            .iter()
+            .map(Problem::summary)
+            .collect::<Vec<ProblemSummary>>(),
+    ))
+}
+
+async fn load_problem(problem_id: &str) -> Result<Problem> {
+    ProblemStore::from_env()
+        .load(problem_id)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Problem {} not found", problem_id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/problems/{id}",
+    tag = "problems",
+    params(("id" = String, Path, description = "Problem id")),
+    responses(
+        (status = 200, description = "Problem without its test cases", body = ProblemSummary),
+        (status = 404, description = "Unknown problem", body = ErrorResponse),
+    )
+)]
+async fn get_problem(problem_id: web::Path<String>) -> Result<HttpResponse> {
+    let problem = load_problem(&problem_id).await?;
+    Ok(HttpResponse::Ok().json(problem.summary()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/problems/{id}",
+    tag = "problems",
+    params(("id" = String, Path, description = "Problem id")),
+    request_body = Problem,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Problem replaced", body = ProblemSummary),
+        (status = 201, description = "Problem created", body = ProblemSummary),
+        (status = 400, description = "Invalid problem", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Admin role required", body = ErrorResponse),
+    )
+)]
+async fn upload_problem(
+    principal: Principal,
+    problem_id: web::Path<String>,
+    payload: web::Json<Problem>,
+) -> Result<HttpResponse> {
+    require_admin(&principal)?;
+    if !ProblemStore::is_valid_id(&problem_id) {
+        return Err(Error::Validation(
+            "Problem id must be made of lowercase letters, digits, '-' and '_'".to_string(),
+        ));
+    }
+
+    let mut problem = payload.into_inner();
+    problem.id = problem_id.into_inner();
+    validate_problem(&problem)?;
+
+    if ProblemStore::from_env().save(&problem).await? {
+        Ok(HttpResponse::Ok().json(problem.summary()))
+    } else {
+        Ok(HttpResponse::Created().json(problem.summary()))
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/problems/{id}",
+    tag = "problems",
+    params(("id" = String, Path, description = "Problem id")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 204, description = "Problem deleted"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Admin role required", body = ErrorResponse),
+        (status = 404, description = "Unknown problem", body = ErrorResponse),
+    )
+)]
+async fn delete_problem(
+    principal: Principal,
+    problem_id: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&principal)?;
+
+    if ProblemStore::from_env().delete(&problem_id).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(Error::NotFound(format!("Problem {} not found", problem_id)))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/problems/{id}/submissions",
+    tag = "problems",
+    params(("id" = String, Path, description = "Problem id")),
+    request_body = SubmissionPayload,
+    responses(
+        (status = 200, description = "Submission judged", body = SubmissionResult),
+        (status = 400, description = "Invalid submission", body = ErrorResponse),
+        (status = 404, description = "Unknown problem", body = ErrorResponse),
+        (status = 409, description = "Judging cancelled, or its id already used", body = ErrorResponse),
+        (status = 500, description = "Executor failure", body = ErrorResponse),
+    )
+)]
+async fn submit_solution(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    problem_id: web::Path<String>,
+    payload: web::Json<SubmissionPayload>,
+) -> Result<HttpResponse> {
+    let problem = load_problem(&problem_id).await?;
+
+    let problem_id = problem.id.clone();
+    let mut judge_payload = problem.into_judge(payload.into_inner());
+    check_execution_id(&history, &principal, judge_payload.execution_id.as_deref()).await?;
+    let execution_id = judge_payload
+        .execution_id
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+    let mut record = history_record(
+        ExecutionKind::Submission,
+        &principal,
+        execution_id.clone(),
+        &judge_payload.language,
+        &judge_payload.code,
+        &[format!("problem:{}", problem_id)],
+    );
+    record.time_limit_ms = judge_payload.time_limit_ms;
+    record.memory_limit_mb = Some(judge_payload.memory_limit_mb.unwrap_or(MAX_MEMORY_LIMIT_MB));
+    record.cases = Some(judge_payload.test_cases.len() as u64);
+
+    let start = Instant::now();
+    let result = run_judge(&principal, judge_payload).await;
+    record.duration_ms = start.elapsed().as_millis() as u64;
+    record_judge_result(&mut record, &result);
+    let duration_ms = record.duration_ms;
+    finish_execution(&history, record).await;
+
+    let response = match result {
+        Ok(judge_result) => {
+            HttpResponse::Ok().json(SubmissionResult::new(problem_id, judge_result))
+        }
+        Err(e) => e.response_for(Some(&execution_id)),
     };
+    Ok(with_execution_time(response, duration_ms))
+}
 
-    info!("Received request to execute code: {:?}", payload);
+async fn execute_code(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    form: ExecutionMultipart,
+) -> Result<HttpResponse> {
+    let ExecutionMultipart(mut payload) = form;
+    // The form wrote the input file, if any, for this request only.
+    let owned_input_file = OwnedInputFiles(payload.input_file_path.iter().cloned().collect());
+    if let Err(e) = validate_tags(&payload.tags) {
+        owned_input_file.remove().await;
+        return Err(e);
+    }
+    if payload.input_file_id.is_some() {
+        if !owned_input_file.0.is_empty() {
+            owned_input_file.remove().await;
+            return Err(Error::Validation(
+                "Fields 'input_file' and 'input_file_id' are mutually exclusive".to_string(),
+            ));
+        }
+        payload.input_file_path =
+            resolve_input_file(&principal.id, None, payload.input_file_id.take())
+                .await?
+                .0;
+    }
+
+    run_execution(payload, owned_input_file, &principal, &history).await
+}
+
+/// Runs the payload on the executor, records it in the history and removes the input file
+/// afterwards when it was written for this request only.
+async fn run_execution(
+    mut payload: ExecutionPayload,
+    owned_input_file: OwnedInputFiles,
+    principal: &Principal,
+    history: &web::Data<ExecutionHistory>,
+) -> Result<HttpResponse> {
+    let execution_id = match payload.execution_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(id)) => id,
+        Some(Err(_)) => {
+            owned_input_file.remove().await;
+            return Err(Error::Validation(
+                "Field 'execution_id' must be a UUID".to_string(),
+            ));
+        }
+        None => Uuid::new_v4(),
+    }
+    .to_string();
+    if let Err(e) = check_execution_id(history, principal, payload.execution_id.as_deref()).await {
+        owned_input_file.remove().await;
+        return Err(e);
+    }
+    payload.execution_id = Some(execution_id.clone());
+    info!(
+        execution_id = execution_id.as_str(),
+        language = payload.language.as_str(),
+        code_bytes = payload.code.len(),
+        has_input_file = payload.input_file_path.is_some();
+        "Executing code"
+    );
+    debug!(
+        execution_id = execution_id.as_str();
+        "Code: {}",
+        logging::user_content(&payload.code)
+    );
 
+    let mut record = history_record(
+        ExecutionKind::Single,
+        principal,
+        execution_id.clone(),
+        &payload.language,
+        &payload.code,
+        &payload.tags,
+    );
+    let start = Instant::now();
     let result = K8sExecutor::execute(&payload).await;
+    owned_input_file.remove().await;
+    let outcome = outcome(&result);
+
+    let duration_ms = start.elapsed().as_millis() as u64;
+    record.memory_limit_mb = Some(MAX_MEMORY_LIMIT_MB);
+    record.outcome = outcome.to_string();
+    record.duration_ms = duration_ms;
+    match &result {
+        Ok(execution_result) => {
+            record.exit_code = execution_result.exit_code;
+            record.run_ms = execution_result.time_ms;
+            record.output_bytes = output_bytes(execution_result);
+        }
+        Err(Error::Runtime { exit_code, .. }) => record.exit_code = *exit_code,
+        Err(_) => {}
+    }
+    finish_execution(history, record).await;
+
+    // Compile and runtime errors are the user's, only the others are ours.
+    let level = match &result {
+        Err(e) if e.status_code().is_server_error() => Level::Error,
+        _ => Level::Info,
+    };
+    log!(
+        level,
+        execution_id = execution_id.as_str(),
+        language = payload.language.as_str(),
+        outcome = outcome,
+        duration_ms = start.elapsed().as_millis() as u64;
+        "Execution finished{}",
+        match &result {
+            Ok(_) => String::new(),
+            Err(e) => format!(": {}", e),
+        }
+    );
+
+    // Failed executions count too, with the time the Job took when Kubernetes did not time the
+    // program. The timings of the script are the program's to forge.
+    let time_ms = result
+        .as_ref()
+        .ok()
+        .and_then(|execution_result| execution_result.billed_ms)
+        .unwrap_or(duration_ms);
+    let response = match result {
+        Ok(execution_result) => {
+            let json_response = ExecutionResponse {
+                execution_id,
+                status: execution_result.status,
+                output: execution_result.output,
+                error: execution_result.error,
+                output_file_path: execution_result.output_file_path,
+                output_file: execution_result.output_file,
+                output_files: execution_result.output_files,
+                output_files_truncated: execution_result.output_files_truncated,
+            };
+
+            debug!(
+                execution_id = json_response.execution_id.as_str();
+                "Output: {}",
+                logging::user_content(&json_response.output)
+            );
+            HttpResponse::Ok().json(json_response)
+        }
+        Err(e) => e.response_for(Some(&execution_id)),
+    };
+    Ok(with_execution_time(response, time_ms))
+}
+
+/// Counts a finished execution in the metrics and keeps it in the history. Languages the server
+/// does not run are labelled `unknown`, so that requests cannot create series at will.
+async fn finish_execution(history: &web::Data<ExecutionHistory>, record: ExecutionRecord) {
+    let language = Language::find(&record.language).map_or("unknown", |language| language.name);
+    let labels = [
+        ("kind", record.kind.as_str()),
+        ("language", language),
+        ("outcome", record.outcome.as_str()),
+    ];
+    metrics::EXECUTIONS.inc(&labels);
+    metrics::EXECUTION_DURATION.observe(&labels, record.duration_ms as f64 / 1000.0);
+    record_execution(history, record).await;
+}
+
+/// Outcome of an execution answered with the result or the error.
+fn outcome<T>(result: &Result<T>) -> &'static str {
     match result {
-        Ok(mut execution_result) => {
-            if let Some(ref path) = payload.input_file_path {
-                tokio::fs::remove_file(path).await.unwrap();
-            }
+        Ok(_) => "completed",
+        Err(e) => e.code().as_str(),
+    }
+}
 
-            if let Some(output_file_path) = execution_result.output_file_path.clone() {
-                if !output_file_path.is_empty() {
-                    info!(
-                        "Successfully processing output with file: {:?}",
-                        execution_result
-                    );
+fn batch_outcome(result: &Result<BatchResult>) -> &'static str {
+    match result {
+        Ok(batch_result) => match batch_result.status {
+            _ if batch_result.compile_error.is_some() => ErrorCode::CompileError.as_str(),
+            ExecutionStatus::Completed => "completed",
+            ExecutionStatus::Failed => "failed",
+            ExecutionStatus::Cancelled => "cancelled",
+        },
+        Err(e) => e.code().as_str(),
+    }
+}
 
-                    let file_content = tokio::fs::read(&output_file_path)
-                        .await
-                        .unwrap_or_else(|_| Vec::new());
+fn record_judge_result(record: &mut ExecutionRecord, result: &Result<JudgeResult>) {
+    record.outcome = match result {
+        Ok(judge_result) if judge_result.compile_error.is_some() => {
+            ErrorCode::CompileError.as_str()
+        }
+        _ => outcome(result),
+    }
+    .to_string();
+    if let Ok(judge_result) = result {
+        let results = judge_result.cases.iter().map(|case| &case.result);
+        record.exit_code = results
+            .clone()
+            .filter_map(|result| result.exit_code)
+            .find(|&code| code != 0);
+        record.run_ms = results.map(|result| result.time_ms).sum();
+    }
+}
 
-                    execution_result.output_file_content =
-                        Some(BASE64_STANDARD.encode(&file_content));
-                }
-            }
+/// Attaches the time spent executing to the response, for [`RateLimit`] to count it against the
+/// daily quota whatever the outcome.
+fn with_execution_time(mut response: HttpResponse, time_ms: u64) -> HttpResponse {
+    response.extensions_mut().insert(ExecutionTime(time_ms));
+    response
+}
 
-            let json_response = json!({
-                "output": execution_result.output,
-                "error": execution_result.error,
-                "output_file_path": execution_result.output_file_path,
-                "output_file_content": execution_result.output_file_content,
-            });
+/// Record of an execution starting now, its outcome being filled in once it is done.
+fn history_record(
+    kind: ExecutionKind,
+    principal: &Principal,
+    execution_id: String,
+    language: &str,
+    code: &str,
+    tags: &[String],
+) -> ExecutionRecord {
+    ExecutionRecord {
+        execution_id,
+        kind,
+        principal: principal.id.clone(),
+        language: language.to_string(),
+        language_version: Language::find(language)
+            .and_then(|language| language.versions.first())
+            .map(|version| version.to_string()),
+        code_sha256: format!("{:x}", Sha256::digest(code)),
+        tags: tags.to_vec(),
+        time_limit_ms: None,
+        memory_limit_mb: None,
+        outcome: String::new(),
+        exit_code: None,
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+        duration_ms: 0,
+        run_ms: None,
+        output_bytes: 0,
+        cases: None,
+    }
+}
 
-            if !execution_result.error.is_empty() {
-                HttpResponse::BadRequest().json(json_response)
-            } else {
-                info!("Successfully returning output: {:?}", execution_result);
-                HttpResponse::Ok().json(json_response)
-            }
+fn output_bytes(result: &ExecutionResult) -> u64 {
+    let files = result.output_files.iter().chain(&result.output_file);
+    (result.output.len() + result.error.len()) as u64 + files.map(|file| file.size).sum::<u64>()
+}
+
+/// Fails with a conflict when the caller already used the execution id, which is then in its
+/// history or still queued or running: the history keeps a single execution per principal and
+/// id.
+async fn check_execution_id(
+    history: &web::Data<ExecutionHistory>,
+    principal: &Principal,
+    execution_id: Option<&str>,
+) -> Result<()> {
+    let Some(execution_id) = execution_id else {
+        return Ok(());
+    };
+    let in_use = ExecutionQueue::global()
+        .job(&principal.id, execution_id)
+        .is_some()
+        || {
+            let history = history.clone();
+            let (principal_id, id) = (principal.id.clone(), execution_id.to_string());
+            web::block(move || history.get(Some(&principal_id), &id))
+                .await
+                .map_err(|e| Error::Infrastructure(e.to_string()))??
+                .is_some()
+        };
+    if in_use {
+        return Err(Error::Conflict(format!(
+            "Execution {} already exists",
+            execution_id
+        )));
+    }
+    Ok(())
+}
+
+/// Failing to record an execution does not fail the execution, which already ran.
+async fn record_execution(history: &web::Data<ExecutionHistory>, record: ExecutionRecord) {
+    let execution_id = record.execution_id.clone();
+    let history = history.clone();
+    let recorded = web::block(move || history.record(&record))
+        .await
+        .unwrap_or_else(|e| Err(Error::Infrastructure(e.to_string())));
+    if let Err(e) = recorded {
+        warn!(
+            execution_id = execution_id.as_str();
+            "Failed to record the execution in the history: {}",
+            e
+        );
+    }
+}
+
+/// Input files written for one request only. They are removed by [`Self::remove`] once the
+/// execution is done, or in the background when the handler is dropped before, which is what
+/// happens when the client disconnects.
+struct OwnedInputFiles(Vec<String>);
+
+impl OwnedInputFiles {
+    async fn remove(mut self) {
+        remove_input_files(&std::mem::take(&mut self.0)).await;
+    }
+}
+
+impl Drop for OwnedInputFiles {
+    fn drop(&mut self) {
+        if self.0.is_empty() {
+            return;
         }
-        Err(e) => {
-            if let Some(ref path) = payload.input_file_path {
-                tokio::fs::remove_file(path).await.unwrap();
+
+        let paths = std::mem::take(&mut self.0);
+        tokio::spawn(async move { remove_input_files(&paths).await });
+    }
+}
+
+async fn remove_input_files<'a>(paths: impl IntoIterator<Item = &'a String>) {
+    for path in paths {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            error!("Failed to delete input file {}: {:?}", path, e);
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "execution",
+    params(("id" = String, Path, description = "Execution id")),
+    responses(
+        (status = 200, description = "Execution queued or running", body = JobInfo),
+        (status = 400, description = "Id is not a UUID", body = ErrorResponse),
+        (status = 404, description = "No queued or running execution of the caller with this id", body = ErrorResponse),
+    )
+)]
+async fn get_job(principal: Principal, execution_id: web::Path<String>) -> Result<HttpResponse> {
+    let execution_id = Uuid::parse_str(&execution_id)
+        .map_err(|_| Error::Validation("Job id must be a UUID".to_string()))?
+        .to_string();
+
+    // Execution ids are chosen by the clients, so each principal only sees its own.
+    match ExecutionQueue::global().job(&principal.id, &execution_id) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(Error::NotFound(format!(
+            "No queued or running job with id {}",
+            execution_id
+        ))),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}",
+    tag = "execution",
+    params(("id" = String, Path, description = "Execution id")),
+    responses(
+        (status = 200, description = "Execution cancelled, or its id already used"),
+        (status = 400, description = "Id is not a UUID", body = ErrorResponse),
+        (status = 404, description = "No queued or running execution of the caller with this id", body = ErrorResponse),
+    )
+)]
+async fn cancel_job(principal: Principal, execution_id: web::Path<String>) -> Result<HttpResponse> {
+    let execution_id = Uuid::parse_str(&execution_id)
+        .map_err(|_| Error::Validation("Job id must be a UUID".to_string()))?
+        .to_string();
+
+    // Jobs of the execution may be running while others are still queued.
+    let dequeued = ExecutionQueue::global().cancel(&principal.id, &execution_id);
+    if K8sExecutor::cancel(&principal.id, &execution_id).await? || dequeued {
+        Ok(HttpResponse::Ok().json(json!({
+            "execution_id": execution_id,
+            "status": ExecutionStatus::Cancelled,
+        })))
+    } else {
+        Err(Error::NotFound(format!(
+            "No running job with id {}",
+            execution_id
+        )))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions",
+    tag = "execution",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Past executions, newest first", body = ExecutionPage),
+        (status = 400, description = "Invalid filter", body = ErrorResponse),
+        (status = 403, description = "Executions of another principal requested without the admin role", body = ErrorResponse),
+    )
+)]
+async fn list_executions(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse> {
+    let mut query = query.into_inner();
+    // Principals only see their own executions, unless they are admins.
+    if !principal.is_admin() {
+        match &query.principal {
+            Some(id) if *id != principal.id => {
+                return Err(Error::Forbidden(
+                    "Admin role required to list the executions of other principals".to_string(),
+                ))
             }
-            error!("Error executing code: {:?}", e);
-            HttpResponse::InternalServerError().json(ExecutionResult {
-                output: "".to_string(),
-                error: e.to_string(),
-                output_file_path: None,
-                output_file_content: None,
-            })
+            _ => query.principal = Some(principal.id.clone()),
         }
     }
+
+    let page = web::block(move || history.list(&query))
+        .await
+        .map_err(|e| Error::Infrastructure(e.to_string()))??;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions/{id}",
+    tag = "execution",
+    params(("id" = String, Path, description = "Execution id")),
+    responses(
+        (status = 200, description = "Past execution", body = ExecutionRecord),
+        (status = 404, description = "No execution with this id in the history", body = ErrorResponse),
+    )
+)]
+async fn get_execution(
+    principal: Principal,
+    history: web::Data<ExecutionHistory>,
+    execution_id: web::Path<String>,
+) -> Result<HttpResponse> {
+    let execution_id = execution_id.into_inner();
+    let id = execution_id.clone();
+    // The executions of other principals are hidden rather than forbidden, admins seeing the
+    // latest one with this id.
+    let owner = (!principal.is_admin()).then(|| principal.id.clone());
+    let record = web::block(move || history.get(owner.as_deref(), &id))
+        .await
+        .map_err(|e| Error::Infrastructure(e.to_string()))??;
+
+    match record {
+        Some(record) => Ok(HttpResponse::Ok().json(record)),
+        None => Err(Error::NotFound(format!(
+            "No execution with id {} in the history",
+            execution_id
+        ))),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/files",
+    tag = "files",
+    request_body(content = FileUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "File stored", body = StoredFile),
+        (status = 200, description = "Same content already stored, its expiry was pushed back", body = StoredFile),
+        (status = 400, description = "Missing or empty file", body = ErrorResponse),
+        (status = 413, description = "File or request too large", body = ErrorResponse),
+        (status = 415, description = "File type not accepted", body = ErrorResponse),
+        (status = 507, description = "Uploaded files quota exceeded", body = ErrorResponse),
+    )
+)]
+async fn upload_file(principal: Principal, upload: FileUpload) -> Result<HttpResponse> {
+    let FileUpload(received) = upload;
+    let name = received.filename.unwrap_or_default();
+    let (file, existed) = FileStore::from_env()
+        .store(
+            &principal.id,
+            &received.path,
+            &name,
+            received.size,
+            &received.sha256,
+        )
+        .await?;
+    info!("Stored input file {} ({} bytes)", file.file_id, file.size);
+    Ok(if existed {
+        HttpResponse::Ok().json(file)
+    } else {
+        HttpResponse::Created().json(file)
+    })
+}
+
+/// The files of other principals are hidden rather than forbidden.
+async fn load_file(principal: &Principal, file_id: &str) -> Result<StoredFile> {
+    FileStore::from_env()
+        .get(&principal.id, file_id)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("File {} not found", file_id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/files/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses(
+        (status = 200, description = "Metadata of the file", body = StoredFile),
+        (status = 404, description = "Unknown or expired file", body = ErrorResponse),
+    )
+)]
+async fn get_file(principal: Principal, file_id: web::Path<String>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(load_file(&principal, &file_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/files/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses(
+        (status = 204, description = "File deleted"),
+        (status = 404, description = "Unknown or expired file", body = ErrorResponse),
+    )
+)]
+async fn delete_file(principal: Principal, file_id: web::Path<String>) -> Result<HttpResponse> {
+    if FileStore::from_env()
+        .delete(&principal.id, &file_id)
+        .await?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(Error::NotFound(format!("File {} not found", file_id)))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/artifacts/{id}",
+    tag = "execution",
+    params(("id" = String, Path, description = "Artifact id, from the `artifact_id` of an output file")),
+    responses(
+        (status = 200, description = "Content of the file, ranges are supported", content_type = "application/octet-stream"),
+        (status = 206, description = "Requested range of the file"),
+        (status = 404, description = "Unknown or expired artifact, or one of another principal", body = ErrorResponse),
+    )
+)]
+async fn download_artifact(
+    req: HttpRequest,
+    principal: Principal,
+    artifact_id: web::Path<String>,
+) -> Result<HttpResponse> {
+    let (metadata, path) = ArtifactStore::from_env()
+        .open(&principal.id, &artifact_id)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Artifact {} not found", artifact_id)))?;
+
+    let content_type = metadata
+        .mime_type
+        .parse()
+        .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM);
+    let mut response = NamedFile::open_async(path)
+        .await?
+        .set_content_type(content_type)
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(metadata.name)],
+        })
+        .into_response(&req);
+    response.headers_mut().insert(
+        http::header::EXPIRES,
+        http::header::HttpDate::from(UNIX_EPOCH + Duration::from_secs(metadata.expires_at))
+            .try_into_value()
+            .map_err(|e| Error::Infrastructure(e.to_string()))?,
+    );
+    Ok(response)
+}
+
+/// Deletes the expired artifacts and uploaded files every `PURGE_INTERVAL`.
+async fn purge_expired_files() {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match ArtifactStore::from_env().purge_expired().await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} expired artifacts", purged),
+            Err(e) => error!("Failed to purge expired artifacts: {:?}", e),
+        }
+        match FileStore::from_env().purge_expired().await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} expired input files", purged),
+            Err(e) => error!("Failed to purge expired input files: {:?}", e),
+        }
+    }
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/execute")
+            .wrap(RateLimit::from_env())
+            .route(
+                web::post()
+                    .guard(guard::fn_guard(is_json_request))
+                    .to(execute_json),
+            )
+            .route(web::post().to(execute_code)),
+    )
+    // Every route creating Jobs is rate limited.
+    .service(
+        web::resource("/execute/batch")
+            .wrap(RateLimit::from_env())
+            .route(web::post().to(execute_batch)),
+    )
+    .service(
+        web::resource("/judge")
+            .wrap(RateLimit::from_env())
+            .route(web::post().to(judge_code)),
+    )
+    .service(
+        web::resource("/judge/interactive")
+            .wrap(RateLimit::from_env())
+            .route(web::post().to(judge_interactive)),
+    )
+    .service(
+        web::resource("/stress")
+            .wrap(RateLimit::from_env())
+            .route(web::post().to(stress_test)),
+    )
+    .route("/problems", web::get().to(list_problems))
+    .service(
+        web::resource("/problems/{id}")
+            .route(web::get().to(get_problem))
+            .route(web::put().to(upload_problem))
+            .route(web::delete().to(delete_problem)),
+    )
+    .service(
+        web::resource("/problems/{id}/submissions")
+            .wrap(RateLimit::from_env())
+            .route(web::post().to(submit_solution)),
+    )
+    .service(
+        web::resource("/jobs/{id}")
+            .route(web::get().to(get_job))
+            .route(web::delete().to(cancel_job)),
+    )
+    .route("/executions", web::get().to(list_executions))
+    .route("/executions/{id}", web::get().to(get_execution))
+    .route("/artifacts/{id}", web::get().to(download_artifact))
+    .route("/files", web::post().to(upload_file))
+    .service(
+        web::resource("/files/{id}")
+            .route(web::get().to(get_file))
+            .route(web::delete().to(delete_file)),
+    )
+    .service(SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", ApiDoc::openapi()))
+    .route("/health", web::get().to(health_check))
+    .route("/ready", web::get().to(ready_check))
+    .route("/metrics", web::get().to(export_metrics))
+    .route("/version", web::get().to(check_version))
+    .route("/languages", web::get().to(list_languages));
 }
 
 pub async fn run_server() -> std::io::Result<()> {
     let port = get_server_port();
     let server_address = (Ipv4Addr::UNSPECIFIED, port);
-    let _swagger_url = format!(
+    let swagger_url = format!(
         "http://{}:{}/swagger-ui/",
         server_address.0, server_address.1
     );
+    info!("Swagger UI available at {}", swagger_url);
+    tokio::spawn(purge_expired_files());
 
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allowed_origin_fn(|origin, _req_head| {
-                if let Some(origin_str) = origin.to_str().ok() {
-                    origin_str.ends_with(":5173") || origin_str.contains("code-valley.xyz")
-                } else {
-                    false
-                }
-            })
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-            .allowed_headers(vec![
-                http::header::AUTHORIZATION,
-                http::header::ACCEPT,
-                http::header::CONTENT_TYPE,
-            ])
-            .supports_credentials()
-            .max_age(3600);
-
-        App::new()
-            .wrap(cors)
-            .route("/execute", web::post().to(execute_code))
-            .route("/health", web::get().to(health_check))
-            .route("/version", web::get().to(check_version))
+    let cors = CorsConfig::from_env()?;
+    for (host, _) in &cors.tenants {
+        info!("CORS policy overridden for {}", host);
+    }
+    let auth = Arc::new(AuthConfig::from_env()?);
+    // Shared by the workers, so that the limits hold for the whole server.
+    let quotas = web::Data::new(Quotas::new(QuotaConfig::from_env()));
+    let history = web::Data::new(ExecutionHistory::from_env()?);
+    if !auth.required {
+        warn!("AUTH_REQUIRED is false, requests without credentials are served anonymously");
+    } else if !auth.has_credentials() {
+        warn!(
+            "No ADMIN_TOKEN, API_KEYS_FILE or JWT key is set, every authenticated route will \
+             answer 401"
+        );
+    }
+
+    HttpServer::new(move || {
+        // Malformed bodies get the same error body as the other validation errors.
+        let json_config = web::JsonConfig::default()
+            .limit(UploadLimits::from_env().max_request_bytes)
+            .error_handler(|e, _req| match e {
+                JsonPayloadError::OverflowKnownLength { .. }
+                | JsonPayloadError::Overflow { .. } => Error::PayloadTooLarge(e.to_string()).into(),
+                e => Error::Validation(e.to_string()).into(),
+            });
+        let query_config = web::QueryConfig::default()
+            .error_handler(|e, _req| Error::Validation(e.to_string()).into());
+
+        // Each tenant gets the routes behind its own CORS policy, the default one serving the
+        // other hosts. Authentication runs inside CORS, so that preflight requests and the
+        // rejections get the CORS headers, and everything runs inside the span and with the id
+        // of the request.
+        let mut app = App::new()
+            .app_data(json_config)
+            .app_data(query_config)
+            .app_data(quotas.clone())
+            .app_data(history.clone())
+            .wrap(RequestId)
+            .wrap(Tracing);
+        for (host, policy) in &cors.tenants {
+            app = app.service(
+                web::scope("")
+                    .guard(guard::Host(host.clone()))
+                    .wrap(Authentication::new(auth.clone()))
+                    .wrap(policy.middleware())
+                    .configure(routes),
+            );
+        }
+        app.service(
+            web::scope("")
+                .wrap(Authentication::new(auth.clone()))
+                .wrap(cors.default.middleware())
+                .configure(routes),
+        )
     })
     .bind(server_address)?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TestCase;
+
+    #[actix_web::test]
+    async fn test_json_input_files() {
+        // Paths are only set by the server, whether for a single execution or for a batch.
+        let error = serde_json::from_str::<ExecutionPayload>(
+            r#"{"language": "python", "code": "", "input_file_path": "/mnt/shared/secret.txt"}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("input_file_id"));
+        assert!(serde_json::from_str::<BatchPayload>(
+            r#"{"language": "python", "code": "", "cases": [{"input_file_path": "/etc/passwd"}]}"#,
+        )
+        .is_err());
+        let payload = serde_json::from_str::<ExecutionPayload>(
+            r#"{"language": "python", "code": "", "input_file_path": null}"#,
+        )
+        .unwrap();
+        assert!(payload.input_file_path.is_none());
+
+        let inline = |content: &str| {
+            Some(InputFile {
+                filename: Some("data.txt".to_string()),
+                content: content.to_string(),
+            })
+        };
+        assert!(matches!(
+            resolve_input_file("alice", inline("aGk="), Some("ab".repeat(32))).await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            resolve_input_file("alice", inline("not base64!"), None).await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            resolve_input_file("alice", None, Some("../../etc/passwd".to_string())).await,
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(
+            resolve_input_file("alice", None, None).await.unwrap(),
+            (None, false)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_batch_case_count_is_bounded() {
+        let mut payload = serde_json::from_str::<BatchPayload>(
+            r#"{"language": "python", "code": "print(input())", "cases": []}"#,
+        )
+        .unwrap();
+        payload.cases = vec![TestCase::default(); MAX_BATCH_CASES + 1];
+        assert!(matches!(
+            prepare_batch("alice", &mut payload).await,
+            Err(Error::Validation(_))
+        ));
+
+        payload.cases.truncate(MAX_BATCH_CASES);
+        assert!(prepare_batch("alice", &mut payload)
+            .await
+            .unwrap()
+            .0
+            .is_empty());
+    }
+    #[actix_web::test]
+    async fn test_execution_ids_are_not_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let history =
+            web::Data::new(ExecutionHistory::open(dir.path().join("history.db")).unwrap());
+        let principal = Principal::anonymous();
+        let execution_id = Uuid::new_v4().to_string();
+
+        assert!(check_execution_id(&history, &principal, None).await.is_ok());
+        assert!(
+            check_execution_id(&history, &principal, Some(&execution_id))
+                .await
+                .is_ok()
+        );
+        history
+            .record(&ExecutionRecord {
+                outcome: "completed".to_string(),
+                ..history_record(
+                    ExecutionKind::Single,
+                    &principal,
+                    execution_id.clone(),
+                    "python",
+                    "print(1)",
+                    &[],
+                )
+            })
+            .unwrap();
+        let error = check_execution_id(&history, &principal, Some(&execution_id))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Conflict(_)));
+
+        // The ids of other principals are theirs.
+        let other = Principal {
+            id: "key:ci".to_string(),
+            ..Principal::anonymous()
+        };
+        assert!(check_execution_id(&history, &other, Some(&execution_id))
+            .await
+            .is_ok());
+    }
+}
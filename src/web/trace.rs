@@ -0,0 +1,80 @@
+use actix_web::dev::{
+    forward_ready,
+    Service,
+    ServiceRequest,
+    ServiceResponse,
+    Transform,
+};
+use futures_util::future::{
+    ready,
+    LocalBoxFuture,
+    Ready,
+};
+use std::rc::Rc;
+use tracing::Instrument;
+
+use crate::telemetry;
+
+/// Runs each request inside a span, continuing the trace of the caller when the request has a
+/// `traceparent` header.
+pub struct Tracing;
+
+impl<S, B> Transform<S, ServiceRequest> for Tracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = TracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TracingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct TracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for TracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let span = tracing::info_span!(
+            "request",
+            otel.name = format!("{} {}", req.method(), route),
+            http.request.method = %req.method(),
+            http.route = route,
+            http.response.status_code = tracing::field::Empty,
+            request_id = tracing::field::Empty,
+        );
+        telemetry::set_remote_parent(&span, req.headers());
+
+        let service = self.service.clone();
+        Box::pin(
+            async move {
+                let response = service.call(req).await?;
+                tracing::Span::current()
+                    .record("http.response.status_code", response.status().as_u16());
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}